@@ -1,154 +1,613 @@
+use crate::input_map::{InputMap, KeyboardLayout};
+use crate::party::{Party, PlayerInput};
+use crate::player::{MainCamera, Player, PlayerId, MAX_PLAYERS};
+use crate::replay::Replay;
+use crate::simulation::SimulationStage;
+use crate::touch_controls::TouchControls;
+use crate::virtual_resolution::VirtualScreen;
 use crate::GameState;
+use bevy::input::gamepad::{GamepadAxisType, GamepadEvent, GamepadEventType};
 use bevy::prelude::*;
+use bevy::utils::HashSet;
+use serde::{Deserialize, Serialize};
 
 pub struct ActionsPlugin;
 
-// This plugin listens for keyboard input and converts the input into Actions
+// This plugin listens for keyboard and gamepad input and converts the input into Actions
 // Actions can then be used as a resource in other systems to act on the player input.
+// Which keys and buttons trigger which action is configured in the InputMap.
+// Every player in the Party has their own PlayerActions. A player that did not pick a device of their own
+// is controlled by the last used device that no other player took.
+// Touch screens use the on-screen controls from the TouchControlsPlugin.
+// While a replay is playing, the ReplayPlugin sets the Actions instead.
+// Presses and releases of every control are also sent as ActionEvents and kept in the InputBuffer for a moment,
+// so short taps and presses during frames without a simulation tick are not lost.
 impl Plugin for ActionsPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.init_resource::<Actions>().add_system_set(
-            SystemSet::on_update(GameState::Playing)
-                .label("gather_input")
-                .with_system(set_movement_actions.system().label("gather_input"))
-                .with_system(set_shoot_actions.system().label("gather_input")),
-        );
+        app.init_resource::<Actions>()
+            .init_resource::<InputBuffer>()
+            .add_event::<ActionEvent>()
+            .init_resource::<ConnectedGamepads>()
+            .insert_resource(InputMap::load())
+            .add_system(track_gamepads.system().label("track_gamepads"))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .label("gather_input")
+                    .after("track_gamepads")
+                    .after("read_touches")
+                    .with_system(set_active_device.system().label("active_device"))
+                    .with_system(
+                        set_movement_actions
+                            .system()
+                            .label("gather_input")
+                            .label("movement_input")
+                            .after("active_device"),
+                    )
+                    .with_system(
+                        set_button_actions
+                            .system()
+                            .label("gather_input")
+                            .label("button_input")
+                            .after("active_device"),
+                    )
+                    .with_system(
+                        buffer_presses
+                            .system()
+                            .label("gather_input")
+                            .after("button_input"),
+                    )
+                    .with_system(
+                        set_aim_actions
+                            .system()
+                            .label("gather_input")
+                            .label("aim_input")
+                            .after("active_device"),
+                    ),
+            )
+            .add_system_to_stage(
+                SimulationStage::Tick,
+                begin_actions_tick
+                    .system()
+                    .label("gather_input")
+                    .label("tick_actions")
+                    .after("begin_tick"),
+            );
     }
 }
 
 #[derive(Default)]
 pub struct Actions {
-    pub player_movement: Option<Vec2>,
-    pub player_shoot: bool,
-    pub player_switch_weapon: bool,
-}
-
-fn set_movement_actions(mut actions: ResMut<Actions>, keyboard_input: Res<Input<KeyCode>>) {
-    if GameControl::Up.just_released(&keyboard_input)
-        || GameControl::Up.pressed(&keyboard_input)
-        || GameControl::Left.just_released(&keyboard_input)
-        || GameControl::Left.pressed(&keyboard_input)
-        || GameControl::Down.just_released(&keyboard_input)
-        || GameControl::Down.pressed(&keyboard_input)
-        || GameControl::Right.just_released(&keyboard_input)
-        || GameControl::Right.pressed(&keyboard_input)
+    players: [PlayerActions; MAX_PLAYERS],
+    /// Any player asked to pause
+    pub pause: bool,
+}
+
+impl Actions {
+    pub fn player(&self, id: PlayerId) -> &PlayerActions {
+        &self.players[id.index()]
+    }
+
+    pub fn player_mut(&mut self, id: PlayerId) -> &mut PlayerActions {
+        &mut self.players[id.index()]
+    }
+}
+
+/// What one player wants to do
+#[derive(Default)]
+pub struct PlayerActions {
+    /// Direction the player wants to move in, with a length between 0 and 1
+    pub movement: Option<Vec2>,
+    pub shoot: bool,
+    /// Set for every tick while a press is in the [InputBuffer], see [InputBuffer::consume]
+    pub switch_weapon: bool,
+    /// Set for every tick while a press is in the [InputBuffer], see [InputBuffer::consume]
+    pub dash: bool,
+    /// Unit vector to fire in, `None` when aiming is off and shots go straight up
+    pub aim: Option<Vec2>,
+    /// The device that produced the latest input, e.g. to show matching button prompts
+    pub device: InputDevice,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ActionEventKind {
+    Pressed,
+    Released,
+}
+
+/// Sent whenever a control is pressed or released on the keyboard or the active device of a player
+#[derive(Clone, Copy, Debug)]
+pub struct ActionEvent {
+    pub player: PlayerId,
+    pub control: GameControl,
+    pub kind: ActionEventKind,
+    /// `Time::seconds_since_startup` of the frame the input arrived in
+    pub time: f64,
+}
+
+/// How long presses of dash and weapon switch wait for gameplay to use them
+pub const ACTION_BUFFER_SECONDS: f64 = 0.15;
+/// Presses older than this are dropped from the [InputBuffer]
+const MAX_BUFFER_SECONDS: f64 = 0.5;
+
+/// The presses of the last moments, e.g. to dash as soon as the cooldown is over
+/// when the button was pressed a little early
+#[derive(Default)]
+pub struct InputBuffer {
+    presses: Vec<(PlayerId, GameControl, f64)>,
+    now: f64,
+}
+
+impl InputBuffer {
+    /// Did `player` press `control` in the last `seconds` without it being consumed yet?
+    pub fn pressed_within(&self, player: PlayerId, control: GameControl, seconds: f64) -> bool {
+        self.presses.iter().any(|(by, pressed, time)| {
+            *by == player && *pressed == control && self.now - time <= seconds
+        })
+    }
+
+    /// Like [InputBuffer::pressed_within], but also forgets those presses, so they only trigger once
+    pub fn consume(&mut self, player: PlayerId, control: GameControl, seconds: f64) -> bool {
+        let pressed = self.pressed_within(player, control, seconds);
+        let now = self.now;
+        self.presses.retain(|(by, pressed, time)| {
+            *by != player || *pressed != control || now - time > seconds
+        });
+        pressed
+    }
+
+    fn update(&mut self, now: f64) {
+        self.now = now;
+        self.presses
+            .retain(|(_, _, time)| now - time <= MAX_BUFFER_SECONDS);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputDevice {
+    Keyboard,
+    Gamepad(Gamepad),
+    Touch,
+}
+
+impl Default for InputDevice {
+    fn default() -> Self {
+        InputDevice::Keyboard
+    }
+}
+
+/// All currently plugged in gamepads
+#[derive(Default)]
+pub struct ConnectedGamepads(pub Vec<Gamepad>);
+
+fn track_gamepads(
+    mut actions: ResMut<Actions>,
+    mut gamepads: ResMut<ConnectedGamepads>,
+    mut gamepad_events: EventReader<GamepadEvent>,
+) {
+    for GamepadEvent(gamepad, event_type) in gamepad_events.iter() {
+        match event_type {
+            GamepadEventType::Connected => {
+                info!("Gamepad {:?} connected", gamepad);
+                gamepads.0.push(*gamepad);
+            }
+            GamepadEventType::Disconnected => {
+                info!("Gamepad {:?} disconnected", gamepad);
+                gamepads.0.retain(|connected| connected != gamepad);
+                for actions in actions.players.iter_mut() {
+                    if actions.device == InputDevice::Gamepad(*gamepad) {
+                        actions.device = InputDevice::Keyboard;
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn set_active_device(
+    mut actions: ResMut<Actions>,
+    party: Res<Party>,
+    input_map: Res<InputMap>,
+    gamepads: Res<ConnectedGamepads>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    touches: Res<Touches>,
+    mut cursor_moved: EventReader<CursorMoved>,
+) {
+    let cursor_moved = cursor_moved.iter().next().is_some();
+    for (player, input) in party.local_players() {
+        let layout = party.keyboard_layout(player);
+        let actions = actions.player_mut(player);
+        match input {
+            PlayerInput::Keyboard(_) => actions.device = InputDevice::Keyboard,
+            PlayerInput::Gamepad(gamepad) => actions.device = InputDevice::Gamepad(gamepad),
+            PlayerInput::Network => (),
+            PlayerInput::LastUsed => {
+                // browsers also send mouse events for touches, so the cursor does not take over from touch input
+                let cursor_moved = cursor_moved && actions.device != InputDevice::Touch;
+                if keyboard_input
+                    .get_just_pressed()
+                    .any(|key| input_map.in_layout(*key, layout))
+                    || cursor_moved
+                {
+                    actions.device = InputDevice::Keyboard;
+                }
+                if touches.iter_just_pressed().next().is_some() {
+                    actions.device = InputDevice::Touch;
+                }
+                for gamepad in gamepads.0.iter() {
+                    if party.is_taken(PlayerInput::Gamepad(*gamepad)) {
+                        continue;
+                    }
+                    if gamepad_buttons
+                        .get_just_pressed()
+                        .any(|button| button.0 == *gamepad)
+                        || gamepad_movement(*gamepad, &input_map, &gamepad_buttons, &gamepad_axes)
+                            .is_some()
+                    {
+                        actions.device = InputDevice::Gamepad(*gamepad);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn set_movement_actions(
+    mut actions: ResMut<Actions>,
+    party: Res<Party>,
+    replay: Res<Replay>,
+    input_map: Res<InputMap>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    touch_controls: Res<TouchControls>,
+) {
+    if replay.is_playing() {
+        return;
+    }
+    for (player, _) in party.local_players() {
+        let layout = party.keyboard_layout(player);
+        let actions = actions.player_mut(player);
+        match actions.device {
+            InputDevice::Keyboard => {
+                set_keyboard_movement(actions, &input_map, layout, &keyboard_input)
+            }
+            InputDevice::Touch => actions.movement = touch_controls.movement,
+            InputDevice::Gamepad(gamepad) => {
+                actions.movement =
+                    gamepad_movement(gamepad, &input_map, &gamepad_buttons, &gamepad_axes).map(
+                        |movement| {
+                            // rescale, so the magnitude goes from 0 at the dead zone to 1 at full tilt
+                            let dead_zone = input_map.gamepad_dead_zone;
+                            let magnitude = (movement.length() - dead_zone) / (1. - dead_zone);
+                            movement.normalize() * magnitude.min(1.)
+                        },
+                    );
+            }
+        }
+    }
+}
+
+/// Combined left stick and d-pad input, or `None` inside the dead zone
+fn gamepad_movement(
+    gamepad: Gamepad,
+    input_map: &InputMap,
+    gamepad_buttons: &Input<GamepadButton>,
+    gamepad_axes: &Axis<GamepadAxis>,
+) -> Option<Vec2> {
+    let axis = |axis_type| {
+        gamepad_axes
+            .get(GamepadAxis(gamepad, axis_type))
+            .unwrap_or(0.)
+    };
+    let button = |control| {
+        if input_map.gamepad_pressed(control, gamepad, gamepad_buttons) {
+            1.
+        } else {
+            0.
+        }
+    };
+    let movement = Vec2::new(
+        axis(GamepadAxisType::LeftStickX)
+            + axis(GamepadAxisType::DPadX)
+            + button(GameControl::Right)
+            - button(GameControl::Left),
+        axis(GamepadAxisType::LeftStickY) + axis(GamepadAxisType::DPadY) + button(GameControl::Up)
+            - button(GameControl::Down),
+    );
+    if movement.length() < input_map.gamepad_dead_zone {
+        None
+    } else {
+        Some(movement)
+    }
+}
+
+fn set_keyboard_movement(
+    actions: &mut PlayerActions,
+    input_map: &InputMap,
+    layout: KeyboardLayout,
+    keyboard_input: &Input<KeyCode>,
+) {
+    if GameControl::Up.just_released(input_map, layout, keyboard_input)
+        || GameControl::Up.pressed(input_map, layout, keyboard_input)
+        || GameControl::Left.just_released(input_map, layout, keyboard_input)
+        || GameControl::Left.pressed(input_map, layout, keyboard_input)
+        || GameControl::Down.just_released(input_map, layout, keyboard_input)
+        || GameControl::Down.pressed(input_map, layout, keyboard_input)
+        || GameControl::Right.just_released(input_map, layout, keyboard_input)
+        || GameControl::Right.pressed(input_map, layout, keyboard_input)
     {
         let mut player_movement = Vec2::ZERO;
 
-        if GameControl::Up.just_released(&keyboard_input)
-            || GameControl::Down.just_released(&keyboard_input)
+        if GameControl::Up.just_released(input_map, layout, keyboard_input)
+            || GameControl::Down.just_released(input_map, layout, keyboard_input)
         {
-            if GameControl::Up.pressed(&keyboard_input) {
+            if GameControl::Up.pressed(input_map, layout, keyboard_input) {
                 player_movement.y = 1.;
-            } else if GameControl::Down.pressed(&keyboard_input) {
+            } else if GameControl::Down.pressed(input_map, layout, keyboard_input) {
                 player_movement.y = -1.;
             } else {
                 player_movement.y = 0.;
             }
-        } else if GameControl::Up.just_pressed(&keyboard_input) {
+        } else if GameControl::Up.just_pressed(input_map, layout, keyboard_input) {
             player_movement.y = 1.;
-        } else if GameControl::Down.just_pressed(&keyboard_input) {
+        } else if GameControl::Down.just_pressed(input_map, layout, keyboard_input) {
             player_movement.y = -1.;
         } else {
-            player_movement.y = actions.player_movement.unwrap_or(Vec2::ZERO).y;
+            player_movement.y = actions.movement.unwrap_or(Vec2::ZERO).y;
         }
 
-        if GameControl::Right.just_released(&keyboard_input)
-            || GameControl::Left.just_released(&keyboard_input)
+        if GameControl::Right.just_released(input_map, layout, keyboard_input)
+            || GameControl::Left.just_released(input_map, layout, keyboard_input)
         {
-            if GameControl::Right.pressed(&keyboard_input) {
+            if GameControl::Right.pressed(input_map, layout, keyboard_input) {
                 player_movement.x = 1.;
-            } else if GameControl::Left.pressed(&keyboard_input) {
+            } else if GameControl::Left.pressed(input_map, layout, keyboard_input) {
                 player_movement.x = -1.;
             } else {
                 player_movement.x = 0.;
             }
-        } else if GameControl::Right.just_pressed(&keyboard_input) {
+        } else if GameControl::Right.just_pressed(input_map, layout, keyboard_input) {
             player_movement.x = 1.;
-        } else if GameControl::Left.just_pressed(&keyboard_input) {
+        } else if GameControl::Left.just_pressed(input_map, layout, keyboard_input) {
             player_movement.x = -1.;
         } else {
-            player_movement.x = actions.player_movement.unwrap_or(Vec2::ZERO).x;
+            player_movement.x = actions.movement.unwrap_or(Vec2::ZERO).x;
         }
 
         if player_movement != Vec2::ZERO {
             player_movement = player_movement.normalize();
-            actions.player_movement = Some(player_movement);
+            actions.movement = Some(player_movement);
         }
     } else {
-        actions.player_movement = None;
+        actions.movement = None;
     }
 }
 
-enum GameControl {
+/// With free aim on, a keyboard player aims at the mouse cursor and a gamepad player with the right stick.
+/// The second player on the keyboard has no mouse and keeps aiming up. Letting go of the stick keeps the last direction.
+fn set_aim_actions(
+    mut actions: ResMut<Actions>,
+    party: Res<Party>,
+    replay: Res<Replay>,
+    input_map: Res<InputMap>,
+    windows: Res<Windows>,
+    screen: Res<VirtualScreen>,
+    gamepad_axes: Res<Axis<GamepadAxis>>,
+    camera_query: Query<&Transform, With<MainCamera>>,
+    player_query: Query<(&PlayerId, &Transform), (With<Player>, Without<MainCamera>)>,
+) {
+    if replay.is_playing() {
+        return;
+    }
+    for (player, _) in party.local_players() {
+        let layout = party.keyboard_layout(player);
+        let actions = actions.player_mut(player);
+        if !input_map.free_aim {
+            actions.aim = None;
+            continue;
+        }
+        let position = player_query
+            .iter()
+            .find(|(id, _)| **id == player)
+            .map(|(_, transform)| transform.translation.truncate());
+        let aim = match actions.device {
+            InputDevice::Keyboard if layout == KeyboardLayout::PlayerTwo => None,
+            InputDevice::Keyboard => {
+                let cursor = windows.get_primary().and_then(|window| {
+                    window
+                        .cursor_position()
+                        .map(|position| screen.world_offset(window, position))
+                });
+                match (cursor, camera_query.single(), position) {
+                    (Some(cursor), Ok(camera), Some(position)) => {
+                        let world = camera.compute_matrix() * cursor.extend(0.).extend(1.);
+                        Some(world.truncate().truncate() - position)
+                    }
+                    _ => None,
+                }
+            }
+            InputDevice::Touch => None,
+            InputDevice::Gamepad(gamepad) => {
+                let axis = |axis_type| {
+                    gamepad_axes
+                        .get(GamepadAxis(gamepad, axis_type))
+                        .unwrap_or(0.)
+                };
+                Some(Vec2::new(
+                    axis(GamepadAxisType::RightStickX),
+                    axis(GamepadAxisType::RightStickY),
+                ))
+                .filter(|stick| stick.length() >= input_map.gamepad_dead_zone)
+            }
+        };
+        match aim.filter(|aim| *aim != Vec2::ZERO) {
+            Some(aim) => actions.aim = Some(aim.normalize()),
+            None => actions.aim = actions.aim.or(Some(Vec2::Y)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GameControl {
     Up,
     Down,
     Left,
     Right,
+    Shoot,
+    SwitchWeapon,
+    Dash,
+    Pause,
 }
 
 impl GameControl {
-    fn just_released(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
+    pub const ALL: [GameControl; 8] = [
+        GameControl::Up,
+        GameControl::Down,
+        GameControl::Left,
+        GameControl::Right,
+        GameControl::Shoot,
+        GameControl::SwitchWeapon,
+        GameControl::Dash,
+        GameControl::Pause,
+    ];
+
+    pub fn name(&self) -> &'static str {
         match self {
-            GameControl::Up => {
-                keyboard_input.just_released(KeyCode::W)
-                    || keyboard_input.just_released(KeyCode::Up)
-            }
-            GameControl::Down => {
-                keyboard_input.just_released(KeyCode::S)
-                    || keyboard_input.just_released(KeyCode::Down)
-            }
-            GameControl::Left => {
-                keyboard_input.just_released(KeyCode::A)
-                    || keyboard_input.just_released(KeyCode::Left)
-            }
-            GameControl::Right => {
-                keyboard_input.just_released(KeyCode::D)
-                    || keyboard_input.just_released(KeyCode::Right)
-            }
+            GameControl::Up => "Up",
+            GameControl::Down => "Down",
+            GameControl::Left => "Left",
+            GameControl::Right => "Right",
+            GameControl::Shoot => "Shoot",
+            GameControl::SwitchWeapon => "Switch weapon",
+            GameControl::Dash => "Dash",
+            GameControl::Pause => "Pause",
         }
     }
 
-    fn pressed(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
-        match self {
-            GameControl::Up => {
-                keyboard_input.pressed(KeyCode::W) || keyboard_input.pressed(KeyCode::Up)
+    fn just_released(
+        &self,
+        input_map: &InputMap,
+        layout: KeyboardLayout,
+        keyboard_input: &Input<KeyCode>,
+    ) -> bool {
+        input_map.just_released(*self, layout, keyboard_input)
+    }
+
+    fn pressed(
+        &self,
+        input_map: &InputMap,
+        layout: KeyboardLayout,
+        keyboard_input: &Input<KeyCode>,
+    ) -> bool {
+        input_map.pressed(*self, layout, keyboard_input)
+    }
+
+    fn just_pressed(
+        &self,
+        input_map: &InputMap,
+        layout: KeyboardLayout,
+        keyboard_input: &Input<KeyCode>,
+    ) -> bool {
+        input_map.just_pressed(*self, layout, keyboard_input)
+    }
+}
+
+fn set_button_actions(
+    time: Res<Time>,
+    mut actions: ResMut<Actions>,
+    mut action_events: EventWriter<ActionEvent>,
+    mut held: Local<HashSet<(PlayerId, GameControl)>>,
+    party: Res<Party>,
+    replay: Res<Replay>,
+    input_map: Res<InputMap>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    touch_controls: Res<TouchControls>,
+) {
+    let now = time.seconds_since_startup();
+    if replay.is_playing() {
+        return;
+    }
+    actions.pause = false;
+    for (player, _) in party.local_players() {
+        let layout = party.keyboard_layout(player);
+        let device = actions.player(player).device;
+        // (just pressed, pressed) on the keyboard or the active device
+        let state = |control: GameControl| {
+            let mut just_pressed = control.just_pressed(&input_map, layout, &keyboard_input);
+            let mut pressed = control.pressed(&input_map, layout, &keyboard_input);
+            match device {
+                InputDevice::Gamepad(gamepad) => {
+                    just_pressed |=
+                        input_map.gamepad_just_pressed(control, gamepad, &gamepad_buttons);
+                    pressed |= input_map.gamepad_pressed(control, gamepad, &gamepad_buttons);
+                }
+                InputDevice::Touch => {
+                    just_pressed |= touch_controls.just_pressed(control);
+                    pressed |= touch_controls.pressed(control);
+                }
+                InputDevice::Keyboard => (),
             }
-            GameControl::Down => {
-                keyboard_input.pressed(KeyCode::S) || keyboard_input.pressed(KeyCode::Down)
+            (just_pressed, pressed)
+        };
+
+        let mut send = |control, kind| {
+            action_events.send(ActionEvent {
+                player,
+                control,
+                kind,
+                time: now,
+            })
+        };
+        for control in GameControl::ALL.iter().copied() {
+            let (just_pressed, pressed) = state(control);
+            let was_pressed = held.contains(&(player, control));
+            if was_pressed && (just_pressed || !pressed) {
+                send(control, ActionEventKind::Released);
+            }
+            if just_pressed || (pressed && !was_pressed) {
+                send(control, ActionEventKind::Pressed);
             }
-            GameControl::Left => {
-                keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left)
+            // pressed and released again within one frame
+            if just_pressed && !pressed {
+                send(control, ActionEventKind::Released);
             }
-            GameControl::Right => {
-                keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right)
+            if pressed {
+                held.insert((player, control));
+            } else {
+                held.remove(&(player, control));
             }
         }
+
+        actions.player_mut(player).shoot = state(GameControl::Shoot).1;
+        actions.pause |= state(GameControl::Pause).0;
     }
+}
 
-    fn just_pressed(&self, keyboard_input: &Res<Input<KeyCode>>) -> bool {
-        match self {
-            GameControl::Up => {
-                keyboard_input.just_pressed(KeyCode::W) || keyboard_input.just_pressed(KeyCode::Up)
-            }
-            GameControl::Down => {
-                keyboard_input.just_pressed(KeyCode::S)
-                    || keyboard_input.just_pressed(KeyCode::Down)
-            }
-            GameControl::Left => {
-                keyboard_input.just_pressed(KeyCode::A)
-                    || keyboard_input.just_pressed(KeyCode::Left)
-            }
-            GameControl::Right => {
-                keyboard_input.just_pressed(KeyCode::D)
-                    || keyboard_input.just_pressed(KeyCode::Right)
-            }
+fn buffer_presses(
+    time: Res<Time>,
+    mut buffer: ResMut<InputBuffer>,
+    mut action_events: EventReader<ActionEvent>,
+) {
+    buffer.update(time.seconds_since_startup());
+    for event in action_events.iter() {
+        if event.kind == ActionEventKind::Pressed {
+            buffer
+                .presses
+                .push((event.player, event.control, event.time));
         }
     }
 }
 
-fn set_shoot_actions(mut actions: ResMut<Actions>, keyboard_input: Res<Input<KeyCode>>) {
-    actions.player_shoot = keyboard_input.pressed(KeyCode::Space);
-    actions.player_switch_weapon = keyboard_input.just_pressed(KeyCode::LShift)
-        || keyboard_input.just_pressed(KeyCode::RShift);
+/// Dash and weapon switch are taken from the [InputBuffer] once per tick
+fn begin_actions_tick(mut actions: ResMut<Actions>, party: Res<Party>, buffer: Res<InputBuffer>) {
+    for (player, _) in party.local_players() {
+        let actions = actions.player_mut(player);
+        actions.switch_weapon =
+            buffer.pressed_within(player, GameControl::SwitchWeapon, ACTION_BUFFER_SECONDS);
+        actions.dash = buffer.pressed_within(player, GameControl::Dash, ACTION_BUFFER_SECONDS);
+    }
 }
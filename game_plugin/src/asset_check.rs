@@ -0,0 +1,127 @@
+use crate::dialogue::DialogueScript;
+use crate::level::LevelData;
+use crate::loading::collection_handles;
+use bevy::asset::{AssetServerSettings, FileAssetIo};
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct AssetCheckPlugin;
+
+/// This plugin compares the asset collections of the [LoadingPlugin](crate::loading::LoadingPlugin)
+/// with the files in the assets directory on startup. It panics with a report of every path
+/// that is declared but missing on disk and of every file that is not used by any collection.
+/// Only added to native debug builds, there is no assets directory to read on the web.
+impl Plugin for AssetCheckPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_startup_system(check_assets.system());
+    }
+}
+
+/// Files in the assets directory that are not assets, like licenses and notes on sources
+const IGNORED_EXTENSIONS: [&str; 1] = ["txt"];
+
+/// Paths of all files below `directory`, relative to `root` and separated by `/`
+fn files_on_disk(root: &Path, directory: &Path, files: &mut Vec<String>) {
+    let entries = match fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(error) => {
+            warn!("Failed to read {}: {}", directory.display(), error);
+            return;
+        }
+    };
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        if path.is_dir() {
+            files_on_disk(root, &path, files);
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            let components: Vec<_> = relative
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect();
+            files.push(components.join("/"));
+        }
+    }
+}
+
+/// Files read by the loaders of declared assets, like the map of a level,
+/// and the portraits of the conversations in a level
+fn dependencies(root: &Path, path: &str) -> Vec<String> {
+    if !path.ends_with(".level.ron") {
+        return vec![];
+    }
+    let level = match fs::read(root.join(path))
+        .ok()
+        .and_then(|bytes| ron::de::from_bytes::<LevelData>(&bytes).ok())
+    {
+        Some(level) => level,
+        None => return vec![],
+    };
+    let mut paths: Vec<String> = level
+        .tilemap
+        .map(|tilemap| tilemap.path)
+        .into_iter()
+        .collect();
+    for dialogue in level.dialogues {
+        let portraits = fs::read(root.join(&dialogue.path))
+            .ok()
+            .and_then(|bytes| ron::de::from_bytes::<DialogueScript>(&bytes).ok())
+            .map(|script| {
+                script
+                    .lines
+                    .into_iter()
+                    .filter_map(|line| line.portrait)
+                    .collect()
+            })
+            .unwrap_or_default();
+        paths.push(dialogue.path);
+        paths.extend::<Vec<String>>(portraits);
+    }
+    paths
+}
+
+fn check_assets(asset_server: Res<AssetServer>, settings: Res<AssetServerSettings>) {
+    let root: PathBuf = FileAssetIo::get_root_path().join(&settings.asset_folder);
+
+    let mut declared: Vec<String> = collection_handles(&asset_server)
+        .iter()
+        .filter_map(|handle| asset_server.get_handle_path(handle))
+        .map(|path| path.path().to_string_lossy().replace('\\', "/"))
+        .collect();
+    let loaded_by_loaders: Vec<String> = declared
+        .iter()
+        .flat_map(|path| dependencies(&root, path))
+        .collect();
+    declared.extend(loaded_by_loaders);
+    let declared: HashSet<String> = declared.into_iter().collect();
+
+    let mut on_disk = vec![];
+    files_on_disk(&root, &root, &mut on_disk);
+    let on_disk: HashSet<String> = on_disk
+        .into_iter()
+        .filter(|path| {
+            !IGNORED_EXTENSIONS
+                .iter()
+                .any(|extension| path.ends_with(&format!(".{}", extension)))
+        })
+        .collect();
+
+    let mut unknown: Vec<&String> = declared.difference(&on_disk).collect();
+    let mut unused: Vec<&String> = on_disk.difference(&declared).collect();
+    if unknown.is_empty() && unused.is_empty() {
+        return;
+    }
+    unknown.sort();
+    unused.sort();
+    let mut report = format!("Asset collections do not match {}", root.display());
+    for path in unknown {
+        report.push_str(&format!("\n  declared, but not on disk: {}", path));
+    }
+    for path in unused {
+        report.push_str(&format!("\n  on disk, but not declared: {}", path));
+    }
+    panic!("{}", report);
+}
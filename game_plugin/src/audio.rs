@@ -1,15 +1,20 @@
 use crate::actions::Actions;
 use crate::loading::AudioAssets;
+use crate::player::PlayerId;
+use crate::settings::Settings;
 use crate::GameState;
 use bevy::prelude::*;
-use bevy_kira_audio::{Audio, AudioPlugin};
+use bevy_kira_audio::{Audio, AudioChannel, AudioPlugin};
 
 pub struct InternalAudioPlugin;
 
 // This plugin is responsible to controll the game audio
+// Music and sound effects play in their own channels, so their volume can be set apart
 impl Plugin for InternalAudioPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_plugin(AudioPlugin)
+            .init_resource::<AudioChannels>()
+            .add_system(apply_volume.system())
             .add_system_set(
                 SystemSet::on_enter(GameState::Playing).with_system(start_audio.system()),
             )
@@ -19,16 +24,43 @@ impl Plugin for InternalAudioPlugin {
     }
 }
 
-fn start_audio(audio_assets: Res<AudioAssets>, audio: Res<Audio>) {
-    audio.set_volume(0.3);
-    audio.play_looped(audio_assets.flying.clone());
-    audio.pause();
+pub struct AudioChannels {
+    pub music: AudioChannel,
+    pub sfx: AudioChannel,
 }
 
-fn control_flying_sound(actions: Res<Actions>, audio: Res<Audio>) {
-    if actions.player_movement.is_some() {
-        audio.resume();
+impl Default for AudioChannels {
+    fn default() -> Self {
+        AudioChannels {
+            music: AudioChannel::new("music".to_owned()),
+            sfx: AudioChannel::new("sfx".to_owned()),
+        }
+    }
+}
+
+fn apply_volume(settings: Res<Settings>, channels: Res<AudioChannels>, audio: Res<Audio>) {
+    if !settings.is_changed() {
+        return;
+    }
+    audio.set_volume_in_channel(
+        settings.master_volume * settings.music_volume,
+        &channels.music,
+    );
+    audio.set_volume_in_channel(settings.master_volume * settings.sfx_volume, &channels.sfx);
+}
+
+fn start_audio(audio_assets: Res<AudioAssets>, audio: Res<Audio>, channels: Res<AudioChannels>) {
+    audio.play_looped_in_channel(audio_assets.flying.clone(), &channels.sfx);
+    audio.pause_channel(&channels.sfx);
+}
+
+fn control_flying_sound(actions: Res<Actions>, audio: Res<Audio>, channels: Res<AudioChannels>) {
+    let flying = PlayerId::ALL
+        .iter()
+        .any(|id| actions.player(*id).movement.is_some());
+    if flying {
+        audio.resume_channel(&channels.sfx);
     } else {
-        audio.pause()
+        audio.pause_channel(&channels.sfx)
     }
 }
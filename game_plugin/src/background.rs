@@ -0,0 +1,199 @@
+use crate::level::{BackgroundLayer, LevelData};
+use crate::loading::{LevelAssets, TextureAtlases};
+use crate::player::MainCamera;
+use crate::simulation::{RunEntity, SimulationStage};
+use crate::virtual_resolution::{VIRTUAL_HEIGHT, VIRTUAL_WIDTH};
+use crate::GameState;
+use bevy::prelude::*;
+use rand::Rng;
+
+pub struct BackgroundPlugin;
+
+/// This plugin spawns the parallax background layers of the current level.
+/// Every layer follows the camera and scrolls its content down at its own speed, wrapping around.
+impl Plugin for BackgroundPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_enter(GameState::Playing).with_system(spawn_background.system()),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Playing).with_system(scroll_layers.system()),
+        )
+        // follow where the camera is drawn, but without the screen shake
+        .add_system_to_stage(
+            SimulationStage::Interpolate,
+            follow_camera
+                .system()
+                .after("interpolate")
+                .before("shake_camera"),
+        );
+    }
+}
+
+const TILE_SIZE: f32 = 16.;
+/// The bullet sprite, scaled down far enough it makes for a star
+const STAR_SPRITE: u32 = 188 - 24;
+
+struct ParallaxLayer;
+
+/// Part of a [ParallaxLayer] that moves down with `speed` and wraps around every `span` pixels
+struct ParallaxItem {
+    speed: f32,
+    span: f32,
+}
+
+fn spawn_background(
+    mut commands: Commands,
+    level_assets: Res<LevelAssets>,
+    levels: Res<Assets<LevelData>>,
+    texture_atlases: Res<TextureAtlases>,
+) {
+    let level = match levels.get(&level_assets.level_1) {
+        Some(level) => level,
+        None => return,
+    };
+    let view = Vec2::new(VIRTUAL_WIDTH, VIRTUAL_HEIGHT);
+
+    for (index, layer) in level.background.iter().enumerate() {
+        // everything in the background needs to stay behind the player at z = 1
+        let z = (index + 1) as f32 / (level.background.len() + 1) as f32;
+        commands
+            .spawn()
+            .insert(Transform::from_translation(Vec3::new(0., 0., z)))
+            .insert(GlobalTransform::default())
+            .insert(ParallaxLayer)
+            .insert(RunEntity)
+            .with_children(|parent| {
+                for (translation, sprite, scale, item) in layer_items(layer, view) {
+                    parent
+                        .spawn_bundle(SpriteSheetBundle {
+                            texture_atlas: texture_atlases.main_sprite_sheet.clone(),
+                            transform: Transform {
+                                translation: translation.extend(0.),
+                                scale: Vec3::splat(scale),
+                                ..Default::default()
+                            },
+                            sprite,
+                            ..Default::default()
+                        })
+                        .insert(item);
+                }
+            });
+    }
+}
+
+fn layer_items(
+    layer: &BackgroundLayer,
+    view: Vec2,
+) -> Vec<(Vec2, TextureAtlasSprite, f32, ParallaxItem)> {
+    let mut items = vec![];
+    match layer {
+        BackgroundLayer::Tiles {
+            speed,
+            scale,
+            pattern,
+            tint,
+        } => {
+            let tile = TILE_SIZE * scale;
+            let pattern_rows = pattern.len();
+            let columns = (view.x / tile).ceil() as usize + 1;
+            // the wrapped span has to be a multiple of the pattern, or it would not be seamless
+            let mut rows = ((view.y + tile) / tile).ceil() as usize;
+            rows += (pattern_rows - rows % pattern_rows) % pattern_rows;
+            let span = rows as f32 * tile;
+            for row in 0..rows {
+                let pattern_row = &pattern[row % pattern_rows];
+                for column in 0..columns {
+                    items.push((
+                        Vec2::new(
+                            -view.x / 2. + (column as f32 + 0.5) * tile,
+                            span / 2. - (row as f32 + 0.5) * tile,
+                        ),
+                        sprite(pattern_row[column % pattern_row.len()], *tint),
+                        *scale,
+                        ParallaxItem {
+                            speed: *speed,
+                            span,
+                        },
+                    ));
+                }
+            }
+        }
+        BackgroundLayer::Sprites {
+            speed,
+            scale,
+            height,
+            sprites,
+            tint,
+        } => {
+            let copies = (view.y / height).ceil() as usize + 1;
+            let span = copies as f32 * height;
+            for copy in 0..copies {
+                for background_sprite in sprites {
+                    items.push((
+                        Vec2::new(
+                            background_sprite.x,
+                            -span / 2. + copy as f32 * height + background_sprite.y,
+                        ),
+                        sprite(background_sprite.index, *tint),
+                        *scale,
+                        ParallaxItem {
+                            speed: *speed,
+                            span,
+                        },
+                    ));
+                }
+            }
+        }
+        BackgroundLayer::Starfield { speed, count, tint } => {
+            let mut rng = rand::thread_rng();
+            let span = view.y + TILE_SIZE;
+            for _ in 0..*count {
+                // smaller stars are further away, so they move slower
+                let depth = rng.gen_range(0.2..1.);
+                items.push((
+                    Vec2::new(
+                        rng.gen_range(-view.x / 2.0..view.x / 2.),
+                        rng.gen_range(-span / 2.0..span / 2.),
+                    ),
+                    sprite(STAR_SPRITE, *tint),
+                    0.3 * depth,
+                    ParallaxItem {
+                        speed: speed * depth,
+                        span,
+                    },
+                ));
+            }
+        }
+    }
+    items
+}
+
+fn sprite(index: u32, tint: (f32, f32, f32)) -> TextureAtlasSprite {
+    TextureAtlasSprite {
+        index,
+        color: Color::rgb(tint.0, tint.1, tint.2),
+        ..Default::default()
+    }
+}
+
+fn follow_camera(
+    camera_query: Query<&Transform, With<MainCamera>>,
+    mut layer_query: Query<&mut Transform, (With<ParallaxLayer>, Without<MainCamera>)>,
+) {
+    if let Ok(camera) = camera_query.single() {
+        for mut layer in layer_query.iter_mut() {
+            layer.translation.x = camera.translation.x;
+            layer.translation.y = camera.translation.y;
+        }
+    }
+}
+
+fn scroll_layers(time: Res<Time>, mut query: Query<(&ParallaxItem, &mut Transform)>) {
+    for (item, mut transform) in query.iter_mut() {
+        transform.translation.y -= item.speed * time.delta_seconds();
+        if transform.translation.y < -item.span / 2. {
+            transform.translation.y += item.span;
+        }
+    }
+}
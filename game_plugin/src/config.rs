@@ -0,0 +1,49 @@
+use bevy::log::warn;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::path::PathBuf;
+
+// Config files are TOML files in the platform's config directory,
+// e.g. `~/.config/rustyjam2021` on Linux. There is no such directory in web builds,
+// so nothing is persisted there.
+
+const CONFIG_DIRECTORY: &str = "rustyjam2021";
+
+pub fn config_path(file_name: &str) -> Option<PathBuf> {
+    dirs::config_dir().map(|directory| directory.join(CONFIG_DIRECTORY).join(file_name))
+}
+
+/// Returns `None` if the file does not exist or could not be read
+pub fn load<T: DeserializeOwned>(file_name: &str) -> Option<T> {
+    let path = config_path(file_name)?;
+    let content = std::fs::read_to_string(&path).ok()?;
+    match toml::from_str(&content) {
+        Ok(config) => Some(config),
+        Err(error) => {
+            warn!("Ignoring invalid config file {:?}: {}", path, error);
+            None
+        }
+    }
+}
+
+pub fn save<T: Serialize>(file_name: &str, config: &T) {
+    match toml::to_string_pretty(config) {
+        Ok(content) => save_bytes(file_name, content.as_bytes()),
+        Err(error) => warn!("Failed to serialize config file {}: {}", file_name, error),
+    }
+}
+
+/// Writes a file in the config directory, creating the directory if needed
+pub fn save_bytes(file_name: &str, content: &[u8]) {
+    let path = match config_path(file_name) {
+        Some(path) => path,
+        None => return,
+    };
+    let result = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(&path, content));
+    if let Err(error) = result {
+        warn!("Failed to save config file {:?}: {}", path, error);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::actions::GameControl;
+use crate::focus::UiFocus;
+use crate::input_map::{Binding, InputMap, KeyboardLayout};
+use crate::loading::FontAssets;
+use crate::widgets::{
+    spawn_back_button, spawn_button, spawn_row_button, spawn_scroll_list, spawn_toggle, Toggle,
+    UiTheme, Widget, WidgetChange, WidgetEvent,
+};
+use crate::GameState;
+use bevy::prelude::*;
+
+pub struct ControlsMenuPlugin;
+
+/// This plugin draws the controls screen, where every [GameControl] can be bound to a different key
+/// or gamepad button.
+/// Clicking a control waits for the next key or button press, Escape keeps the old bindings.
+/// The input map is saved when leaving the screen.
+/// Free aiming with the mouse or the right stick is also switched on here.
+impl Plugin for ControlsMenuPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Rebinding>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Controls).with_system(setup_controls_menu.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Controls)
+                    .with_system(
+                        click_controls_button
+                            .system()
+                            .label("click_controls_button")
+                            .after("widgets"),
+                    )
+                    .with_system(listen_for_binding.system().after("click_controls_button"))
+                    .with_system(update_labels.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Controls).with_system(cleanup_controls_menu.system()),
+            );
+    }
+}
+
+/// Stops waiting for a key press without changing any binding
+const CANCEL_KEY: KeyCode = KeyCode::Escape;
+
+#[derive(Default)]
+struct Rebinding {
+    /// The control waiting for a key press
+    control: Option<GameControl>,
+    message: String,
+}
+
+struct ControlsMenu;
+
+enum ControlsButton {
+    Rebind(GameControl),
+    ToggleAim,
+    Reset,
+    Back,
+}
+
+struct MessageText;
+
+/// Rows of the list of controls shown at once
+const VISIBLE_CONTROLS: usize = 6;
+
+fn setup_controls_menu(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    theme: Res<UiTheme>,
+    input_map: Res<InputMap>,
+    mut rebinding: ResMut<Rebinding>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    *rebinding = Rebinding::default();
+    let text_style = TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: 25.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(ControlsMenu)
+        .with_children(|parent| {
+            spawn_scroll_list(parent, VISIBLE_CONTROLS, |parent| {
+                for control in GameControl::ALL.iter() {
+                    spawn_row_button(
+                        parent,
+                        &binding_label(*control, &input_map),
+                        &font_assets,
+                        &theme,
+                        ControlsButton::Rebind(*control),
+                    );
+                }
+            });
+            spawn_toggle(
+                parent,
+                "Aim with mouse / right stick",
+                input_map.free_aim,
+                &font_assets,
+                &theme,
+                ControlsButton::ToggleAim,
+            );
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(10.)),
+                        ..Default::default()
+                    },
+                    text: Text::with_section("", text_style.clone(), Default::default()),
+                    ..Default::default()
+                })
+                .insert(MessageText);
+            spawn_button(parent, "Reset", &font_assets, &theme, ControlsButton::Reset);
+            spawn_back_button(parent, "Back", &font_assets, &theme, ControlsButton::Back);
+        });
+}
+
+fn binding_label(control: GameControl, input_map: &InputMap) -> String {
+    let bindings: Vec<String> = input_map
+        .bindings(control)
+        .iter()
+        .map(|binding| binding.to_string())
+        .collect();
+    if bindings.is_empty() {
+        format!("{}: -", control.name())
+    } else {
+        format!("{}: {}", control.name(), bindings.join(", "))
+    }
+}
+
+fn click_controls_button(
+    mut events: EventReader<WidgetEvent>,
+    mut state: ResMut<State<GameState>>,
+    mut input_map: ResMut<InputMap>,
+    mut rebinding: ResMut<Rebinding>,
+    button_query: Query<&ControlsButton>,
+) {
+    for event in events.iter() {
+        let button = match button_query.get(event.entity) {
+            Ok(button) => button,
+            Err(_) => continue,
+        };
+        match (button, &event.change) {
+            (ControlsButton::Rebind(control), _) => {
+                rebinding.control = Some(*control);
+                rebinding.message = format!(
+                    "Press a key or button for '{}', {:?} cancels",
+                    control.name(),
+                    CANCEL_KEY
+                );
+            }
+            (ControlsButton::ToggleAim, WidgetChange::Toggled(on)) => input_map.free_aim = *on,
+            (ControlsButton::Reset, _) => {
+                *input_map = InputMap::default();
+                *rebinding = Rebinding::default();
+            }
+            (ControlsButton::Back, _) => {
+                if let Err(error) = state.set(GameState::Menu) {
+                    warn!("Ignoring back button: {:?}", error);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn listen_for_binding(
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut input_map: ResMut<InputMap>,
+    mut rebinding: ResMut<Rebinding>,
+    mut focus: ResMut<UiFocus>,
+) {
+    // the press that started rebinding, e.g. confirming with Enter, should not be bound
+    focus.paused = rebinding.control.is_some();
+    if rebinding.is_changed() {
+        return;
+    }
+    let control = match rebinding.control {
+        Some(control) => control,
+        None => return,
+    };
+    if keyboard_input.just_pressed(CANCEL_KEY) {
+        rebinding.control = None;
+        rebinding.message = format!("Kept the bindings of '{}'", control.name());
+        return;
+    }
+    let binding = keyboard_input
+        .get_just_pressed()
+        .next()
+        .map(|key| Binding::Key(*key))
+        .or_else(|| {
+            gamepad_buttons
+                .get_just_pressed()
+                .next()
+                .map(|button| Binding::GamepadButton(button.1))
+        });
+    if let Some(binding) = binding {
+        let unbound: Vec<String> = input_map
+            .rebind(control, binding)
+            .iter()
+            .map(|(other, layout)| match layout {
+                KeyboardLayout::PlayerTwo => format!("'{}' of player two", other.name()),
+                _ => format!("'{}'", other.name()),
+            })
+            .collect();
+        rebinding.message = if unbound.is_empty() {
+            String::new()
+        } else {
+            format!(
+                "{} was bound to {}, it is now unbound there",
+                binding,
+                unbound.join(" and ")
+            )
+        };
+        rebinding.control = None;
+    }
+}
+
+fn update_labels(
+    input_map: Res<InputMap>,
+    rebinding: Res<Rebinding>,
+    mut widget_query: Query<(&ControlsButton, &mut Widget)>,
+    mut toggle_query: Query<(&ControlsButton, &mut Toggle)>,
+    mut message_query: Query<&mut Text, With<MessageText>>,
+) {
+    if !input_map.is_changed() && !rebinding.is_changed() {
+        return;
+    }
+    for (button, mut widget) in widget_query.iter_mut() {
+        if let ControlsButton::Rebind(control) = button {
+            widget.label = if rebinding.control == Some(*control) {
+                format!("{}: ...", control.name())
+            } else {
+                binding_label(*control, &input_map)
+            };
+        }
+    }
+    // resetting the controls also resets the aim
+    for (button, mut toggle) in toggle_query.iter_mut() {
+        if let ControlsButton::ToggleAim = button {
+            toggle.on = input_map.free_aim;
+        }
+    }
+    for mut text in message_query.iter_mut() {
+        text.sections[0].value = rebinding.message.clone();
+    }
+}
+
+fn cleanup_controls_menu(
+    mut commands: Commands,
+    input_map: Res<InputMap>,
+    mut focus: ResMut<UiFocus>,
+    menu: Query<Entity, With<ControlsMenu>>,
+) {
+    input_map.save();
+    focus.paused = false;
+    for entity in menu.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,268 @@
+use crate::enemy::Enemy;
+use crate::loading::FontAssets;
+use crate::overlap::CollisionStats;
+use crate::player::Bullet;
+use crate::rich_text::sections;
+use bevy::diagnostic::{
+    Diagnostic, DiagnosticId, Diagnostics, EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin,
+};
+use bevy::prelude::*;
+use bevy::ui::FocusPolicy;
+use std::collections::VecDeque;
+
+pub struct DebugOverlayPlugin;
+
+/// This plugin registers diagnostics for the things the game spends its time on and shows them,
+/// together with a graph of the last frame times, in an overlay toggled with [TOGGLE_KEY].
+/// It is only compiled with the `debug_overlay` feature.
+impl Plugin for DebugOverlayPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_plugin(FrameTimeDiagnosticsPlugin)
+            .add_plugin(EntityCountDiagnosticsPlugin)
+            .init_resource::<DebugOverlay>()
+            .add_startup_system(setup_diagnostics.system())
+            .add_system(measure.system().label("measure"))
+            .add_system(toggle_overlay.system().label("toggle_overlay"))
+            .add_system(
+                update_overlay
+                    .system()
+                    .after("measure")
+                    .after("toggle_overlay"),
+            );
+    }
+}
+
+pub const BULLETS: DiagnosticId = DiagnosticId::from_u128(142215487320156794215630186349731538925);
+pub const ENEMIES: DiagnosticId = DiagnosticId::from_u128(265948174329873512458630012947562830195);
+/// Collision pairs tested in all ticks of a frame
+pub const COLLISION_PAIRS: DiagnosticId =
+    DiagnosticId::from_u128(98217346590127734650183746512093847261);
+
+const TOGGLE_KEY: KeyCode = KeyCode::F3;
+/// Frames shown in the graph
+const GRAPH_FRAMES: usize = 120;
+const GRAPH_WIDTH: f32 = 240.;
+const GRAPH_HEIGHT: f32 = 60.;
+/// Frame time in seconds that fills the graph
+const GRAPH_MAX: f32 = 1. / 30.;
+const FONT_SIZE: f32 = 16.;
+
+struct DebugOverlay {
+    visible: bool,
+    background: Handle<ColorMaterial>,
+    /// The most recent frame time is at the back
+    frame_times: VecDeque<f32>,
+    /// Bar materials for frames within 60 fps, 30 fps and slower ones
+    fast: Handle<ColorMaterial>,
+    slow: Handle<ColorMaterial>,
+    very_slow: Handle<ColorMaterial>,
+}
+
+impl FromWorld for DebugOverlay {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+        DebugOverlay {
+            visible: false,
+            background: materials.add(Color::rgba(0., 0., 0., 0.6).into()),
+            frame_times: VecDeque::with_capacity(GRAPH_FRAMES),
+            fast: materials.add(Color::rgb(0.3, 0.8, 0.3).into()),
+            slow: materials.add(Color::rgb(0.9, 0.7, 0.2).into()),
+            very_slow: materials.add(Color::rgb(0.9, 0.2, 0.2).into()),
+        }
+    }
+}
+
+struct OverlayRoot;
+
+struct OverlayText;
+
+/// One bar of the graph, 0 is the oldest frame
+struct GraphBar(usize);
+
+fn setup_diagnostics(mut diagnostics: ResMut<Diagnostics>) {
+    diagnostics.add(Diagnostic::new(BULLETS, "bullets", 20));
+    diagnostics.add(Diagnostic::new(ENEMIES, "enemies", 20));
+    diagnostics.add(Diagnostic::new(COLLISION_PAIRS, "collision_pairs", 20));
+}
+
+fn measure(
+    time: Res<Time>,
+    mut overlay: ResMut<DebugOverlay>,
+    mut diagnostics: ResMut<Diagnostics>,
+    mut collision_stats: ResMut<CollisionStats>,
+    bullet_query: Query<(), With<Bullet>>,
+    enemy_query: Query<(), With<Enemy>>,
+) {
+    diagnostics.add_measurement(BULLETS, bullet_query.iter().count() as f64);
+    diagnostics.add_measurement(ENEMIES, enemy_query.iter().count() as f64);
+    diagnostics.add_measurement(COLLISION_PAIRS, collision_stats.pairs_tested as f64);
+    collision_stats.pairs_tested = 0;
+
+    if overlay.frame_times.len() == GRAPH_FRAMES {
+        overlay.frame_times.pop_front();
+    }
+    overlay.frame_times.push_back(time.delta_seconds());
+}
+
+fn toggle_overlay(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut overlay: ResMut<DebugOverlay>,
+    font_assets: Option<Res<FontAssets>>,
+    root_query: Query<Entity, With<OverlayRoot>>,
+) {
+    if keyboard_input.just_pressed(TOGGLE_KEY) {
+        overlay.visible = !overlay.visible;
+    }
+    let root = root_query.iter().next();
+    match (overlay.visible, root, font_assets) {
+        (true, None, Some(font_assets)) => spawn_overlay(&mut commands, &overlay, &font_assets),
+        (false, Some(root), _) => commands.entity(root).despawn_recursive(),
+        _ => (),
+    }
+}
+
+fn spawn_overlay(commands: &mut Commands, overlay: &DebugOverlay, font_assets: &FontAssets) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.),
+                    bottom: Val::Px(10.),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                padding: Rect::all(Val::Px(5.)),
+                ..Default::default()
+            },
+            material: overlay.background.clone(),
+            ..Default::default()
+        })
+        .insert(OverlayRoot)
+        // the overlay must not keep clicks from the menu buttons below it
+        .insert(FocusPolicy::Pass)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        "",
+                        TextStyle {
+                            font: font_assets.share_tech_mono.clone(),
+                            font_size: FONT_SIZE,
+                            color: Color::WHITE,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                })
+                .insert(OverlayText);
+            // the bars stand on the bottom edge of the graph
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(GRAPH_WIDTH), Val::Px(GRAPH_HEIGHT)),
+                        align_items: AlignItems::FlexStart,
+                        margin: Rect {
+                            top: Val::Px(5.),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    },
+                    visible: Visible {
+                        is_visible: false,
+                        is_transparent: true,
+                    },
+                    ..Default::default()
+                })
+                .insert(FocusPolicy::Pass)
+                .with_children(|parent| {
+                    for index in 0..GRAPH_FRAMES {
+                        parent
+                            .spawn_bundle(NodeBundle {
+                                style: Style {
+                                    size: Size::new(
+                                        Val::Px(GRAPH_WIDTH / GRAPH_FRAMES as f32),
+                                        Val::Px(0.),
+                                    ),
+                                    ..Default::default()
+                                },
+                                material: overlay.fast.clone(),
+                                ..Default::default()
+                            })
+                            .insert(GraphBar(index))
+                            .insert(FocusPolicy::Pass);
+                    }
+                });
+        });
+}
+
+fn update_overlay(
+    overlay: Res<DebugOverlay>,
+    diagnostics: Res<Diagnostics>,
+    mut text_query: Query<&mut Text, With<OverlayText>>,
+    mut bar_query: Query<(&GraphBar, &mut Style, &mut Handle<ColorMaterial>)>,
+) {
+    if !overlay.visible {
+        return;
+    }
+    let average = |id| {
+        diagnostics
+            .get(id)
+            .and_then(|diagnostic| diagnostic.average())
+            .unwrap_or_default()
+    };
+    let lines = [
+        format!(
+            "FPS            [color=gold]{:>6.1}[/color]",
+            average(FrameTimeDiagnosticsPlugin::FPS)
+        ),
+        format!(
+            "Frame time     [color=gold]{:>6.2}[/color] ms",
+            average(FrameTimeDiagnosticsPlugin::FRAME_TIME) * 1000.
+        ),
+        format!(
+            "Entities       [color=gold]{:>6.0}[/color]",
+            average(EntityCountDiagnosticsPlugin::ENTITY_COUNT)
+        ),
+        format!(
+            "Bullets        [color=gold]{:>6.0}[/color]",
+            average(BULLETS)
+        ),
+        format!(
+            "Enemies        [color=gold]{:>6.0}[/color]",
+            average(ENEMIES)
+        ),
+        format!(
+            "Collision pairs[color=gold]{:>6.0}[/color]",
+            average(COLLISION_PAIRS)
+        ),
+    ];
+    let markup = lines.join("\n");
+    for mut text in text_query.iter_mut() {
+        // the first line starts with the plain style
+        let style = text.sections[0].style.clone();
+        text.sections = sections(&markup, &style);
+    }
+
+    // the newest frame is drawn at the right edge
+    let offset = GRAPH_FRAMES - overlay.frame_times.len();
+    for (GraphBar(index), mut style, mut material) in bar_query.iter_mut() {
+        let frame_time = index
+            .checked_sub(offset)
+            .and_then(|index| overlay.frame_times.get(index))
+            .copied()
+            .unwrap_or_default();
+        style.size.height = Val::Px((frame_time / GRAPH_MAX).min(1.) * GRAPH_HEIGHT);
+        let wanted = if frame_time <= 1. / 60. + 0.001 {
+            &overlay.fast
+        } else if frame_time <= GRAPH_MAX + 0.001 {
+            &overlay.slow
+        } else {
+            &overlay.very_slow
+        };
+        if *material != *wanted {
+            *material = wanted.clone();
+        }
+    }
+}
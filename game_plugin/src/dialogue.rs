@@ -0,0 +1,556 @@
+use crate::actions::ConnectedGamepads;
+use crate::input_map::{InputMap, MenuControl};
+use crate::level::{DialogueTrigger, LevelData};
+use crate::loading::{FontAssets, LevelAssets};
+use crate::run_timer::SplitReached;
+use crate::widgets::{spawn_row_button, UiTheme, WidgetChange, WidgetEvent};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::Deserialize;
+use std::collections::VecDeque;
+
+pub struct DialoguePlugin;
+
+/// This plugin shows the conversations of a level in a dialogue box. Levels start them
+/// with [DialogueTrigger]s, other plugins can send a [StartDialogue].
+/// A conversation pushes `GameState::Dialogue` on top of `GameState::Playing`, so the game waits for it.
+/// Lines are typed out letter by letter, confirming shows the whole line and then moves on to the next one.
+/// [DialogueLineFinished] and [DialogueFinished] tell the rest of the game how the conversation went.
+impl Plugin for DialoguePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<StartDialogue>()
+            .add_event::<DialogueLineFinished>()
+            .add_event::<DialogueFinished>()
+            .init_resource::<DialogueQueue>()
+            .init_resource::<ActiveDialogue>()
+            .init_resource::<DialogueMaterials>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing).with_system(queue_level_dialogues.system()),
+            )
+            // choices are picked during a conversation, and conversations can be started
+            // from any state, they wait in the queue for the game to play
+            .add_system(trigger_level_dialogues.system().label("trigger_dialogues"))
+            .add_system(
+                queue_dialogues
+                    .system()
+                    .label("queue_dialogues")
+                    .after("trigger_dialogues"),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing).with_system(
+                    start_dialogue
+                        .system()
+                        .after("queue_dialogues")
+                        .after("pause_game"),
+                ),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Dialogue)
+                    .with_system(
+                        advance_dialogue
+                            .system()
+                            .label("advance_dialogue")
+                            .after("widgets"),
+                    )
+                    .with_system(
+                        type_text
+                            .system()
+                            .label("type_text")
+                            .after("advance_dialogue"),
+                    )
+                    .with_system(redraw_dialogue_box.system().after("type_text")),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Dialogue).with_system(hide_dialogue.system()),
+            );
+    }
+}
+
+/// Letters typed out per second
+const TYPING_SPEED: f32 = 40.;
+const TEXT_WIDTH: f32 = 560.;
+const PORTRAIT_SIZE: f32 = 128.;
+const SPEAKER_COLOR: Color = Color::rgb(0.95, 0.8, 0.4);
+
+/// A conversation, loaded from a `.dialogue.ron` file, see `assets/dialogues` for examples
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct DialogueScript {
+    pub lines: Vec<DialogueLine>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct DialogueLine {
+    pub speaker: String,
+    /// Path of the portrait image, relative to the assets directory
+    #[serde(default)]
+    pub portrait: Option<String>,
+    pub text: String,
+    /// Answers the player picks from once the line is shown
+    #[serde(default)]
+    pub choices: Vec<DialogueChoice>,
+    /// Where the conversation goes after this line, if it has no choices
+    #[serde(default)]
+    pub next: Next,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct DialogueChoice {
+    pub text: String,
+    #[serde(default)]
+    pub next: Next,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum Next {
+    /// The line below
+    Following,
+    /// The line with this index
+    Line(usize),
+    End,
+}
+
+impl Default for Next {
+    fn default() -> Self {
+        Next::Following
+    }
+}
+
+/// Starts the conversation from this script path of the level as soon as the game is playing
+pub struct StartDialogue(pub String);
+
+/// Sent when the player moves on from a line
+pub struct DialogueLineFinished {
+    /// Script path of the conversation
+    pub dialogue: String,
+    pub line: usize,
+    /// Index of the picked choice, for lines with choices
+    pub choice: Option<usize>,
+}
+
+/// Sent when a conversation is over
+pub struct DialogueFinished {
+    /// Script path of the conversation
+    pub dialogue: String,
+}
+
+/// Conversations waiting for the current one to end
+#[derive(Default)]
+struct DialogueQueue(VecDeque<String>);
+
+#[derive(Default)]
+struct ActiveDialogue {
+    path: String,
+    script: DialogueScript,
+    line: usize,
+    /// Letters of the line shown so far
+    typed: f32,
+}
+
+impl ActiveDialogue {
+    fn line(&self) -> &DialogueLine {
+        &self.script.lines[self.line]
+    }
+
+    fn is_typing(&self) -> bool {
+        (self.typed as usize) < self.line().text.chars().count()
+    }
+}
+
+struct DialogueMaterials {
+    background: Handle<ColorMaterial>,
+    none: Handle<ColorMaterial>,
+    /// Portraits of the conversations in the current level by path, made when the run starts
+    portraits: HashMap<String, Handle<ColorMaterial>>,
+}
+
+impl FromWorld for DialogueMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+        DialogueMaterials {
+            background: materials.add(Color::rgba(0.05, 0.05, 0.1, 0.9).into()),
+            none: materials.add(Color::NONE.into()),
+            portraits: HashMap::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct DialogueBox {
+    line: usize,
+    /// Choices are only shown once the line is typed out
+    choices: bool,
+}
+
+struct DialogueText;
+
+/// Picks the choice with this index of the current line
+struct ChoiceButton(usize);
+
+fn queue_level_dialogues(
+    mut queue: ResMut<DialogueQueue>,
+    mut dialogue_materials: ResMut<DialogueMaterials>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    level_assets: Res<LevelAssets>,
+    levels: Res<Assets<LevelData>>,
+) {
+    let level = match levels.get(&level_assets.level_1) {
+        Some(level) => level,
+        None => return,
+    };
+    queue.0 = level
+        .dialogues
+        .iter()
+        .filter(|dialogue| matches!(dialogue.trigger, DialogueTrigger::Start))
+        .map(|dialogue| dialogue.path.clone())
+        .collect();
+
+    for (path, texture) in level.portraits.iter() {
+        if !dialogue_materials.portraits.contains_key(path) {
+            let material = materials.add(texture.clone().into());
+            dialogue_materials.portraits.insert(path.clone(), material);
+        }
+    }
+}
+
+fn trigger_level_dialogues(
+    mut splits: EventReader<SplitReached>,
+    mut lines: EventReader<DialogueLineFinished>,
+    mut finished: EventReader<DialogueFinished>,
+    mut start: EventWriter<StartDialogue>,
+    level_assets: Res<LevelAssets>,
+    levels: Res<Assets<LevelData>>,
+) {
+    let level = match levels.get(&level_assets.level_1) {
+        Some(level) => level,
+        None => return,
+    };
+    let split_names: Vec<&str> = level.tilemap.as_ref().map_or(vec![], |tilemap| {
+        tilemap
+            .splits
+            .iter()
+            .map(|split| split.name.as_str())
+            .collect()
+    });
+    let reached: Vec<&str> = splits
+        .iter()
+        .filter_map(|split| split_names.get(split.index).copied())
+        .collect();
+    let picked: Vec<(&str, usize, usize)> = lines
+        .iter()
+        .filter_map(|line| Some((line.dialogue.as_str(), line.line, line.choice?)))
+        .collect();
+    let finished: Vec<&str> = finished
+        .iter()
+        .map(|finished| finished.dialogue.as_str())
+        .collect();
+    for dialogue in level.dialogues.iter() {
+        let triggered = match &dialogue.trigger {
+            DialogueTrigger::Start => false,
+            DialogueTrigger::Split(name) => reached.contains(&name.as_str()),
+            DialogueTrigger::After(path) => finished.contains(&path.as_str()),
+            DialogueTrigger::Choice {
+                dialogue,
+                line,
+                choice,
+            } => picked.contains(&(dialogue.as_str(), *line, *choice)),
+        };
+        if triggered {
+            start.send(StartDialogue(dialogue.path.clone()));
+        }
+    }
+}
+
+fn queue_dialogues(mut events: EventReader<StartDialogue>, mut queue: ResMut<DialogueQueue>) {
+    queue
+        .0
+        .extend(events.iter().map(|StartDialogue(path)| path.clone()));
+}
+
+fn start_dialogue(
+    mut queue: ResMut<DialogueQueue>,
+    mut active: ResMut<ActiveDialogue>,
+    mut state: ResMut<State<GameState>>,
+    level_assets: Res<LevelAssets>,
+    levels: Res<Assets<LevelData>>,
+) {
+    let path = match queue.0.front() {
+        Some(path) => path.clone(),
+        None => return,
+    };
+    let script = levels
+        .get(&level_assets.level_1)
+        .and_then(|level| {
+            level
+                .dialogues
+                .iter()
+                .find(|dialogue| dialogue.path == path)
+        })
+        .and_then(|dialogue| dialogue.script.clone());
+    match script {
+        Some(script) if !script.lines.is_empty() => {
+            // another transition this frame, like pausing, goes first
+            if state.push(GameState::Dialogue).is_err() {
+                return;
+            }
+            *active = ActiveDialogue {
+                path,
+                script,
+                line: 0,
+                typed: 0.,
+            };
+        }
+        _ => warn!("The level has no conversation {}", path),
+    }
+    queue.0.pop_front();
+}
+
+/// Spawns the box again for every new line, and once more when the choices of a line appear
+fn redraw_dialogue_box(
+    mut commands: Commands,
+    active: Res<ActiveDialogue>,
+    dialogue_materials: Res<DialogueMaterials>,
+    font_assets: Res<FontAssets>,
+    theme: Res<UiTheme>,
+    box_query: Query<(Entity, &DialogueBox)>,
+) {
+    let wanted = DialogueBox {
+        line: active.line,
+        choices: !active.line().choices.is_empty() && !active.is_typing(),
+    };
+    if box_query.iter().any(|(_, shown)| *shown == wanted) {
+        return;
+    }
+    for (entity, _) in box_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    spawn_dialogue_box(
+        &mut commands,
+        wanted,
+        &active,
+        &dialogue_materials,
+        &font_assets,
+        &theme,
+    );
+}
+
+/// The box at the bottom of the window with the current line
+fn spawn_dialogue_box(
+    commands: &mut Commands,
+    dialogue_box: DialogueBox,
+    active: &ActiveDialogue,
+    dialogue_materials: &DialogueMaterials,
+    font_assets: &FontAssets,
+    theme: &UiTheme,
+) {
+    let line = active.line();
+    let style = |color| TextStyle {
+        font: font_assets.fira_sans.clone(),
+        font_size: 24.,
+        color,
+    };
+    let typed = active.typed as usize;
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(0.),
+                    right: Val::Px(0.),
+                    bottom: Val::Px(20.),
+                    ..Default::default()
+                },
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            material: dialogue_materials.none.clone(),
+            ..Default::default()
+        })
+        .insert(dialogue_box)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        padding: Rect::all(Val::Px(15.)),
+                        align_items: AlignItems::FlexEnd,
+                        ..Default::default()
+                    },
+                    material: dialogue_materials.background.clone(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    let portrait = line
+                        .portrait
+                        .as_ref()
+                        .and_then(|path| dialogue_materials.portraits.get(path));
+                    if let Some(portrait) = portrait {
+                        parent.spawn_bundle(ImageBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(PORTRAIT_SIZE), Val::Px(PORTRAIT_SIZE)),
+                                margin: Rect {
+                                    right: Val::Px(15.),
+                                    ..Default::default()
+                                },
+                                ..Default::default()
+                            },
+                            material: portrait.clone(),
+                            ..Default::default()
+                        });
+                    }
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: Style {
+                                flex_direction: FlexDirection::ColumnReverse,
+                                align_items: AlignItems::FlexStart,
+                                min_size: Size::new(Val::Px(TEXT_WIDTH), Val::Px(PORTRAIT_SIZE)),
+                                ..Default::default()
+                            },
+                            material: dialogue_materials.none.clone(),
+                            ..Default::default()
+                        })
+                        .with_children(|parent| {
+                            parent.spawn_bundle(TextBundle {
+                                text: Text::with_section(
+                                    line.speaker.clone(),
+                                    style(SPEAKER_COLOR),
+                                    Default::default(),
+                                ),
+                                ..Default::default()
+                            });
+                            // the untyped rest of the line is already there, but invisible,
+                            // so words do not jump to the next row while they are typed
+                            parent
+                                .spawn_bundle(TextBundle {
+                                    style: Style {
+                                        max_size: Size::new(Val::Px(TEXT_WIDTH), Val::Undefined),
+                                        margin: Rect {
+                                            top: Val::Px(8.),
+                                            bottom: Val::Px(8.),
+                                            ..Default::default()
+                                        },
+                                        ..Default::default()
+                                    },
+                                    text: Text {
+                                        sections: vec![
+                                            TextSection {
+                                                value: line.text.chars().take(typed).collect(),
+                                                style: style(theme.text_color),
+                                            },
+                                            TextSection {
+                                                value: line.text.chars().skip(typed).collect(),
+                                                style: style(Color::NONE),
+                                            },
+                                        ],
+                                        ..Default::default()
+                                    },
+                                    ..Default::default()
+                                })
+                                .insert(DialogueText);
+                            if dialogue_box.choices {
+                                for (index, choice) in line.choices.iter().enumerate() {
+                                    spawn_row_button(
+                                        parent,
+                                        &choice.text,
+                                        font_assets,
+                                        theme,
+                                        ChoiceButton(index),
+                                    );
+                                }
+                            }
+                        });
+                });
+        });
+}
+
+fn type_text(
+    time: Res<Time>,
+    mut active: ResMut<ActiveDialogue>,
+    mut text_query: Query<&mut Text, With<DialogueText>>,
+) {
+    let length = active.line().text.chars().count();
+    active.typed = (active.typed + TYPING_SPEED * time.delta_seconds()).min(length as f32);
+    let typed = active.typed as usize;
+    for mut text in text_query.iter_mut() {
+        if text.sections[0].value.chars().count() == typed {
+            continue;
+        }
+        let line = &active.line().text;
+        text.sections[0].value = line.chars().take(typed).collect();
+        text.sections[1].value = line.chars().skip(typed).collect();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn advance_dialogue(
+    mut active: ResMut<ActiveDialogue>,
+    mut state: ResMut<State<GameState>>,
+    mut widget_events: EventReader<WidgetEvent>,
+    mut line_finished: EventWriter<DialogueLineFinished>,
+    mut finished: EventWriter<DialogueFinished>,
+    input_map: Res<InputMap>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepads: Res<ConnectedGamepads>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    touches: Res<Touches>,
+    choice_query: Query<&ChoiceButton>,
+) {
+    let picked = widget_events
+        .iter()
+        .filter(|event| event.change == WidgetChange::Clicked)
+        .find_map(|event| choice_query.get(event.entity).ok())
+        .map(|ChoiceButton(index)| *index);
+    let confirmed = input_map.menu_just_pressed(
+        MenuControl::Confirm,
+        &keyboard_input,
+        &gamepads.0,
+        &gamepad_buttons,
+    ) || mouse_buttons.just_pressed(MouseButton::Left)
+        || touches.iter_just_pressed().next().is_some();
+
+    if picked.is_none() && !confirmed {
+        return;
+    }
+
+    let line = active.line().clone();
+    let next = match picked {
+        Some(index) => line.choices[index].next,
+        // choices are buttons, the focus takes care of confirming them
+        None if !line.choices.is_empty() && !active.is_typing() => return,
+        None if active.is_typing() => {
+            active.typed = line.text.chars().count() as f32;
+            return;
+        }
+        None => line.next,
+    };
+    let next = match next {
+        Next::Following => Some(active.line + 1),
+        Next::Line(line) => Some(line),
+        Next::End => None,
+    }
+    .filter(|line| *line < active.script.lines.len());
+    // another transition this frame goes first, the conversation stays on this line
+    if next.is_none() && state.pop().is_err() {
+        return;
+    }
+    line_finished.send(DialogueLineFinished {
+        dialogue: active.path.clone(),
+        line: active.line,
+        choice: picked,
+    });
+    match next {
+        Some(next) => {
+            active.line = next;
+            active.typed = 0.;
+        }
+        None => finished.send(DialogueFinished {
+            dialogue: active.path.clone(),
+        }),
+    }
+}
+
+fn hide_dialogue(mut commands: Commands, box_query: Query<Entity, With<DialogueBox>>) {
+    for entity in box_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -1,42 +1,71 @@
-use crate::loading::TextureAssets;
+use crate::loading::TextureAtlases;
+use crate::rng::GameRng;
+use crate::simulation::{Interpolated, RunEntity, SimulationStage, TIMESTEP};
 use crate::GameState;
 use bevy::prelude::*;
+use rand::Rng;
+use std::time::Duration;
 
 pub struct EnemyPlugin;
-pub struct Enemy;
+
+#[derive(Clone)]
+pub struct Enemy {
+    pub health: u32,
+}
 
 impl Plugin for EnemyPlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_system_set(
             SystemSet::on_enter(GameState::Playing)
-                .with_system(spawn_enemy.system())
+                .with_system(spawn_enemy.system().after("start_run"))
         )
-        .add_system_set(SystemSet::on_update(GameState::Playing).with_system(move_enemy.system()));
+        .add_system_to_stage(SimulationStage::Tick, move_enemy.system().after("gather_input"));
     }
 }
 
+/// Enemies spawn up to this far left or right of the center
+const SPAWN_SPREAD: f32 = 100.;
+
 fn spawn_enemy(
     mut commands: Commands,
-    textures: Res<TextureAssets>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut rng: ResMut<GameRng>,
+    texture_atlases: Res<TextureAtlases>,
 ) {
-    let texture_handle = textures.texture_tileset.clone().into();
-    let texture_atlas = TextureAtlas::from_grid(texture_handle, Vec2::new(16.0, 16.0), 24, 10);
-    let texture_atlas_handle = texture_atlases.add(texture_atlas);
+    // a replay or the other player online rolls the same position
+    let x = rng.gen_range(-SPAWN_SPREAD..SPAWN_SPREAD);
+    spawn_enemy_at(
+        &mut commands,
+        &texture_atlases.main_sprite_sheet,
+        Transform::from_translation(Vec3::new(x, 0., 1.)),
+        Enemy { health: 3 },
+        Timer::from_seconds(0.1, true),
+        189,
+    );
+}
 
+/// Spawns an enemy in the given state, `animation` drives its sprite `index`
+pub fn spawn_enemy_at(
+    commands: &mut Commands,
+    texture_atlas_handle: &Handle<TextureAtlas>,
+    transform: Transform,
+    enemy: Enemy,
+    animation: Timer,
+    index: u32,
+) {
     commands.spawn()
         .insert_bundle(SpriteSheetBundle {
-            texture_atlas: texture_atlas_handle,
-            transform: Transform::from_translation(Vec3::new(0., 0., 1.)),
-            sprite: TextureAtlasSprite::new(189),
+            texture_atlas: texture_atlas_handle.clone(),
+            transform,
+            sprite: TextureAtlasSprite::new(index),
             ..Default::default()
         })
-        .insert(Timer::from_seconds(0.1, true))
-        .insert(Enemy);
+        .insert(animation)
+        .insert(enemy)
+        .insert(RunEntity)
+        .insert(Interpolated::new(transform.translation));
 }
 
 fn move_enemy(
-    time: Res<Time>,
     mut movement_query: Query<&mut Transform, With<Enemy>>,
     mut sprite_query: Query<(&mut Timer, &mut TextureAtlasSprite)>,
 ) {
@@ -47,7 +76,7 @@ fn move_enemy(
     // rapidly swap its texture, like it's an animation or something.
     let anim_sprite_sheet_indices: [u32; 2] = [189, (189-24)];
     for (mut timer, mut sprite) in sprite_query.iter_mut() {
-        timer.tick(time.delta());
+        timer.tick(Duration::from_secs_f32(TIMESTEP));
         if timer.finished() {
             let current_index = anim_sprite_sheet_indices.iter().position(|&x| x == sprite.index).unwrap();
             sprite.index = anim_sprite_sheet_indices[(current_index + 1) % anim_sprite_sheet_indices.len()];
@@ -0,0 +1,363 @@
+use crate::actions::ConnectedGamepads;
+use crate::input_map::{Binding, InputMap, MenuControl};
+use crate::widgets::{
+    activate, scroll_to, BackButton, Focused, LetterPicker, OnClick, ScrollList, Selector, Slider,
+    Toggle, Widget, WidgetChange, WidgetEvent,
+};
+use crate::GameState;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+pub struct FocusPlugin;
+
+/// This plugin makes every menu usable without a mouse. The [MenuControl]s of the [InputMap] move the
+/// focus to the next widget in a direction, step sliders and selectors, confirm the focused widget
+/// and press the [BackButton] of a screen. The focused widget of every screen is remembered,
+/// so returning to a screen focuses the same widget again.
+impl Plugin for FocusPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<UiFocus>()
+            .add_system(restore_focus.system().label("restore_focus"))
+            .add_system(
+                navigate_menus
+                    .system()
+                    .label("widgets")
+                    .after("restore_focus"),
+            )
+            .add_system(remember_focus.system().after("widgets"))
+            .add_system(scroll_to_focus.system().after("widgets"));
+    }
+}
+
+#[derive(Default)]
+pub struct UiFocus {
+    /// Menu controls are ignored while set, e.g. while waiting for a key to bind
+    pub paused: bool,
+    /// The position of the focused widget on every screen
+    remembered: HashMap<GameState, usize>,
+}
+
+/// Stick input past this counts as a direction
+const STICK_THRESHOLD: f32 = 0.5;
+
+/// Takes the focus from the `focused` widgets and gives it to `entity`
+pub fn move_focus(commands: &mut Commands, focused: impl Iterator<Item = Entity>, entity: Entity) {
+    for focused in focused.filter(|focused| *focused != entity) {
+        commands.entity(focused).remove::<Focused>();
+    }
+    commands.entity(entity).insert(Focused);
+}
+
+/// All widgets in the order they were spawned in
+fn widget_order(
+    roots: &Query<Entity, (With<Node>, Without<Parent>)>,
+    children_query: &Query<&Children>,
+    widget_query: &Query<Entity, With<Widget>>,
+) -> Vec<Entity> {
+    fn visit(
+        entity: Entity,
+        children_query: &Query<&Children>,
+        widget_query: &Query<Entity, With<Widget>>,
+        order: &mut Vec<Entity>,
+    ) {
+        if widget_query.get(entity).is_ok() {
+            order.push(entity);
+        }
+        if let Ok(children) = children_query.get(entity) {
+            for child in children.iter() {
+                visit(*child, children_query, widget_query, order);
+            }
+        }
+    }
+    let mut order = vec![];
+    for root in roots.iter() {
+        visit(root, children_query, widget_query, &mut order);
+    }
+    order
+}
+
+/// A screen that was just entered has no focused widget yet
+fn restore_focus(
+    mut commands: Commands,
+    state: Res<State<GameState>>,
+    focus: Res<UiFocus>,
+    focused_query: Query<Entity, With<Focused>>,
+    roots: Query<Entity, (With<Node>, Without<Parent>)>,
+    children_query: Query<&Children>,
+    widget_query: Query<Entity, With<Widget>>,
+) {
+    if focused_query.iter().next().is_some() || widget_query.iter().next().is_none() {
+        return;
+    }
+    let order = widget_order(&roots, &children_query, &widget_query);
+    let index = focus
+        .remembered
+        .get(state.current())
+        .copied()
+        .unwrap_or(0)
+        .min(order.len().saturating_sub(1));
+    if let Some(entity) = order.get(index) {
+        commands.entity(*entity).insert(Focused);
+    }
+}
+
+fn remember_focus(
+    state: Res<State<GameState>>,
+    mut focus: ResMut<UiFocus>,
+    focused_query: Query<Entity, Added<Focused>>,
+    roots: Query<Entity, (With<Node>, Without<Parent>)>,
+    children_query: Query<&Children>,
+    widget_query: Query<Entity, With<Widget>>,
+) {
+    let focused = match focused_query.iter().next() {
+        Some(focused) => focused,
+        None => return,
+    };
+    let order = widget_order(&roots, &children_query, &widget_query);
+    if let Some(index) = order.iter().position(|entity| *entity == focused) {
+        focus.remembered.insert(state.current().clone(), index);
+    }
+}
+
+fn scroll_to_focus(
+    focused_query: Query<(Entity, &Parent), Added<Focused>>,
+    mut list_query: Query<(&mut ScrollList, &Children)>,
+) {
+    for (entity, parent) in focused_query.iter() {
+        if let Ok((mut list, rows)) = list_query.get_mut(parent.0) {
+            if let Some(row) = rows.iter().position(|row| *row == entity) {
+                scroll_to(&mut list, row);
+            }
+        }
+    }
+}
+
+/// Keys that change a focused letter picker instead of navigating
+fn edits_text(key: KeyCode) -> bool {
+    use KeyCode::*;
+    matches!(
+        key,
+        A | B
+            | C
+            | D
+            | E
+            | F
+            | G
+            | H
+            | I
+            | J
+            | K
+            | L
+            | M
+            | N
+            | O
+            | P
+            | Q
+            | R
+            | S
+            | T
+            | U
+            | V
+            | W
+            | X
+            | Y
+            | Z
+            | Key0
+            | Key1
+            | Key2
+            | Key3
+            | Key4
+            | Key5
+            | Key6
+            | Key7
+            | Key8
+            | Key9
+            | Space
+            | Back
+    )
+}
+
+fn direction(control: MenuControl) -> Vec2 {
+    match control {
+        MenuControl::Up => Vec2::Y,
+        MenuControl::Down => -Vec2::Y,
+        MenuControl::Left => -Vec2::X,
+        MenuControl::Right => Vec2::X,
+        MenuControl::Confirm | MenuControl::Back => Vec2::ZERO,
+    }
+}
+
+type FocusedWidget<'a> = (
+    Entity,
+    &'a GlobalTransform,
+    Option<&'a Parent>,
+    Option<&'a mut Toggle>,
+    Option<&'a mut Selector>,
+    Option<&'a mut Slider>,
+    Option<&'a OnClick>,
+    Option<&'a mut LetterPicker>,
+);
+
+#[allow(clippy::too_many_arguments)]
+fn navigate_menus(
+    mut commands: Commands,
+    focus: Res<UiFocus>,
+    input_map: Res<InputMap>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    gamepads: Res<ConnectedGamepads>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut last_stick: Local<Option<MenuControl>>,
+    mut events: EventWriter<WidgetEvent>,
+    mut focused_query: Query<FocusedWidget, With<Focused>>,
+    widget_query: Query<(Entity, &Node, &GlobalTransform, &Visible), With<Widget>>,
+    back_query: Query<(Entity, Option<&OnClick>), With<BackButton>>,
+    list_query: Query<&Children, With<ScrollList>>,
+) {
+    // the stick counts once when it is pushed into a direction
+    let stick = gamepads.0.iter().find_map(|gamepad| {
+        let axis = |axis_type| {
+            axes.get(GamepadAxis(*gamepad, axis_type))
+                .unwrap_or_default()
+        };
+        let (x, y) = (
+            axis(GamepadAxisType::LeftStickX),
+            axis(GamepadAxisType::LeftStickY),
+        );
+        if y > STICK_THRESHOLD {
+            Some(MenuControl::Up)
+        } else if y < -STICK_THRESHOLD {
+            Some(MenuControl::Down)
+        } else if x < -STICK_THRESHOLD {
+            Some(MenuControl::Left)
+        } else if x > STICK_THRESHOLD {
+            Some(MenuControl::Right)
+        } else {
+            None
+        }
+    });
+    let stick_pushed = stick.filter(|control| *last_stick != Some(*control));
+    *last_stick = stick;
+    if focus.paused {
+        return;
+    }
+
+    let mut focused = focused_query.iter_mut().next();
+    let typing = focused
+        .as_ref()
+        .map_or(false, |(.., picker)| picker.is_some());
+    let pressed = |control: MenuControl| {
+        stick_pushed == Some(control)
+            || input_map
+                .menu_bindings(control)
+                .iter()
+                .any(|binding| match binding {
+                    Binding::Key(key) => {
+                        !(typing && edits_text(*key)) && keyboard_input.just_pressed(*key)
+                    }
+                    Binding::GamepadButton(button) => gamepads.0.iter().any(|gamepad| {
+                        gamepad_buttons.just_pressed(GamepadButton(*gamepad, *button))
+                    }),
+                })
+    };
+
+    if pressed(MenuControl::Back) {
+        if let Some((entity, on_click)) = back_query.iter().next() {
+            let change = activate(&mut commands, None, None, on_click);
+            events.send(WidgetEvent { entity, change });
+        }
+        return;
+    }
+    let (entity, transform, parent, toggle, selector, slider, on_click, picker) =
+        match focused.as_mut() {
+            Some(focused) => focused,
+            None => return,
+        };
+    let entity = *entity;
+    if pressed(MenuControl::Confirm) {
+        if slider.is_none() {
+            let change = activate(
+                &mut commands,
+                toggle.as_deref_mut(),
+                selector.as_deref_mut(),
+                *on_click,
+            );
+            events.send(WidgetEvent { entity, change });
+        }
+        return;
+    }
+
+    let control = match [
+        MenuControl::Up,
+        MenuControl::Down,
+        MenuControl::Left,
+        MenuControl::Right,
+    ]
+    .iter()
+    .copied()
+    .find(|control| pressed(*control))
+    {
+        Some(control) => control,
+        None => return,
+    };
+    let horizontal = control == MenuControl::Left || control == MenuControl::Right;
+    let forward = control == MenuControl::Right;
+    // sliders and selectors take left and right for themselves
+    if let (true, Some(slider)) = (horizontal, slider.as_mut()) {
+        let step = if forward { slider.step } else { -slider.step };
+        let value = slider.value + step;
+        if slider.set(value) {
+            let change = WidgetChange::Value(slider.value);
+            events.send(WidgetEvent { entity, change });
+        }
+        return;
+    }
+    if let (true, Some(selector)) = (horizontal, selector.as_mut()) {
+        selector.cycle(forward);
+        let change = WidgetChange::Selected(selector.selected);
+        events.send(WidgetEvent { entity, change });
+        return;
+    }
+    // letter pickers take up and down, like on an arcade cabinet
+    if let (false, Some(picker)) = (horizontal, picker.as_mut()) {
+        picker.cycle(control == MenuControl::Up);
+        let change = WidgetChange::Letter(picker.letter);
+        events.send(WidgetEvent { entity, change });
+        return;
+    }
+
+    // rows of a scroll list can be hidden, so up and down walk through the list
+    let next_row = (*parent)
+        .and_then(|parent| list_query.get(parent.0).ok())
+        .and_then(|rows| {
+            let row = rows.iter().position(|row| *row == entity)?;
+            let next = match control {
+                MenuControl::Up => row.checked_sub(1)?,
+                MenuControl::Down => row + 1,
+                _ => return None,
+            };
+            rows.get(next).copied()
+        });
+    let next = next_row.or_else(|| {
+        let from = transform.translation.truncate();
+        let direction = direction(control);
+        widget_query
+            .iter()
+            .filter(|(other, node, _, visible)| {
+                *other != entity && visible.is_visible && node.size != Vec2::ZERO
+            })
+            .filter_map(|(other, _, other_transform, _)| {
+                let offset = other_transform.translation.truncate() - from;
+                let along = offset.dot(direction);
+                let across = (offset - direction * along).length();
+                // prefer widgets in line with the focused one
+                (along > 0.).then(|| (other, along + 2. * across))
+            })
+            .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
+            .map(|(other, _)| other)
+    });
+    if let Some(next) = next {
+        commands.entity(entity).remove::<Focused>();
+        commands.entity(next).insert(Focused);
+    }
+}
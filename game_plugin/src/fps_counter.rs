@@ -1,119 +0,0 @@
-use bevy::{
-    diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
-    prelude::*,
-};
-
-pub struct FPSCounterPlugin;
-
-struct FpsText;
-struct ColorText;
-
-impl Plugin for FPSCounterPlugin {
-    fn build(&self, app: &mut AppBuilder) {
-        app.add_plugin(FrameTimeDiagnosticsPlugin::default())
-            .add_startup_system(setup.system())
-            .add_system(text_update_system.system())
-            .add_system(text_color_system.system());
-    }
-}
-
-fn setup(mut commands: Commands, asset_server: Res<AssetServer>) {
-    // UI camera
-    commands.spawn_bundle(UiCameraBundle::default()); // TODO: Should this be somewhere else?
-
-    // Text with one section
-    commands
-        .spawn_bundle(TextBundle {
-            style: Style {
-                align_self: AlignSelf::FlexEnd,
-                position_type: PositionType::Absolute,
-                position: Rect {
-                    left: Val::Px(25.),
-                    right: Val::Px(25.),
-                    bottom: Val::Px(25.),
-                    top: Val::Px(420.),
-                },
-                max_size: Size::new(Val::Px(910.), Val::Px(515.)),
-                ..Default::default()
-            },
-            text: Text::with_section(
-                "This is example text at the bottom of the screen. Two whole complete sentences worth! Wowzers! This is example text at the bottom of the screen. Two whole complete sentences worth! Wowzers! This is example text at the bottom of the screen. Two whole complete sentences worth! Wowzers!",
-                TextStyle {
-                    font: asset_server.load("fonts/ShareTechMono-Regular.ttf"),
-                    font_size: 25.0,
-                    color: Color::WHITE,
-                },
-                TextAlignment {
-                    horizontal: HorizontalAlign::Center,
-                    ..Default::default()
-                },
-            ),
-            ..Default::default()
-        })
-        .insert(ColorText);
-
-    // Rich text with multiple sections
-    commands
-        .spawn_bundle(TextBundle {
-            style: Style {
-                align_self: AlignSelf::FlexEnd,
-                position_type: PositionType::Absolute,
-                position: Rect {
-                    bottom: Val::Px(5.0),
-                    right: Val::Px(15.0),
-                    ..Default::default()
-                },
-                ..Default::default()
-            },
-            // Use `Text` directly
-            text: Text {
-                // Construct a `Vec` of `TextSection`s
-                sections: vec![
-                    TextSection {
-                        value: "FPS: ".to_string(),
-                        style: TextStyle {
-                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                            font_size: 60.0,
-                            color: Color::WHITE,
-                        },
-                    },
-                    TextSection {
-                        value: "".to_string(),
-                        style: TextStyle {
-                            font: asset_server.load("fonts/FiraSans-Bold.ttf"),
-                            font_size: 60.0,
-                            color: Color::GOLD,
-                        },
-                    },
-                ],
-                ..Default::default()
-            },
-            ..Default::default()
-        })
-        .insert(FpsText);
-}
-
-fn text_update_system(diagnostics: Res<Diagnostics>, mut query: Query<&mut Text, With<FpsText>>) {
-    for mut text in query.iter_mut() {
-        if let Some(fps) = diagnostics.get(FrameTimeDiagnosticsPlugin::FPS) {
-            if let Some(average) = fps.average() {
-                // Update the value of the second section
-                text.sections[1].value = format!("{:.2}", average);
-            }
-        }
-    }
-}
-
-fn text_color_system(time: Res<Time>, mut query: Query<&mut Text, With<ColorText>>) {
-    for mut text in query.iter_mut() {
-        let seconds = time.seconds_since_startup() as f32;
-        // We used the `Text::with_section` helper method, but it is still just a `Text`,
-        // so to update it, we are still updating the one and only section
-        text.sections[0].style.color = Color::Rgba {
-            red: (1.25 * seconds).sin() / 2.0 + 0.5,
-            green: (0.75 * seconds).sin() / 2.0 + 0.5,
-            blue: (0.50 * seconds).sin() / 2.0 + 0.5,
-            alpha: 1.0,
-        };
-    }
-}
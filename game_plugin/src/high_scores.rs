@@ -0,0 +1,554 @@
+use crate::config;
+use crate::focus::move_focus;
+use crate::loading::FontAssets;
+use crate::party::{Party, PlayerInput};
+use crate::replay::Replay;
+use crate::rich_text::RichTextBundle;
+use crate::run_timer::format_time;
+use crate::score::Score;
+use crate::simulation::{SimulationTime, TIMESTEP};
+use crate::widgets::{
+    spawn_back_button, spawn_button, spawn_letter_picker, spawn_selector, Focused, LetterPicker,
+    Selector, UiTheme, WidgetChange, WidgetEvent,
+};
+use crate::GameState;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct HighScoresPlugin;
+
+/// This plugin keeps the best scores of every [GameMode] in `high_scores.toml`.
+/// A run that makes it into the table leads to the name entry screen instead of the menu,
+/// the tables can be looked at from the menu.
+impl Plugin for HighScoresPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(HighScores::load())
+            .init_resource::<HighScoreView>()
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing)
+                    .with_system(submit_score.system().before("finish_run")),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Menu).with_system(enter_pending_name.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::NameEntry).with_system(setup_name_entry.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::NameEntry)
+                    .with_system(enter_name.system().after("widgets")),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::NameEntry)
+                    .with_system(cleanup::<NameEntryScreen>.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::HighScores).with_system(setup_high_scores.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::HighScores)
+                    .with_system(
+                        click_high_scores_button
+                            .system()
+                            .label("click_high_scores_button")
+                            .after("widgets"),
+                    )
+                    .with_system(update_table.system().after("click_high_scores_button")),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::HighScores)
+                    .with_system(cleanup::<HighScoresScreen>.system()),
+            );
+    }
+}
+
+const HIGH_SCORES_FILE: &str = "high_scores.toml";
+/// Version of the file layout, files of other versions are ignored
+const VERSION: u32 = 1;
+/// Entries kept per game mode
+const TABLE_SIZE: usize = 10;
+const NAME_LENGTH: usize = 5;
+const HIGHLIGHT_COLOR: Color = Color::GOLD;
+
+/// High scores are kept apart for every way of playing
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameMode {
+    Solo,
+    Coop,
+    Online,
+}
+
+impl GameMode {
+    const ALL: [GameMode; 3] = [GameMode::Solo, GameMode::Coop, GameMode::Online];
+
+    fn of(party: &Party) -> Self {
+        if party
+            .players()
+            .any(|(_, input)| input == PlayerInput::Network)
+        {
+            GameMode::Online
+        } else if party.len() > 1 {
+            GameMode::Coop
+        } else {
+            GameMode::Solo
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            GameMode::Solo => "Solo",
+            GameMode::Coop => "Co-op",
+            GameMode::Online => "Online",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct HighScore {
+    name: String,
+    score: u32,
+    /// Seconds the run lasted
+    time: f32,
+    /// Day of the run as `YYYY-MM-DD`
+    date: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct HighScoreTable {
+    mode: GameMode,
+    /// Best first
+    entries: Vec<HighScore>,
+}
+
+/// Everything saved in the high scores file
+#[derive(Serialize, Deserialize)]
+struct HighScores {
+    version: u32,
+    tables: Vec<HighScoreTable>,
+}
+
+impl Default for HighScores {
+    fn default() -> Self {
+        HighScores {
+            version: VERSION,
+            tables: vec![],
+        }
+    }
+}
+
+impl HighScores {
+    fn load() -> Self {
+        match config::load::<HighScores>(HIGH_SCORES_FILE) {
+            Some(scores) if scores.version == VERSION => scores,
+            Some(scores) => {
+                warn!("Ignoring high scores of version {}", scores.version);
+                HighScores::default()
+            }
+            None => HighScores::default(),
+        }
+    }
+
+    fn entries(&self, mode: GameMode) -> &[HighScore] {
+        self.tables
+            .iter()
+            .find(|table| table.mode == mode)
+            .map_or(&[], |table| &table.entries[..])
+    }
+
+    fn qualifies(&self, mode: GameMode, score: u32) -> bool {
+        let last = self.entries(mode).get(TABLE_SIZE - 1);
+        score > 0 && !matches!(last, Some(last) if score <= last.score)
+    }
+
+    /// Adds a score to the table of its mode and returns its rank, starting at 0
+    fn insert(&mut self, mode: GameMode, entry: HighScore) -> usize {
+        let index = match self.tables.iter().position(|table| table.mode == mode) {
+            Some(index) => index,
+            None => {
+                self.tables.push(HighScoreTable {
+                    mode,
+                    entries: vec![],
+                });
+                self.tables.len() - 1
+            }
+        };
+        let entries = &mut self.tables[index].entries;
+        // older entries stay ahead of equal scores
+        let rank = entries
+            .iter()
+            .position(|other| entry.score > other.score)
+            .unwrap_or(entries.len());
+        entries.insert(rank, entry);
+        entries.truncate(TABLE_SIZE);
+        rank
+    }
+}
+
+/// A score waiting for the players to enter their name
+struct PendingScore {
+    mode: GameMode,
+    entry: HighScore,
+}
+
+/// Which table the high scores screen shows
+struct HighScoreView {
+    mode: GameMode,
+    /// Rank of the entry that was just added
+    highlight: Option<usize>,
+}
+
+impl Default for HighScoreView {
+    fn default() -> Self {
+        HighScoreView {
+            mode: GameMode::Solo,
+            highlight: None,
+        }
+    }
+}
+
+/// Today's date in UTC as `YYYY-MM-DD`
+#[cfg(not(target_arch = "wasm32"))]
+fn today() -> String {
+    let seconds = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    // days since 1970-01-01 to a civil date, see http://howardhinnant.github.io/date_algorithms.html
+    let days = seconds / 86_400 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// There is no system clock to read in web builds
+#[cfg(target_arch = "wasm32")]
+fn today() -> String {
+    String::new()
+}
+
+/// Replays do not count, and the replay forgets that it was playing in `finish_run`
+fn submit_score(
+    mut commands: Commands,
+    score: Res<Score>,
+    high_scores: Res<HighScores>,
+    party: Res<Party>,
+    replay: Res<Replay>,
+    simulation_time: Res<SimulationTime>,
+) {
+    let mode = GameMode::of(&party);
+    if replay.is_playing() || !high_scores.qualifies(mode, score.0) {
+        return;
+    }
+    commands.insert_resource(PendingScore {
+        mode,
+        entry: HighScore {
+            name: String::new(),
+            score: score.0,
+            time: simulation_time.tick() as f32 * TIMESTEP,
+            date: today(),
+        },
+    });
+}
+
+/// The menu is left again within the same frame, so it never shows
+fn enter_pending_name(pending: Option<Res<PendingScore>>, mut state: ResMut<State<GameState>>) {
+    if pending.is_some() {
+        if let Err(error) = state.set(GameState::NameEntry) {
+            warn!("Delaying the name entry: {:?}", error);
+        }
+    }
+}
+
+struct NameEntryScreen;
+
+enum NameEntryButton {
+    Letter(usize),
+    Done,
+}
+
+fn setup_name_entry(
+    mut commands: Commands,
+    pending: Res<PendingScore>,
+    font_assets: Res<FontAssets>,
+    theme: Res<UiTheme>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let none = materials.add(Color::NONE.into());
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: none.clone(),
+            ..Default::default()
+        })
+        .insert(NameEntryScreen)
+        .with_children(|parent| {
+            parent.spawn_bundle(RichTextBundle::new(
+                format!(
+                    "New {} high score: [wave][rainbow]{}[/rainbow][/wave]",
+                    pending.mode.name().to_lowercase(),
+                    pending.entry.score
+                ),
+                TextStyle {
+                    font: font_assets.fira_sans.clone(),
+                    font_size: theme.button_font_size,
+                    color: HIGHLIGHT_COLOR,
+                },
+                Style {
+                    margin: Rect::all(Val::Px(10.)),
+                    ..Default::default()
+                },
+            ));
+            parent
+                .spawn_bundle(NodeBundle {
+                    material: none.clone(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for index in 0..NAME_LENGTH {
+                        spawn_letter_picker(
+                            parent,
+                            'A',
+                            &font_assets,
+                            &theme,
+                            NameEntryButton::Letter(index),
+                        );
+                    }
+                });
+            spawn_button(parent, "Done", &font_assets, &theme, NameEntryButton::Done);
+        });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn enter_name(
+    mut commands: Commands,
+    mut events: EventReader<WidgetEvent>,
+    mut state: ResMut<State<GameState>>,
+    pending: Option<Res<PendingScore>>,
+    mut high_scores: ResMut<HighScores>,
+    mut view: ResMut<HighScoreView>,
+    button_query: Query<(Entity, &NameEntryButton)>,
+    letter_query: Query<(&NameEntryButton, &LetterPicker)>,
+    focused_query: Query<Entity, With<Focused>>,
+) {
+    let pending = match pending {
+        Some(pending) => pending,
+        None => return,
+    };
+    for event in events.iter() {
+        if event.change != WidgetChange::Clicked {
+            continue;
+        }
+        match button_query.get(event.entity) {
+            // confirming a letter goes on to the next one, the last one to the done button
+            Ok((_, NameEntryButton::Letter(index))) => {
+                let next = button_query
+                    .iter()
+                    .find(|(_, button)| match button {
+                        NameEntryButton::Letter(other) => *other == index + 1,
+                        NameEntryButton::Done => index + 1 == NAME_LENGTH,
+                    })
+                    .map(|(entity, _)| entity);
+                if let Some(next) = next {
+                    move_focus(&mut commands, focused_query.iter(), next);
+                }
+            }
+            Ok((_, NameEntryButton::Done)) => {
+                let mut letters = vec![' '; NAME_LENGTH];
+                for (button, picker) in letter_query.iter() {
+                    if let NameEntryButton::Letter(index) = button {
+                        letters[*index] = picker.letter;
+                    }
+                }
+                let name: String = letters.into_iter().collect();
+                let name = match name.trim() {
+                    "" => "?".to_string(),
+                    name => name.to_string(),
+                };
+                // the name stays on screen to confirm again
+                if let Err(error) = state.set(GameState::HighScores) {
+                    warn!("Not showing the high scores yet: {:?}", error);
+                    return;
+                }
+                let rank = high_scores.insert(
+                    pending.mode,
+                    HighScore {
+                        name,
+                        ..pending.entry.clone()
+                    },
+                );
+                config::save(HIGH_SCORES_FILE, &*high_scores);
+                *view = HighScoreView {
+                    mode: pending.mode,
+                    highlight: Some(rank),
+                };
+                commands.remove_resource::<PendingScore>();
+                return;
+            }
+            Err(_) => (),
+        }
+    }
+}
+
+struct HighScoresScreen;
+
+/// One line of the table, by rank
+struct ScoreRow(usize);
+
+enum HighScoresButton {
+    Mode,
+    Back,
+}
+
+fn setup_high_scores(
+    mut commands: Commands,
+    view: Res<HighScoreView>,
+    font_assets: Res<FontAssets>,
+    theme: Res<UiTheme>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let none = materials.add(Color::NONE.into());
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: none.clone(),
+            ..Default::default()
+        })
+        .insert(HighScoresScreen)
+        .with_children(|parent| {
+            spawn_selector(
+                parent,
+                "Mode",
+                Selector {
+                    options: GameMode::ALL
+                        .iter()
+                        .map(|mode| mode.name().to_string())
+                        .collect(),
+                    selected: GameMode::ALL
+                        .iter()
+                        .position(|mode| *mode == view.mode)
+                        .unwrap_or(0),
+                },
+                &font_assets,
+                &theme,
+                HighScoresButton::Mode,
+            );
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::ColumnReverse,
+                        margin: Rect::all(Val::Px(10.)),
+                        ..Default::default()
+                    },
+                    material: none.clone(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    for rank in 0..TABLE_SIZE {
+                        parent
+                            .spawn_bundle(TextBundle {
+                                text: Text::with_section(
+                                    "",
+                                    TextStyle {
+                                        // monospaced, so the columns line up
+                                        font: font_assets.share_tech_mono.clone(),
+                                        font_size: theme.row_font_size,
+                                        color: theme.text_color,
+                                    },
+                                    Default::default(),
+                                ),
+                                ..Default::default()
+                            })
+                            .insert(ScoreRow(rank));
+                    }
+                });
+            spawn_back_button(parent, "Back", &font_assets, &theme, HighScoresButton::Back);
+        });
+}
+
+fn click_high_scores_button(
+    mut events: EventReader<WidgetEvent>,
+    mut state: ResMut<State<GameState>>,
+    mut view: ResMut<HighScoreView>,
+    button_query: Query<&HighScoresButton>,
+) {
+    for event in events.iter() {
+        match (button_query.get(event.entity), &event.change) {
+            (Ok(HighScoresButton::Mode), WidgetChange::Selected(index)) => {
+                *view = HighScoreView {
+                    mode: GameMode::ALL[*index],
+                    highlight: None,
+                };
+            }
+            (Ok(HighScoresButton::Back), _) => {
+                view.highlight = None;
+                // a click and the back key can both press the button in one frame
+                if let Err(error) = state.set(GameState::Menu) {
+                    warn!("Ignoring back button: {:?}", error);
+                }
+                return;
+            }
+            _ => (),
+        }
+    }
+}
+
+fn update_table(
+    view: Res<HighScoreView>,
+    theme: Res<UiTheme>,
+    high_scores: Res<HighScores>,
+    mut row_query: Query<(&ScoreRow, &mut Text)>,
+) {
+    let entries = high_scores.entries(view.mode);
+    for (ScoreRow(rank), mut text) in row_query.iter_mut() {
+        let value = match entries.get(*rank) {
+            Some(entry) => format!(
+                "{:>2}. {:<5} {:>7} {:>8} {:>10}",
+                rank + 1,
+                entry.name,
+                entry.score,
+                format_time(entry.time),
+                entry.date
+            ),
+            None => format!("{:>2}. {:<5} {:>7} {:>8} {:>10}", rank + 1, "-", "", "", ""),
+        };
+        let color = if view.highlight == Some(*rank) {
+            HIGHLIGHT_COLOR
+        } else {
+            theme.text_color
+        };
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+        if text.sections[0].style.color != color {
+            text.sections[0].style.color = color;
+        }
+    }
+}
+
+fn cleanup<T: Send + Sync + 'static>(mut commands: Commands, query: Query<Entity, With<T>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
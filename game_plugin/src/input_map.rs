@@ -0,0 +1,501 @@
+use crate::actions::{GameControl, InputDevice};
+use crate::config;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+const INPUT_MAP_FILE: &str = "controls.toml";
+
+/// Something the player can press to trigger a [GameControl]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    /// A button on any gamepad
+    GamepadButton(GamepadButtonType),
+}
+
+impl Binding {
+    fn is_keyboard(&self) -> bool {
+        matches!(self, Binding::Key(_))
+    }
+}
+
+impl std::fmt::Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Binding::Key(key) => write!(f, "{:?}", key),
+            Binding::GamepadButton(button) => write!(f, "Pad {:?}", button),
+        }
+    }
+}
+
+/// Moves the focus through menus and presses the focused widget, see [FocusPlugin](crate::focus::FocusPlugin)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MenuControl {
+    Up,
+    Down,
+    Left,
+    Right,
+    Confirm,
+    Back,
+}
+
+impl MenuControl {
+    pub const ALL: [MenuControl; 6] = [
+        MenuControl::Up,
+        MenuControl::Down,
+        MenuControl::Left,
+        MenuControl::Right,
+        MenuControl::Confirm,
+        MenuControl::Back,
+    ];
+}
+
+/// Which keys a keyboard player uses. When the second player takes the keyboard in co-op,
+/// they get the [InputMap::player_two_keys] and the first player keeps all other keys.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyboardLayout {
+    Full,
+    PlayerOne,
+    PlayerTwo,
+}
+
+/// Maps every [GameControl] to the inputs that trigger it.
+/// It is loaded from the config directory on startup and saved whenever the controls screen is left.
+pub struct InputMap {
+    bindings: HashMap<GameControl, Vec<Binding>>,
+    /// The keys of the second player when two players share the keyboard
+    player_two_keys: HashMap<GameControl, Vec<KeyCode>>,
+    /// Menu controls can share inputs with the game controls, so they are kept apart
+    menu_bindings: HashMap<MenuControl, Vec<Binding>>,
+    /// Stick input shorter than this is ignored
+    pub gamepad_dead_zone: f32,
+    /// Aim with the mouse or the right stick instead of always firing upwards
+    pub free_aim: bool,
+}
+
+const DEFAULT_DEAD_ZONE: f32 = 0.2;
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let mut bindings = HashMap::default();
+        bindings.insert(
+            GameControl::Up,
+            vec![
+                Binding::Key(KeyCode::W),
+                Binding::Key(KeyCode::Up),
+                Binding::GamepadButton(GamepadButtonType::DPadUp),
+            ],
+        );
+        bindings.insert(
+            GameControl::Down,
+            vec![
+                Binding::Key(KeyCode::S),
+                Binding::Key(KeyCode::Down),
+                Binding::GamepadButton(GamepadButtonType::DPadDown),
+            ],
+        );
+        bindings.insert(
+            GameControl::Left,
+            vec![
+                Binding::Key(KeyCode::A),
+                Binding::Key(KeyCode::Left),
+                Binding::GamepadButton(GamepadButtonType::DPadLeft),
+            ],
+        );
+        bindings.insert(
+            GameControl::Right,
+            vec![
+                Binding::Key(KeyCode::D),
+                Binding::Key(KeyCode::Right),
+                Binding::GamepadButton(GamepadButtonType::DPadRight),
+            ],
+        );
+        bindings.insert(
+            GameControl::Shoot,
+            vec![
+                Binding::Key(KeyCode::Space),
+                Binding::GamepadButton(GamepadButtonType::South),
+                Binding::GamepadButton(GamepadButtonType::RightTrigger2),
+            ],
+        );
+        bindings.insert(
+            GameControl::SwitchWeapon,
+            vec![
+                Binding::Key(KeyCode::LShift),
+                Binding::Key(KeyCode::RShift),
+                Binding::GamepadButton(GamepadButtonType::West),
+            ],
+        );
+        bindings.insert(
+            GameControl::Dash,
+            vec![
+                Binding::Key(KeyCode::X),
+                Binding::GamepadButton(GamepadButtonType::East),
+            ],
+        );
+        bindings.insert(
+            GameControl::Pause,
+            vec![
+                Binding::Key(KeyCode::P),
+                Binding::GamepadButton(GamepadButtonType::Start),
+            ],
+        );
+        let mut player_two_keys = HashMap::default();
+        player_two_keys.insert(GameControl::Up, vec![KeyCode::Up]);
+        player_two_keys.insert(GameControl::Down, vec![KeyCode::Down]);
+        player_two_keys.insert(GameControl::Left, vec![KeyCode::Left]);
+        player_two_keys.insert(GameControl::Right, vec![KeyCode::Right]);
+        player_two_keys.insert(GameControl::Shoot, vec![KeyCode::RControl]);
+        player_two_keys.insert(GameControl::SwitchWeapon, vec![KeyCode::RShift]);
+        player_two_keys.insert(GameControl::Dash, vec![KeyCode::Slash]);
+        let mut menu_bindings = HashMap::default();
+        menu_bindings.insert(
+            MenuControl::Up,
+            vec![
+                Binding::Key(KeyCode::Up),
+                Binding::Key(KeyCode::W),
+                Binding::GamepadButton(GamepadButtonType::DPadUp),
+            ],
+        );
+        menu_bindings.insert(
+            MenuControl::Down,
+            vec![
+                Binding::Key(KeyCode::Down),
+                Binding::Key(KeyCode::S),
+                Binding::GamepadButton(GamepadButtonType::DPadDown),
+            ],
+        );
+        menu_bindings.insert(
+            MenuControl::Left,
+            vec![
+                Binding::Key(KeyCode::Left),
+                Binding::Key(KeyCode::A),
+                Binding::GamepadButton(GamepadButtonType::DPadLeft),
+            ],
+        );
+        menu_bindings.insert(
+            MenuControl::Right,
+            vec![
+                Binding::Key(KeyCode::Right),
+                Binding::Key(KeyCode::D),
+                Binding::GamepadButton(GamepadButtonType::DPadRight),
+            ],
+        );
+        menu_bindings.insert(
+            MenuControl::Confirm,
+            vec![
+                Binding::Key(KeyCode::Return),
+                Binding::Key(KeyCode::Space),
+                Binding::GamepadButton(GamepadButtonType::South),
+            ],
+        );
+        menu_bindings.insert(
+            MenuControl::Back,
+            vec![
+                Binding::Key(KeyCode::Escape),
+                Binding::Key(KeyCode::Back),
+                Binding::GamepadButton(GamepadButtonType::East),
+            ],
+        );
+        InputMap {
+            bindings,
+            player_two_keys,
+            menu_bindings,
+            gamepad_dead_zone: DEFAULT_DEAD_ZONE,
+            free_aim: false,
+        }
+    }
+}
+
+impl InputMap {
+    pub fn load() -> Self {
+        let mut input_map = InputMap::default();
+        if let Some(file) = config::load::<InputMapFile>(INPUT_MAP_FILE) {
+            for entry in file.binding {
+                input_map.bindings.insert(entry.control, entry.inputs);
+            }
+            for entry in file.player_two {
+                input_map.player_two_keys.insert(entry.control, entry.keys);
+            }
+            for entry in file.menu {
+                input_map.menu_bindings.insert(entry.control, entry.inputs);
+            }
+            input_map.gamepad_dead_zone = file.gamepad_dead_zone;
+            input_map.free_aim = file.free_aim;
+        }
+        input_map
+    }
+
+    pub fn save(&self) {
+        let mut file = InputMapFile {
+            gamepad_dead_zone: self.gamepad_dead_zone,
+            free_aim: self.free_aim,
+            binding: vec![],
+            player_two: vec![],
+            menu: vec![],
+        };
+        for control in GameControl::ALL.iter() {
+            file.binding.push(InputMapEntry {
+                control: *control,
+                inputs: self.bindings(*control).to_vec(),
+            });
+            file.player_two.push(PlayerTwoEntry {
+                control: *control,
+                keys: self.player_two_keys(*control).to_vec(),
+            });
+        }
+        for control in MenuControl::ALL.iter() {
+            file.menu.push(MenuEntry {
+                control: *control,
+                inputs: self.menu_bindings(*control).to_vec(),
+            });
+        }
+        config::save(INPUT_MAP_FILE, &file);
+    }
+
+    pub fn bindings(&self, control: GameControl) -> &[Binding] {
+        self.bindings
+            .get(&control)
+            .map(|bindings| bindings.as_slice())
+            .unwrap_or(&[])
+    }
+
+    pub fn menu_bindings(&self, control: MenuControl) -> &[Binding] {
+        self.menu_bindings
+            .get(&control)
+            .map(|bindings| bindings.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Was `control` just pressed on the keyboard or any of the `gamepads`?
+    pub fn menu_just_pressed(
+        &self,
+        control: MenuControl,
+        keyboard_input: &Input<KeyCode>,
+        gamepads: &[Gamepad],
+        gamepad_buttons: &Input<GamepadButton>,
+    ) -> bool {
+        self.menu_bindings(control)
+            .iter()
+            .any(|binding| match binding {
+                Binding::Key(key) => keyboard_input.just_pressed(*key),
+                Binding::GamepadButton(button) => gamepads
+                    .iter()
+                    .any(|gamepad| gamepad_buttons.just_pressed(GamepadButton(*gamepad, *button))),
+            })
+    }
+
+    pub fn player_two_keys(&self, control: GameControl) -> &[KeyCode] {
+        self.player_two_keys
+            .get(&control)
+            .map(|keys| keys.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Does a player with `layout` use `key`?
+    pub fn in_layout(&self, key: KeyCode, layout: KeyboardLayout) -> bool {
+        let player_two = self
+            .player_two_keys
+            .values()
+            .any(|keys| keys.contains(&key));
+        match layout {
+            KeyboardLayout::Full => true,
+            KeyboardLayout::PlayerOne => !player_two,
+            KeyboardLayout::PlayerTwo => player_two,
+        }
+    }
+
+    /// Text for the first key of `control` in `layout`
+    pub fn key_prompt(&self, control: GameControl, layout: KeyboardLayout) -> String {
+        self.keys(control, layout)
+            .first()
+            .map(|key| format!("{:?}", key))
+            .unwrap_or_else(|| "-".to_string())
+    }
+
+    fn keys(&self, control: GameControl, layout: KeyboardLayout) -> Vec<KeyCode> {
+        if layout == KeyboardLayout::PlayerTwo {
+            return self.player_two_keys(control).to_vec();
+        }
+        self.bindings(control)
+            .iter()
+            .filter_map(|binding| match binding {
+                Binding::Key(key) if self.in_layout(*key, layout) => Some(*key),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The control `binding` is currently bound to, if any
+    pub fn control_for(&self, binding: Binding) -> Option<GameControl> {
+        GameControl::ALL
+            .iter()
+            .copied()
+            .find(|control| self.bindings(*control).contains(&binding))
+    }
+
+    /// Text for the first binding of `control` on the given device, e.g. "Space" or "Pad South"
+    pub fn prompt(&self, control: GameControl, device: InputDevice) -> String {
+        if device == InputDevice::Touch {
+            // the on-screen buttons are labeled with the control
+            return control.name().to_string();
+        }
+        let keyboard = device == InputDevice::Keyboard;
+        self.bindings(control)
+            .iter()
+            .find(|binding| binding.is_keyboard() == keyboard)
+            .map(|binding| binding.to_string())
+            .unwrap_or_else(|| "-".to_string())
+    }
+
+    /// Text for the first gamepad button of `control`, e.g. "Pad Start"
+    pub fn gamepad_prompt(&self, control: GameControl) -> String {
+        self.bindings(control)
+            .iter()
+            .find(|binding| !binding.is_keyboard())
+            .map(|binding| binding.to_string())
+            .unwrap_or_else(|| "-".to_string())
+    }
+
+    /// Replaces the bindings of `control` on the same device as `binding` with `binding`.
+    /// If other controls were using `binding`, it is taken away from them. A key is also taken away
+    /// from the [InputMap::player_two_keys], so it never belongs to both players.
+    /// Returns the controls that lost `binding` with the layout of the player they belong to.
+    pub fn rebind(
+        &mut self,
+        control: GameControl,
+        binding: Binding,
+    ) -> Vec<(GameControl, KeyboardLayout)> {
+        let mut conflicts = Vec::new();
+        if let Some(other) = self.control_for(binding).filter(|other| *other != control) {
+            if let Some(bindings) = self.bindings.get_mut(&other) {
+                bindings.retain(|existing| *existing != binding);
+            }
+            conflicts.push((other, KeyboardLayout::PlayerOne));
+        }
+        if let Binding::Key(key) = binding {
+            for (other, keys) in self.player_two_keys.iter_mut() {
+                if keys.contains(&key) {
+                    keys.retain(|existing| *existing != key);
+                    conflicts.push((*other, KeyboardLayout::PlayerTwo));
+                }
+            }
+        }
+        let bindings = self.bindings.entry(control).or_insert_with(Vec::new);
+        bindings.retain(|existing| existing.is_keyboard() != binding.is_keyboard());
+        bindings.push(binding);
+        conflicts
+    }
+
+    pub fn pressed(
+        &self,
+        control: GameControl,
+        layout: KeyboardLayout,
+        keyboard_input: &Input<KeyCode>,
+    ) -> bool {
+        self.keys(control, layout)
+            .iter()
+            .any(|key| keyboard_input.pressed(*key))
+    }
+
+    pub fn just_pressed(
+        &self,
+        control: GameControl,
+        layout: KeyboardLayout,
+        keyboard_input: &Input<KeyCode>,
+    ) -> bool {
+        self.keys(control, layout)
+            .iter()
+            .any(|key| keyboard_input.just_pressed(*key))
+    }
+
+    pub fn just_released(
+        &self,
+        control: GameControl,
+        layout: KeyboardLayout,
+        keyboard_input: &Input<KeyCode>,
+    ) -> bool {
+        self.keys(control, layout)
+            .iter()
+            .any(|key| keyboard_input.just_released(*key))
+    }
+
+    pub fn gamepad_pressed(
+        &self,
+        control: GameControl,
+        gamepad: Gamepad,
+        gamepad_buttons: &Input<GamepadButton>,
+    ) -> bool {
+        self.bindings(control).iter().any(|binding| match binding {
+            Binding::GamepadButton(button) => {
+                gamepad_buttons.pressed(GamepadButton(gamepad, *button))
+            }
+            _ => false,
+        })
+    }
+
+    pub fn gamepad_just_pressed(
+        &self,
+        control: GameControl,
+        gamepad: Gamepad,
+        gamepad_buttons: &Input<GamepadButton>,
+    ) -> bool {
+        self.bindings(control).iter().any(|binding| match binding {
+            Binding::GamepadButton(button) => {
+                gamepad_buttons.just_pressed(GamepadButton(gamepad, *button))
+            }
+            _ => false,
+        })
+    }
+}
+
+// The layout of the config file:
+// gamepad_dead_zone = 0.2
+// free_aim = false
+//
+// [[binding]]
+// control = "Up"
+// inputs = [{ Key = "W" }, { Key = "Up" }, { GamepadButton = "DPadUp" }]
+//
+// [[player_two]]
+// control = "Up"
+// keys = ["Up"]
+//
+// [[menu]]
+// control = "Confirm"
+// inputs = [{ Key = "Return" }, { GamepadButton = "South" }]
+#[derive(Serialize, Deserialize)]
+struct InputMapFile {
+    #[serde(default = "default_dead_zone")]
+    gamepad_dead_zone: f32,
+    #[serde(default)]
+    free_aim: bool,
+    binding: Vec<InputMapEntry>,
+    #[serde(default)]
+    player_two: Vec<PlayerTwoEntry>,
+    #[serde(default)]
+    menu: Vec<MenuEntry>,
+}
+
+fn default_dead_zone() -> f32 {
+    DEFAULT_DEAD_ZONE
+}
+
+#[derive(Serialize, Deserialize)]
+struct InputMapEntry {
+    control: GameControl,
+    inputs: Vec<Binding>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PlayerTwoEntry {
+    control: GameControl,
+    keys: Vec<KeyCode>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct MenuEntry {
+    control: MenuControl,
+    inputs: Vec<Binding>,
+}
@@ -0,0 +1,219 @@
+use crate::player::MainCamera;
+use crate::settings::Settings;
+use crate::simulation::SimulationStage;
+use crate::GameState;
+use bevy::prelude::*;
+use rand::Rng;
+use std::collections::HashMap;
+
+pub struct JuicePlugin;
+
+/// This plugin makes hits feel like hits: it flashes damaged sprites, shakes the camera and
+/// briefly freezes gameplay on big hits. What happens for each [HitKind] is set in [JuiceConfig].
+impl Plugin for JuicePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<HitEvent>()
+            .init_resource::<JuiceConfig>()
+            .init_resource::<HitStop>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(react_to_hits.system().label("react_to_hits"))
+                    .with_system(update_hit_flash.system().after("react_to_hits"))
+                    .with_system(tick_hit_stop.system().after("react_to_hits")),
+            )
+            // the shake goes on top of the interpolated camera position
+            .add_system_to_stage(
+                SimulationStage::Interpolate,
+                shake_camera
+                    .system()
+                    .label("shake_camera")
+                    .after("interpolate"),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing).with_system(reset_hit_stop.system()),
+            );
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HitKind {
+    EnemyHit,
+    // the target of this hit is already despawned, so it should not flash
+    EnemyKilled,
+    /// A player ship lost a life
+    PlayerHit,
+}
+
+pub struct HitEvent {
+    pub target: Entity,
+    pub kind: HitKind,
+    /// Where the hit landed
+    pub position: Vec3,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct HitFeedback {
+    /// For how many frames the target is drawn white
+    pub flash_frames: u32,
+    /// Added to the camera trauma, which is clamped to 1
+    pub trauma: f32,
+    /// How long gameplay is frozen
+    pub hit_stop_seconds: f32,
+}
+
+pub struct JuiceConfig {
+    pub feedback: HashMap<HitKind, HitFeedback>,
+    /// Camera offset in pixels at full trauma
+    pub max_shake_offset: f32,
+    /// Camera rotation in radians at full trauma
+    pub max_shake_angle: f32,
+    /// Trauma lost per second
+    pub trauma_decay: f32,
+}
+
+impl Default for JuiceConfig {
+    fn default() -> Self {
+        let mut feedback = HashMap::default();
+        feedback.insert(
+            HitKind::EnemyHit,
+            HitFeedback {
+                flash_frames: 3,
+                trauma: 0.2,
+                hit_stop_seconds: 0.,
+            },
+        );
+        feedback.insert(
+            HitKind::EnemyKilled,
+            HitFeedback {
+                flash_frames: 0,
+                trauma: 0.5,
+                hit_stop_seconds: 0.08,
+            },
+        );
+        feedback.insert(
+            HitKind::PlayerHit,
+            HitFeedback {
+                flash_frames: 6,
+                trauma: 0.7,
+                hit_stop_seconds: 0.12,
+            },
+        );
+        JuiceConfig {
+            feedback,
+            max_shake_offset: 12.,
+            max_shake_angle: 0.05,
+            trauma_decay: 1.5,
+        }
+    }
+}
+
+/// While active, gameplay systems should skip their update
+#[derive(Default)]
+pub struct HitStop {
+    remaining: f32,
+}
+
+impl HitStop {
+    pub fn is_active(&self) -> bool {
+        self.remaining > 0.
+    }
+}
+
+struct HitFlash {
+    frames_left: u32,
+    original_color: Color,
+}
+
+/// Sprite colors are multiplied with the texture, so a very bright tint turns every visible pixel white
+const FLASH_COLOR: Color = Color::rgb_linear(100., 100., 100.);
+
+/// Trauma based screen shake, see https://www.youtube.com/watch?v=tu-Qe66AvtY
+#[derive(Default)]
+pub struct CameraShake {
+    trauma: f32,
+    offset: Vec3,
+    angle: f32,
+}
+
+fn react_to_hits(
+    mut commands: Commands,
+    config: Res<JuiceConfig>,
+    mut hit_stop: ResMut<HitStop>,
+    mut hits: EventReader<HitEvent>,
+    mut sprites: Query<(&mut TextureAtlasSprite, Option<&mut HitFlash>)>,
+    mut cameras: Query<&mut CameraShake, With<MainCamera>>,
+) {
+    for hit in hits.iter() {
+        let feedback = match config.feedback.get(&hit.kind) {
+            Some(feedback) => feedback,
+            None => continue,
+        };
+        if feedback.flash_frames > 0 {
+            if let Ok((mut sprite, flash)) = sprites.get_mut(hit.target) {
+                match flash {
+                    Some(mut flash) => flash.frames_left = feedback.flash_frames,
+                    None => {
+                        commands.entity(hit.target).insert(HitFlash {
+                            frames_left: feedback.flash_frames,
+                            original_color: sprite.color,
+                        });
+                    }
+                }
+                sprite.color = FLASH_COLOR;
+            }
+        }
+        for mut shake in cameras.iter_mut() {
+            shake.trauma = (shake.trauma + feedback.trauma).min(1.);
+        }
+        hit_stop.remaining = hit_stop.remaining.max(feedback.hit_stop_seconds);
+    }
+}
+
+fn update_hit_flash(
+    mut commands: Commands,
+    mut query: Query<(Entity, &mut HitFlash, &mut TextureAtlasSprite)>,
+) {
+    for (entity, mut flash, mut sprite) in query.iter_mut() {
+        if flash.frames_left == 0 {
+            sprite.color = flash.original_color;
+            commands.entity(entity).remove::<HitFlash>();
+        } else {
+            flash.frames_left -= 1;
+        }
+    }
+}
+
+fn shake_camera(
+    time: Res<Time>,
+    state: Res<State<GameState>>,
+    config: Res<JuiceConfig>,
+    settings: Res<Settings>,
+    mut query: Query<(&mut CameraShake, &mut Transform), With<MainCamera>>,
+) {
+    let mut rng = rand::thread_rng();
+    for (mut shake, mut transform) in query.iter_mut() {
+        // keep the last offset while paused
+        if *state.current() == GameState::Playing {
+            shake.trauma = (shake.trauma - config.trauma_decay * time.delta_seconds()).max(0.);
+            // squaring the trauma makes small hits subtle and big hits violent
+            let strength = shake.trauma * shake.trauma * settings.screen_shake;
+            shake.offset = Vec3::new(
+                config.max_shake_offset * strength * rng.gen_range(-1.0..1.0),
+                config.max_shake_offset * strength * rng.gen_range(-1.0..1.0),
+                0.,
+            );
+            shake.angle = config.max_shake_angle * strength * rng.gen_range(-1.0..1.0);
+        }
+
+        transform.translation += shake.offset;
+        transform.rotation = Quat::from_rotation_z(shake.angle);
+    }
+}
+
+fn tick_hit_stop(time: Res<Time>, mut hit_stop: ResMut<HitStop>) {
+    hit_stop.remaining = (hit_stop.remaining - time.delta_seconds()).max(0.);
+}
+
+fn reset_hit_stop(mut hit_stop: ResMut<HitStop>) {
+    hit_stop.remaining = 0.;
+}
@@ -0,0 +1,201 @@
+use crate::dialogue::DialogueScript;
+use crate::tilemap::TileMap;
+use anyhow::bail;
+use bevy::asset::{AssetLoader, AssetPath, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::{BoxedFuture, HashMap};
+use serde::Deserialize;
+
+pub struct LevelPlugin;
+
+/// This plugin registers the [LevelData] asset, so levels can be loaded from `.level.ron` files
+impl Plugin for LevelPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_asset::<LevelData>()
+            .init_asset_loader::<LevelDataLoader>();
+    }
+}
+
+/// Everything needed to build a level, see `assets/levels` for examples
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "abda65dd-2f9d-4898-bc77-76f6185c93ac"]
+pub struct LevelData {
+    /// Background layers, drawn back to front
+    #[serde(default)]
+    pub background: Vec<BackgroundLayer>,
+    #[serde(default)]
+    pub tilemap: Option<TileMapSettings>,
+    /// Loaded from [TileMapSettings::path] together with the level
+    #[serde(skip)]
+    pub tiles: Option<TileMap>,
+    /// Conversations and when they start
+    #[serde(default)]
+    pub dialogues: Vec<DialogueSettings>,
+    /// Portraits of the conversations by path, loaded as dependencies of the level
+    #[serde(skip)]
+    pub portraits: HashMap<String, Handle<Texture>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TileMapSettings {
+    /// Path of the CSV map, relative to the assets directory
+    pub path: String,
+    pub scale: f32,
+    /// Camera speed in pixels per second
+    pub scroll_speed: f32,
+    /// Sprite sheet indices of tiles that block the player and bullets
+    #[serde(default)]
+    pub solid: Vec<u32>,
+    /// Points of the map the run timer records a split at, bottom to top.
+    /// The last one ends the run.
+    #[serde(default)]
+    pub splits: Vec<SplitSettings>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SplitSettings {
+    pub name: String,
+    /// Map row counted from the bottom, the split is recorded once the top of the screen reaches it
+    pub row: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DialogueSettings {
+    /// Path of the `.dialogue.ron` script, relative to the assets directory.
+    /// It also names the conversation in [StartDialogue](crate::dialogue::StartDialogue) and other dialogue events.
+    pub path: String,
+    pub trigger: DialogueTrigger,
+    /// Loaded from [DialogueSettings::path] together with the level
+    #[serde(skip)]
+    pub script: Option<DialogueScript>,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum DialogueTrigger {
+    /// When the run starts
+    Start,
+    /// When the run timer records the split with this name
+    Split(String),
+    /// When the conversation with this path ends
+    After(String),
+    /// When the player picks a choice in a line of the conversation with this path
+    Choice {
+        dialogue: String,
+        line: usize,
+        choice: usize,
+    },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub enum BackgroundLayer {
+    /// A repeating pattern of tiles from the main sprite sheet
+    Tiles {
+        /// Scroll speed in pixels per second
+        speed: f32,
+        scale: f32,
+        /// Rows of sprite sheet indices, top to bottom
+        pattern: Vec<Vec<u32>>,
+        #[serde(default = "default_tint")]
+        tint: (f32, f32, f32),
+    },
+    /// Loose sprites from the main sprite sheet, repeating every `height` pixels
+    Sprites {
+        speed: f32,
+        scale: f32,
+        height: f32,
+        sprites: Vec<BackgroundSprite>,
+        #[serde(default = "default_tint")]
+        tint: (f32, f32, f32),
+    },
+    /// Randomly placed stars
+    Starfield {
+        speed: f32,
+        count: u32,
+        #[serde(default = "default_tint")]
+        tint: (f32, f32, f32),
+    },
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BackgroundSprite {
+    pub index: u32,
+    pub x: f32,
+    pub y: f32,
+}
+
+impl BackgroundLayer {
+    /// Layers that could not be drawn are rejected when the level loads
+    fn validate(&self) -> anyhow::Result<()> {
+        match self {
+            BackgroundLayer::Tiles { scale, pattern, .. } => {
+                if *scale <= 0. {
+                    bail!("the scale of a tiles layer has to be positive");
+                }
+                if pattern.is_empty() || pattern.iter().any(Vec::is_empty) {
+                    bail!(
+                        "the pattern of a tiles layer needs at least one row, and every row a tile"
+                    );
+                }
+            }
+            BackgroundLayer::Sprites { scale, height, .. } => {
+                if *scale <= 0. || *height <= 0. {
+                    bail!("the scale and height of a sprites layer have to be positive");
+                }
+            }
+            BackgroundLayer::Starfield { .. } => (),
+        }
+        Ok(())
+    }
+}
+
+fn default_tint() -> (f32, f32, f32) {
+    (1., 1., 1.)
+}
+
+#[derive(Default)]
+pub struct LevelDataLoader;
+
+impl AssetLoader for LevelDataLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let mut level: LevelData = ron::de::from_bytes(bytes)?;
+            for layer in level.background.iter() {
+                layer.validate()?;
+            }
+            if let Some(tilemap) = &level.tilemap {
+                let map = load_context.read_asset_bytes(&tilemap.path).await?;
+                level.tiles = Some(TileMap::from_csv(std::str::from_utf8(&map)?)?);
+            }
+            let mut dependencies = vec![];
+            for dialogue in level.dialogues.iter_mut() {
+                let script = load_context.read_asset_bytes(&dialogue.path).await?;
+                let script: DialogueScript = ron::de::from_bytes(&script)?;
+                for path in script
+                    .lines
+                    .iter()
+                    .filter_map(|line| line.portrait.as_ref())
+                {
+                    if !level.portraits.contains_key(path) {
+                        let asset_path = AssetPath::from(path.as_str()).to_owned();
+                        level
+                            .portraits
+                            .insert(path.clone(), load_context.get_handle(asset_path.clone()));
+                        dependencies.push(asset_path);
+                    }
+                }
+                dialogue.script = Some(script);
+            }
+            load_context.set_default_asset(LoadedAsset::new(level).with_dependencies(dependencies));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["level.ron"]
+    }
+}
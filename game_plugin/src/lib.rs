@@ -1,27 +1,86 @@
 mod actions;
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+mod asset_check;
 mod audio;
+mod background;
+mod config;
+mod controls_menu;
+#[cfg(feature = "debug_overlay")]
+mod debug_overlay;
+mod dialogue;
 mod enemy;
-mod fps_counter;
+mod focus;
+mod high_scores;
+mod input_map;
+mod juice;
+mod level;
 mod loading;
+mod loading_screen;
 mod menu;
+mod netcode;
 mod overlap;
+mod particles;
+mod party;
+mod pause;
 mod player;
-mod seconds_timer;
+mod replay;
+mod rich_text;
+mod rng;
+mod run_timer;
+mod score;
+mod settings;
+mod settings_menu;
+mod simulation;
+mod tilemap;
+mod touch_controls;
+mod tuning;
+mod virtual_resolution;
+mod widgets;
 
 use crate::actions::ActionsPlugin;
+#[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+use crate::asset_check::AssetCheckPlugin;
 use crate::audio::InternalAudioPlugin;
+use crate::background::BackgroundPlugin;
+use crate::controls_menu::ControlsMenuPlugin;
+#[cfg(feature = "debug_overlay")]
+use crate::debug_overlay::DebugOverlayPlugin;
+use crate::dialogue::DialoguePlugin;
 use crate::enemy::EnemyPlugin;
+use crate::focus::FocusPlugin;
+use crate::high_scores::HighScoresPlugin;
+use crate::juice::JuicePlugin;
+use crate::level::LevelPlugin;
 use crate::loading::LoadingPlugin;
+use crate::loading_screen::LoadingScreenPlugin;
 use crate::menu::MenuPlugin;
+use crate::netcode::NetcodePlugin;
+use crate::overlap::OverlapPlugin;
+use crate::particles::ParticlesPlugin;
+use crate::party::PartyPlugin;
+use crate::pause::PausePlugin;
 use crate::player::PlayerPlugin;
-
-use crate::fps_counter::FPSCounterPlugin;
-use crate::seconds_timer::SecondsTimerPlugin;
+use crate::replay::ReplayPlugin;
+use crate::rich_text::RichTextPlugin;
+use crate::run_timer::RunTimerPlugin;
+use crate::score::ScorePlugin;
+use crate::settings::SettingsPlugin;
+use crate::settings_menu::SettingsMenuPlugin;
+use crate::simulation::SimulationPlugin;
+use crate::tilemap::TileMapPlugin;
+use crate::touch_controls::TouchControlsPlugin;
+use crate::tuning::TuningPlugin;
+use crate::virtual_resolution::VirtualResolutionPlugin;
+use crate::widgets::WidgetsPlugin;
 use bevy::app::AppBuilder;
+#[cfg(all(debug_assertions, not(feature = "debug_overlay")))]
+use bevy::diagnostic::FrameTimeDiagnosticsPlugin;
 #[cfg(debug_assertions)]
-use bevy::diagnostic::{FrameTimeDiagnosticsPlugin, LogDiagnosticsPlugin};
+use bevy::diagnostic::LogDiagnosticsPlugin;
 use bevy::prelude::*;
 
+pub use crate::settings::Settings;
+
 // This example game uses States to separate logic
 // See https://bevy-cheatbook.github.io/programming/states.html
 // Or https://github.com/bevyengine/bevy/blob/main/examples/ecs/state.rs
@@ -29,10 +88,24 @@ use bevy::prelude::*;
 enum GameState {
     // During the loading State the LoadingPlugin will load our assets
     Loading,
+    // Pushed on top of Loading when an asset could not be loaded
+    LoadingFailed,
     // During this State the actual game logic is executed
     Playing,
     // Here the menu is drawn and waiting for player interaction
     Menu,
+    // Here the controls can be rebound
+    Controls,
+    // Here audio, video and gameplay settings can be changed
+    Settings,
+    // Pushed on top of Playing while the game is paused
+    Paused,
+    // After a run that made it into the high scores
+    NameEntry,
+    // Here the high score tables are shown
+    HighScores,
+    // Pushed on top of Playing while a conversation is shown
+    Dialogue,
 }
 
 pub struct GamePlugin;
@@ -40,20 +113,47 @@ pub struct GamePlugin;
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut AppBuilder) {
         app.add_state(GameState::Loading)
+            // the simulation stages need to exist before other plugins add systems to them
+            .add_plugin(SimulationPlugin)
+            .add_plugin(LevelPlugin)
+            .add_plugin(TuningPlugin)
             .add_plugin(LoadingPlugin)
+            .add_plugin(WidgetsPlugin)
+            .add_plugin(RichTextPlugin)
+            .add_plugin(LoadingScreenPlugin)
+            .add_plugin(FocusPlugin)
             .add_plugin(MenuPlugin)
+            .add_plugin(ControlsMenuPlugin)
+            .add_plugin(SettingsPlugin)
+            .add_plugin(SettingsMenuPlugin)
+            .add_plugin(VirtualResolutionPlugin)
             .add_plugin(ActionsPlugin)
+            .add_plugin(PartyPlugin)
+            .add_plugin(TouchControlsPlugin)
+            .add_plugin(ReplayPlugin)
+            .add_plugin(NetcodePlugin)
+            .add_plugin(PausePlugin)
             .add_plugin(InternalAudioPlugin)
             .add_plugin(PlayerPlugin)
             .add_plugin(EnemyPlugin)
-            .add_plugin(FPSCounterPlugin)
-            .add_plugin(SecondsTimerPlugin)
-            .add_system(bevy::input::system::exit_on_esc_system.system());
+            .add_plugin(BackgroundPlugin)
+            .add_plugin(TileMapPlugin)
+            .add_plugin(OverlapPlugin)
+            .add_plugin(JuicePlugin)
+            .add_plugin(ParticlesPlugin)
+            .add_plugin(RunTimerPlugin)
+            .add_plugin(ScorePlugin)
+            .add_plugin(HighScoresPlugin)
+            .add_plugin(DialoguePlugin);
 
+        // the debug overlay adds the frame time diagnostics itself
+        #[cfg(all(debug_assertions, not(feature = "debug_overlay")))]
+        app.add_plugin(FrameTimeDiagnosticsPlugin::default());
         #[cfg(debug_assertions)]
-        {
-            app.add_plugin(FrameTimeDiagnosticsPlugin::default())
-                .add_plugin(LogDiagnosticsPlugin::default());
-        }
+        app.add_plugin(LogDiagnosticsPlugin::default());
+        #[cfg(feature = "debug_overlay")]
+        app.add_plugin(DebugOverlayPlugin);
+        #[cfg(all(debug_assertions, not(target_arch = "wasm32")))]
+        app.add_plugin(AssetCheckPlugin);
     }
 }
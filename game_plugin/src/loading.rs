@@ -1,3 +1,5 @@
+use crate::level::LevelData;
+use crate::tuning::PlayerTuning;
 use crate::GameState;
 use bevy::prelude::*;
 use bevy_asset_loader::{AssetCollection, AssetLoader};
@@ -14,11 +16,23 @@ impl Plugin for LoadingPlugin {
             .with_collection::<FontAssets>()
             .with_collection::<AudioAssets>()
             .with_collection::<TextureAssets>()
+            .with_collection::<LevelAssets>()
+            .with_collection::<TuningAssets>()
             .init_resource::<TextureAtlases>()
             .build(app);
     }
 }
 
+/// Handles of every asset in the collections the [LoadingPlugin] loads, to follow their progress
+pub fn collection_handles(asset_server: &Res<AssetServer>) -> Vec<HandleUntyped> {
+    let mut handles = FontAssets::load(asset_server);
+    handles.extend(AudioAssets::load(asset_server));
+    handles.extend(TextureAssets::load(asset_server));
+    handles.extend(LevelAssets::load(asset_server));
+    handles.extend(TuningAssets::load(asset_server));
+    handles
+}
+
 // the following asset collections will be loaded during the State `GameState::Loading`
 // when done loading, they will be inserted as resources (see https://github.com/NiklasEi/bevy_asset_loader)
 
@@ -26,6 +40,8 @@ impl Plugin for LoadingPlugin {
 pub struct FontAssets {
     #[asset(path = "fonts/FiraSans-Bold.ttf")]
     pub fira_sans: Handle<Font>,
+    #[asset(path = "fonts/ShareTechMono-Regular.ttf")]
+    pub share_tech_mono: Handle<Font>,
 }
 
 #[derive(AssetCollection)]
@@ -42,6 +58,18 @@ pub struct TextureAssets {
     pub texture_tileset: Handle<Texture>,
 }
 
+#[derive(AssetCollection)]
+pub struct LevelAssets {
+    #[asset(path = "levels/level_1.level.ron")]
+    pub level_1: Handle<LevelData>,
+}
+
+#[derive(AssetCollection)]
+pub struct TuningAssets {
+    #[asset(path = "data/player.tuning.ron")]
+    pub player: Handle<PlayerTuning>,
+}
+
 pub struct TextureAtlases {
     pub main_sprite_sheet: Handle<TextureAtlas>,
 }
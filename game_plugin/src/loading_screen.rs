@@ -0,0 +1,242 @@
+use crate::actions::ConnectedGamepads;
+use crate::input_map::{InputMap, MenuControl};
+use crate::loading::{collection_handles, FontAssets};
+use crate::widgets::UiTheme;
+use crate::GameState;
+use bevy::app::AppExit;
+use bevy::asset::LoadState;
+use bevy::prelude::*;
+use bevy_asset_loader::AssetCollection;
+
+pub struct LoadingScreenPlugin;
+
+/// This plugin shows a progress bar while the [LoadingPlugin](crate::loading::LoadingPlugin) loads the assets.
+/// If an asset fails to load, `GameState::LoadingFailed` is pushed on top of `GameState::Loading`
+/// and the paths of all missing assets are listed.
+impl Plugin for LoadingScreenPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_enter(GameState::Loading).with_system(setup_loading_screen.system()),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Loading).with_system(update_progress.system()),
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Loading).with_system(cleanup_loading_screen.system()),
+        )
+        .add_system_set(
+            SystemSet::on_pause(GameState::Loading).with_system(cleanup_loading_screen.system()),
+        )
+        .add_system_set(
+            SystemSet::on_enter(GameState::LoadingFailed).with_system(show_failed_assets.system()),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::LoadingFailed).with_system(quit_on_back.system()),
+        );
+    }
+}
+
+struct LoadingProgress {
+    handles: Vec<HandleUntyped>,
+}
+
+struct LoadingScreen;
+
+struct ProgressFill;
+
+/// The [FontAssets] are not available as a resource before they are loaded,
+/// so this picks the first font from the collection that did not fail to load
+fn screen_font(asset_server: &Res<AssetServer>) -> Handle<Font> {
+    let fonts = FontAssets::create(asset_server);
+    if asset_server.get_load_state(&fonts.fira_sans) == LoadState::Failed {
+        fonts.share_tech_mono
+    } else {
+        fonts.fira_sans
+    }
+}
+
+fn setup_loading_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    theme: Res<UiTheme>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands.insert_resource(LoadingProgress {
+        handles: collection_handles(&asset_server),
+    });
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(LoadingScreen)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle {
+                style: Style {
+                    margin: Rect::all(Val::Px(10.)),
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    "Loading",
+                    TextStyle {
+                        font: screen_font(&asset_server),
+                        font_size: theme.button_font_size,
+                        color: theme.text_color,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(theme.row_size.x), Val::Px(theme.row_size.y)),
+                        ..Default::default()
+                    },
+                    material: theme.normal.clone(),
+                    ..Default::default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(0.), Val::Percent(100.)),
+                                ..Default::default()
+                            },
+                            material: theme.fill.clone(),
+                            ..Default::default()
+                        })
+                        .insert(ProgressFill);
+                });
+        });
+}
+
+fn update_progress(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    progress: Option<Res<LoadingProgress>>,
+    mut state: ResMut<State<GameState>>,
+    mut fill_query: Query<&mut Style, With<ProgressFill>>,
+) {
+    let progress = match progress {
+        Some(progress) => progress,
+        None => return,
+    };
+    let load_states: Vec<LoadState> = progress
+        .handles
+        .iter()
+        .map(|handle| asset_server.get_load_state(handle))
+        .collect();
+    let failed: Vec<String> = progress
+        .handles
+        .iter()
+        .zip(load_states.iter())
+        .filter(|(_, load_state)| **load_state == LoadState::Failed)
+        .map(|(handle, _)| {
+            asset_server
+                .get_handle_path(handle)
+                .map_or("unknown asset".to_string(), |path| {
+                    path.path().display().to_string()
+                })
+        })
+        .collect();
+    if !failed.is_empty() {
+        commands.insert_resource(FailedAssets(failed));
+        // the next frame tries again
+        if let Err(error) = state.push(GameState::LoadingFailed) {
+            warn!("Delaying the failed assets screen: {:?}", error);
+        }
+        return;
+    }
+
+    let loaded = load_states
+        .iter()
+        .filter(|load_state| **load_state == LoadState::Loaded)
+        .count();
+    let fraction = loaded as f32 / progress.handles.len().max(1) as f32;
+    for mut style in fill_query.iter_mut() {
+        style.size.width = Val::Percent(fraction * 100.);
+    }
+}
+
+fn cleanup_loading_screen(
+    mut commands: Commands,
+    screen_query: Query<Entity, With<LoadingScreen>>,
+) {
+    commands.remove_resource::<LoadingProgress>();
+    for entity in screen_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+/// Paths of the assets that could not be loaded
+struct FailedAssets(Vec<String>);
+
+fn show_failed_assets(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    failed: Res<FailedAssets>,
+    theme: Res<UiTheme>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let font = screen_font(&asset_server);
+    let lines = std::iter::once(("Some assets could not be loaded:".to_string(), 30.))
+        .chain(failed.0.iter().map(|path| (path.clone(), 25.)))
+        .chain(std::iter::once(("Press Escape to quit".to_string(), 30.)));
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .with_children(|parent| {
+            for (line, font_size) in lines {
+                parent.spawn_bundle(TextBundle {
+                    style: Style {
+                        margin: Rect::all(Val::Px(5.)),
+                        ..Default::default()
+                    },
+                    text: Text::with_section(
+                        line,
+                        TextStyle {
+                            font: font.clone(),
+                            font_size,
+                            color: theme.text_color,
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                });
+            }
+        });
+}
+
+fn quit_on_back(
+    input_map: Res<InputMap>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepads: Res<ConnectedGamepads>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut exit: EventWriter<AppExit>,
+) {
+    if input_map.menu_just_pressed(
+        MenuControl::Back,
+        &keyboard_input,
+        &gamepads.0,
+        &gamepad_buttons,
+    ) {
+        exit.send(AppExit);
+    }
+}
@@ -1,103 +1,100 @@
 use crate::loading::FontAssets;
+use crate::widgets::{spawn_back_button, spawn_button, OnClick, UiTheme};
 use crate::GameState;
+use bevy::app::{AppExit, Events};
 use bevy::prelude::*;
 
 pub struct MenuPlugin;
 
-/// This plugin is responsible for the game menu (containing only one button...)
-/// The menu is only drawn during the State `GameState::Menu` and is removed when that state is exited
+/// This plugin is responsible for the game menu
+/// The menu is only drawn during the State `GameState::Menu` and is removed when that state is exited.
+/// Going back in the menu quits the game, except in the browser.
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.init_resource::<ButtonMaterials>()
+        app.add_startup_system(spawn_ui_camera.system())
             .add_system_set(SystemSet::on_enter(GameState::Menu).with_system(setup_menu.system()))
-            .add_system_set(
-                SystemSet::on_update(GameState::Menu).with_system(click_play_button.system()),
-            );
+            .add_system_set(SystemSet::on_exit(GameState::Menu).with_system(cleanup_menu.system()));
     }
 }
 
-struct ButtonMaterials {
-    normal: Handle<ColorMaterial>,
-    hovered: Handle<ColorMaterial>,
-}
+struct Menu;
 
-impl FromWorld for ButtonMaterials {
-    fn from_world(world: &mut World) -> Self {
-        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
-        ButtonMaterials {
-            normal: materials.add(Color::rgb(0.15, 0.15, 0.15).into()),
-            hovered: materials.add(Color::rgb(0.25, 0.25, 0.25).into()),
-        }
-    }
+fn spawn_ui_camera(mut commands: Commands) {
+    commands.spawn_bundle(UiCameraBundle::default());
 }
 
-struct PlayButton;
-
 fn setup_menu(
     mut commands: Commands,
     font_assets: Res<FontAssets>,
-    button_materials: Res<ButtonMaterials>,
+    theme: Res<UiTheme>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
-    commands.spawn_bundle(UiCameraBundle::default());
     commands
-        .spawn_bundle(ButtonBundle {
+        .spawn_bundle(NodeBundle {
             style: Style {
-                size: Size::new(Val::Px(120.0), Val::Px(50.0)),
-                margin: Rect::all(Val::Auto),
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                flex_direction: FlexDirection::ColumnReverse,
                 justify_content: JustifyContent::Center,
                 align_items: AlignItems::Center,
                 ..Default::default()
             },
-            material: button_materials.normal.clone(),
+            material: materials.add(Color::NONE.into()),
             ..Default::default()
         })
-        .insert(PlayButton)
+        .insert(Menu)
         .with_children(|parent| {
-            parent.spawn_bundle(TextBundle {
-                text: Text {
-                    sections: vec![TextSection {
-                        value: "Play".to_string(),
-                        style: TextStyle {
-                            font: font_assets.fira_sans.clone(),
-                            font_size: 40.0,
-                            color: Color::rgb(0.9, 0.9, 0.9),
-                        },
-                    }],
-                    alignment: Default::default(),
-                },
-                ..Default::default()
-            });
+            spawn_button(
+                parent,
+                "Play",
+                &font_assets,
+                &theme,
+                OnClick(|world| set_state(world, GameState::Playing)),
+            );
+            spawn_button(
+                parent,
+                "Controls",
+                &font_assets,
+                &theme,
+                OnClick(|world| set_state(world, GameState::Controls)),
+            );
+            spawn_button(
+                parent,
+                "Settings",
+                &font_assets,
+                &theme,
+                OnClick(|world| set_state(world, GameState::Settings)),
+            );
+            spawn_button(
+                parent,
+                "High scores",
+                &font_assets,
+                &theme,
+                OnClick(|world| set_state(world, GameState::HighScores)),
+            );
+            #[cfg(not(target_arch = "wasm32"))]
+            spawn_back_button(
+                parent,
+                "Quit",
+                &font_assets,
+                &theme,
+                OnClick(|world| {
+                    let mut exit = world.get_resource_mut::<Events<AppExit>>().unwrap();
+                    exit.send(AppExit);
+                }),
+            );
         });
 }
 
-type ButtonInteraction<'a> = (
-    Entity,
-    &'a Interaction,
-    &'a mut Handle<ColorMaterial>,
-    &'a Children,
-);
+fn set_state(world: &mut World, state: GameState) {
+    let mut current = world.get_resource_mut::<State<GameState>>().unwrap();
+    // another transition could already be queued this frame, e.g. an online run starting
+    if let Err(error) = current.set(state) {
+        warn!("Ignoring menu button: {:?}", error);
+    }
+}
 
-fn click_play_button(
-    mut commands: Commands,
-    button_materials: Res<ButtonMaterials>,
-    mut state: ResMut<State<GameState>>,
-    mut interaction_query: Query<ButtonInteraction, (Changed<Interaction>, With<Button>)>,
-    text_query: Query<Entity, With<Text>>,
-) {
-    for (button, interaction, mut material, children) in interaction_query.iter_mut() {
-        let text = text_query.get(children[0]).unwrap();
-        match *interaction {
-            Interaction::Clicked => {
-                commands.entity(button).despawn();
-                commands.entity(text).despawn();
-                state.set(GameState::Playing).unwrap();
-            }
-            Interaction::Hovered => {
-                *material = button_materials.hovered.clone();
-            }
-            Interaction::None => {
-                *material = button_materials.normal.clone();
-            }
-        }
+fn cleanup_menu(mut commands: Commands, menu: Query<Entity, With<Menu>>) {
+    for entity in menu.iter() {
+        commands.entity(entity).despawn_recursive();
     }
 }
@@ -0,0 +1,966 @@
+use crate::actions::{Actions, GameControl, InputBuffer, ACTION_BUFFER_SECONDS};
+use crate::enemy::{spawn_enemy_at, Enemy};
+use crate::loading::TextureAtlases;
+use crate::party::Party;
+use crate::player::{spawn_bullet, spawn_ship, Bullet, MainCamera, Player, PlayerId, PlayerMotion};
+use crate::replay::{array, Reader, TickActions};
+use crate::rng::{GameRng, NextRunSeed};
+use crate::score::Score;
+use crate::simulation::{Interpolated, SimulationStage, SimulationTime};
+use crate::tilemap::ActiveTileMap;
+use crate::GameState;
+use anyhow::{anyhow, bail};
+use bevy::ecs::system::CommandQueue;
+use bevy::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+
+pub struct NetcodePlugin;
+
+/// This plugin plays online co-op with rollback. Both games simulate right away with their own input
+/// and guess the input of the other player by repeating what they held last. Once the real input
+/// arrives and differs from the guess, the game restores the snapshot of that tick and simulates again.
+/// Local input is delayed by [INPUT_DELAY] ticks, so the real input is often there in time.
+///
+/// Start two instances with `--online <1|2> <local port> <peer address>`, e.g. `--online 1 7000 127.0.0.1:7001`
+/// and `--online 2 7001 127.0.0.1:7000`. A run starts as soon as both are in the menu.
+/// `--net-loss <share>` drops that share of the outgoing packets and `--net-latency <milliseconds>` holds them back,
+/// to try bad connections on one machine.
+impl Plugin for NetcodePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        let args: Vec<String> = std::env::args().collect();
+        if args.iter().any(|arg| arg == "--online") {
+            match NetSession::from_args(&args) {
+                Ok(session) => {
+                    app.insert_resource(session);
+                }
+                Err(error) => warn!("Not going online: {}", error),
+            }
+        }
+        app.add_system_set(SystemSet::on_update(GameState::Menu).with_system(connect.system()))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing).with_system(receive_inputs.system()),
+            )
+            .add_system_to_stage(CoreStage::Update, rollback.exclusive_system().at_end())
+            .add_system_to_stage(
+                SimulationStage::Tick,
+                save_snapshot
+                    .system()
+                    .label("save_snapshot")
+                    .after("begin_tick"),
+            )
+            .add_system_to_stage(
+                SimulationStage::Tick,
+                apply_net_inputs
+                    .system()
+                    .label("gather_input")
+                    .label("net_input")
+                    .after("tick_actions")
+                    .after("save_snapshot"),
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, send_inputs.system())
+            .add_system_set(SystemSet::on_exit(GameState::Playing).with_system(end_run.system()));
+    }
+}
+
+/// Ticks between reading local input and using it
+const INPUT_DELAY: usize = 2;
+/// How many ticks the game may run ahead of the input of the other player, before it waits
+const MAX_PREDICTION: u64 = 8;
+/// Unconfirmed inputs are sent again with every packet, up to this many
+const MAX_INPUTS_PER_PACKET: usize = 32;
+const MAGIC: &[u8; 4] = b"RJNT";
+
+/// The connection and the inputs of an online run, with the gameplay state saved as `S` for rollbacks
+struct NetSession<S = Snapshot> {
+    link: Link,
+    local: PlayerId,
+    /// Counts the runs of this session, so packets from an earlier run are ignored
+    run: u32,
+    /// Seed of the current run, picked by player one
+    seed: u64,
+    running: bool,
+    /// Input of the local player by tick, already [INPUT_DELAY] ticks ahead
+    local_inputs: Vec<TickActions>,
+    /// Input of the other player by tick, as far as it arrived without gaps
+    remote_inputs: Vec<TickActions>,
+    /// The input of the other player each simulated tick used
+    predicted: Vec<TickActions>,
+    /// First tick that was simulated with a wrong guess
+    rollback_to: Option<u64>,
+    /// Number of local inputs the other side confirmed
+    peer_ack: usize,
+    /// The state at the start of the most recent ticks, by tick
+    snapshots: VecDeque<(u64, S)>,
+}
+
+/// The gameplay state at the start of a tick.
+/// Tilemap chunks are spawned around the camera every frame, so they are not part of it.
+/// Split events are only sent in the first run of a tick, and the run timer and the dialogues
+/// follow those events.
+#[derive(Clone)]
+struct Snapshot {
+    rng: GameRng,
+    score: u32,
+    camera: Vec3,
+    next_split: usize,
+    players: Vec<(PlayerId, Transform, Player, PlayerMotion)>,
+    enemies: Vec<(Transform, Enemy, Timer, u32)>,
+    bullets: Vec<(Transform, Bullet)>,
+}
+
+/// The UDP socket to the other player. To try bad connections on one machine,
+/// it can drop a share of the outgoing packets and hold back the others.
+struct Link {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    loss: f32,
+    /// Picks the dropped packets
+    loss_rng: StdRng,
+    latency: f64,
+    outgoing: VecDeque<(f64, Vec<u8>)>,
+}
+
+impl Link {
+    fn new(
+        socket: UdpSocket,
+        peer: SocketAddr,
+        loss: f32,
+        loss_seed: u64,
+        latency: f64,
+    ) -> std::io::Result<Self> {
+        socket.set_nonblocking(true)?;
+        Ok(Link {
+            socket,
+            peer,
+            loss,
+            loss_rng: StdRng::seed_from_u64(loss_seed),
+            latency,
+            outgoing: VecDeque::new(),
+        })
+    }
+
+    fn send(&mut self, bytes: Vec<u8>, now: f64) {
+        if self.loss_rng.gen::<f32>() >= self.loss {
+            self.outgoing.push_back((now + self.latency, bytes));
+        }
+        while let Some((due, _)) = self.outgoing.front() {
+            if *due > now {
+                break;
+            }
+            if let Some((_, bytes)) = self.outgoing.pop_front() {
+                if let Err(error) = self.socket.send_to(&bytes, self.peer) {
+                    debug!("Failed to send packet: {}", error);
+                }
+            }
+        }
+    }
+
+    fn receive(&mut self) -> Vec<Packet> {
+        let mut packets = vec![];
+        let mut buffer = [0; 1500];
+        loop {
+            match self.socket.recv_from(&mut buffer) {
+                Ok((length, from)) => {
+                    if from != self.peer {
+                        continue;
+                    }
+                    match Packet::from_bytes(&buffer[..length]) {
+                        Ok(packet) => packets.push(packet),
+                        Err(error) => debug!("Dropped packet: {}", error),
+                    }
+                }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                // some systems report packets that found nobody listening yet on the next receive
+                Err(error) if error.kind() == ErrorKind::ConnectionReset => continue,
+                Err(error) => {
+                    warn!("Failed to receive packets: {}", error);
+                    break;
+                }
+            }
+        }
+        packets
+    }
+}
+
+/// All numbers little endian: "RJNT", run: u32, seed: u64, ack: u32, first tick: u32, input count: u8,
+/// then the [TickActions] of the sender for the ticks from `first tick` on.
+/// `ack` is the number of ticks the sender has the input of the receiver for.
+struct Packet {
+    run: u32,
+    seed: u64,
+    ack: u32,
+    first_tick: u32,
+    inputs: Vec<TickActions>,
+}
+
+impl Packet {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&self.run.to_le_bytes());
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.extend_from_slice(&self.ack.to_le_bytes());
+        bytes.extend_from_slice(&self.first_tick.to_le_bytes());
+        bytes.push(self.inputs.len() as u8);
+        for input in self.inputs.iter() {
+            input.write(&mut bytes);
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(4)? != MAGIC {
+            bail!("not a game packet");
+        }
+        let run = u32::from_le_bytes(array(reader.take(4)?));
+        let seed = u64::from_le_bytes(array(reader.take(8)?));
+        let ack = u32::from_le_bytes(array(reader.take(4)?));
+        let first_tick = u32::from_le_bytes(array(reader.take(4)?));
+        let count = reader.byte()?;
+        let inputs = (0..count)
+            .map(|_| TickActions::read(&mut reader))
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Packet {
+            run,
+            seed,
+            ack,
+            first_tick,
+            inputs,
+        })
+    }
+}
+
+impl NetSession {
+    fn from_args(args: &[String]) -> anyhow::Result<Self> {
+        let value = |flag: &str, offset: usize| {
+            args.iter()
+                .position(|arg| arg == flag)
+                .and_then(|position| args.get(position + offset))
+        };
+        let usage = || anyhow!("expected --online <1|2> <local port> <peer address>");
+        let local = match value("--online", 1).map(String::as_str) {
+            Some("1") => PlayerId::One,
+            Some("2") => PlayerId::Two,
+            _ => return Err(usage()),
+        };
+        let port: u16 = value("--online", 2).ok_or_else(usage)?.parse()?;
+        let peer: SocketAddr = value("--online", 3).ok_or_else(usage)?.parse()?;
+        let loss: f32 = value("--net-loss", 1)
+            .map(|loss| loss.parse())
+            .transpose()?
+            .unwrap_or(0.);
+        let latency: f64 = value("--net-latency", 1)
+            .map(|latency| latency.parse())
+            .transpose()?
+            .unwrap_or(0.);
+
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        info!(
+            "Playing online as {} on port {} with {}",
+            local.name(),
+            port,
+            peer
+        );
+        let link = Link::new(socket, peer, loss, rand::random(), latency / 1000.)?;
+        Ok(NetSession::new(local, link))
+    }
+}
+
+impl<S: Clone> NetSession<S> {
+    fn new(local: PlayerId, link: Link) -> Self {
+        NetSession {
+            link,
+            local,
+            run: 0,
+            seed: rand::random(),
+            running: false,
+            local_inputs: vec![],
+            remote_inputs: vec![],
+            predicted: vec![],
+            rollback_to: None,
+            peer_ack: 0,
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    fn remote(&self) -> PlayerId {
+        match self.local {
+            PlayerId::One => PlayerId::Two,
+            PlayerId::Two => PlayerId::One,
+        }
+    }
+
+    fn packet(&self) -> Packet {
+        let first = self.peer_ack.min(self.local_inputs.len());
+        let last = self.local_inputs.len().min(first + MAX_INPUTS_PER_PACKET);
+        Packet {
+            run: self.run,
+            seed: self.seed,
+            ack: self.remote_inputs.len() as u32,
+            first_tick: first as u32,
+            inputs: self.local_inputs[first..last].to_vec(),
+        }
+    }
+
+    fn handle(&mut self, packet: Packet) {
+        if packet.run != self.run {
+            return;
+        }
+        self.peer_ack = self.peer_ack.max(packet.ack as usize);
+        for (offset, input) in packet.inputs.into_iter().enumerate() {
+            let tick = packet.first_tick as usize + offset;
+            // inputs after a gap come again with the next packets
+            if tick != self.remote_inputs.len() {
+                continue;
+            }
+            if let Some(predicted) = self.predicted.get(tick) {
+                if *predicted != input {
+                    let tick = tick as u64;
+                    self.rollback_to = Some(self.rollback_to.map_or(tick, |first| first.min(tick)));
+                }
+            }
+            self.remote_inputs.push(input);
+        }
+    }
+
+    fn reset(&mut self) {
+        self.local_inputs.clear();
+        self.remote_inputs.clear();
+        self.predicted.clear();
+        self.rollback_to = None;
+        self.peer_ack = 0;
+        self.snapshots.clear();
+    }
+
+    /// Calls out to the other side and starts the run once it answers, returns whether it started
+    fn connect(&mut self, now: f64) -> bool {
+        let hello = self.packet().to_bytes();
+        self.link.send(hello, now);
+        let run = self.run;
+        let packet = match self
+            .link
+            .receive()
+            .into_iter()
+            .find(|packet| packet.run == run)
+        {
+            Some(packet) => packet,
+            None => return false,
+        };
+        if self.local == PlayerId::Two {
+            self.seed = packet.seed;
+        }
+        info!("Starting online run {}", run);
+        self.reset();
+        self.running = true;
+        // the other side might already be playing and sent some input
+        self.handle(packet);
+        true
+    }
+
+    fn receive(&mut self) {
+        for packet in self.link.receive() {
+            self.handle(packet);
+        }
+    }
+
+    fn send(&mut self, now: f64) {
+        let packet = self.packet().to_bytes();
+        self.link.send(packet, now);
+    }
+
+    /// Ticks with input from both peers, they are never rolled back again
+    fn confirmed_ticks(&self) -> u64 {
+        self.remote_inputs.len() as u64
+    }
+
+    /// Ticks from this one on wait for the input of the other player,
+    /// there are only snapshots to roll back this far
+    fn tick_limit(&self) -> u64 {
+        self.confirmed_ticks() + MAX_PREDICTION
+    }
+
+    /// Whether the local input read in `tick` is still missing
+    fn needs_local_input(&self, tick: usize) -> bool {
+        self.local_inputs.len() <= tick + INPUT_DELAY
+    }
+
+    /// Stores the local input read in `tick`, it is used [INPUT_DELAY] ticks later
+    fn add_local_input(&mut self, tick: usize, input: TickActions) {
+        self.local_inputs
+            .resize(tick + INPUT_DELAY, TickActions::default());
+        self.local_inputs.push(input);
+    }
+
+    /// The input of the local and the other player in `tick`, with a guess if the latter did not arrive yet
+    fn inputs(&mut self, tick: usize) -> (TickActions, TickActions) {
+        let local = self.local_inputs.get(tick).cloned().unwrap_or_default();
+        let remote = match self.remote_inputs.get(tick) {
+            Some(input) => input.clone(),
+            None => self
+                .remote_inputs
+                .last()
+                .map(TickActions::held)
+                .unwrap_or_default(),
+        };
+        self.predicted.truncate(tick);
+        self.predicted.push(remote.clone());
+        (local, remote)
+    }
+
+    fn save_snapshot(&mut self, tick: u64, snapshot: S) {
+        self.snapshots.retain(|(saved, _)| *saved < tick);
+        self.snapshots.push_back((tick, snapshot));
+        while self.snapshots.len() > MAX_PREDICTION as usize + 1 {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// The first tick before `next_tick` that was simulated with a wrong guess, and the state to run it again from
+    fn rollback(&mut self, next_tick: u64) -> Option<(u64, S)> {
+        let tick = match self.rollback_to.take() {
+            Some(tick) if tick < next_tick => tick,
+            _ => return None,
+        };
+        self.predicted.truncate(tick as usize);
+        let snapshot = self
+            .snapshots
+            .iter()
+            .find(|(saved, _)| *saved == tick)
+            .map(|(_, snapshot)| (tick, snapshot.clone()));
+        if snapshot.is_none() {
+            warn!("There is no snapshot of tick {} to roll back to", tick);
+        }
+        snapshot
+    }
+}
+
+/// In the menu, both sides call out until they hear from each other and then start the run together
+fn connect(
+    time: Res<Time>,
+    session: Option<ResMut<NetSession>>,
+    mut party: ResMut<Party>,
+    mut next_seed: ResMut<NextRunSeed>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let mut session = match session {
+        Some(session) => session,
+        None => return,
+    };
+    // a run that could not start last frame tries again
+    if !session.running && !session.connect(time.seconds_since_startup()) {
+        return;
+    }
+    next_seed.0 = Some(session.seed);
+    *party = Party::online(session.local);
+    if let Err(error) = state.set(GameState::Playing) {
+        warn!("Starting the online run next frame: {:?}", error);
+    }
+}
+
+fn receive_inputs(session: Option<ResMut<NetSession>>, mut simulation: ResMut<SimulationTime>) {
+    let mut session = match session {
+        Some(session) if session.running => session,
+        _ => return,
+    };
+    session.receive();
+    simulation.tick_limit = Some(session.tick_limit());
+    simulation.confirmed = Some(session.confirmed_ticks());
+}
+
+#[allow(clippy::too_many_arguments)]
+fn save_snapshot(
+    session: Option<ResMut<NetSession>>,
+    simulation: Res<SimulationTime>,
+    rng: Res<GameRng>,
+    score: Res<Score>,
+    tilemap: Option<Res<ActiveTileMap>>,
+    camera_query: Query<&Transform, With<MainCamera>>,
+    player_query: Query<(&PlayerId, &Transform, &Player, &PlayerMotion)>,
+    enemy_query: Query<(&Transform, &Enemy, &Timer, &TextureAtlasSprite)>,
+    bullet_query: Query<(&Transform, &Bullet)>,
+) {
+    let mut session = match session {
+        Some(session) if session.running => session,
+        _ => return,
+    };
+    let snapshot = Snapshot {
+        rng: rng.clone(),
+        score: score.0,
+        camera: camera_query
+            .single()
+            .map(|camera| camera.translation)
+            .unwrap_or(Vec3::ZERO),
+        next_split: tilemap.map_or(0, |tilemap| tilemap.next_split),
+        players: player_query
+            .iter()
+            .map(|(id, transform, player, motion)| {
+                (*id, *transform, player.clone(), motion.clone())
+            })
+            .collect(),
+        enemies: enemy_query
+            .iter()
+            .map(|(transform, enemy, timer, sprite)| {
+                (*transform, enemy.clone(), timer.clone(), sprite.index)
+            })
+            .collect(),
+        bullets: bullet_query
+            .iter()
+            .map(|(transform, bullet)| (*transform, bullet.clone()))
+            .collect(),
+    };
+    session.save_snapshot(simulation.tick(), snapshot);
+}
+
+/// Uses the delayed local input and the input of the other player, or a guess if it did not arrive yet
+fn apply_net_inputs(
+    session: Option<ResMut<NetSession>>,
+    simulation: Res<SimulationTime>,
+    mut actions: ResMut<Actions>,
+    mut buffer: ResMut<InputBuffer>,
+) {
+    let mut session = match session {
+        Some(session) if session.running => session,
+        _ => return,
+    };
+    let tick = simulation.tick() as usize;
+    let local = session.local;
+    // ticks that run again after a rollback keep the input they had
+    if !simulation.is_resimulating() && session.needs_local_input(tick) {
+        let sampled = actions.player(local);
+        // presses are sent once, instead of waiting in the buffer until gameplay uses them
+        if sampled.dash {
+            buffer.consume(local, GameControl::Dash, ACTION_BUFFER_SECONDS);
+        }
+        if sampled.switch_weapon {
+            buffer.consume(local, GameControl::SwitchWeapon, ACTION_BUFFER_SECONDS);
+        }
+        session.add_local_input(tick, TickActions::from_actions(sampled));
+    }
+    let (local_input, remote_input) = session.inputs(tick);
+    local_input.apply(actions.player_mut(local));
+    remote_input.apply(actions.player_mut(session.remote()));
+}
+
+fn rollback(world: &mut World) {
+    let playing = world
+        .get_resource::<State<GameState>>()
+        .map_or(false, |state| *state.current() == GameState::Playing);
+    if !playing {
+        return;
+    }
+    let next_tick = world
+        .get_resource::<SimulationTime>()
+        .map_or(0, |simulation| simulation.next_tick());
+    let rollback = world
+        .get_resource_mut::<NetSession>()
+        .and_then(|mut session| session.rollback(next_tick));
+    let (tick, snapshot) = match rollback {
+        Some(rollback) => rollback,
+        None => return,
+    };
+    restore(world, &snapshot);
+    if let Some(mut simulation) = world.get_resource_mut::<SimulationTime>() {
+        simulation.rollback(tick);
+    }
+}
+
+/// Replaces all players, enemies and bullets with the ones in `snapshot`,
+/// and puts back the camera, the score and the split progress
+fn restore(world: &mut World, snapshot: &Snapshot) {
+    let outdated: Vec<Entity> = world
+        .query_filtered::<Entity, Or<(With<Player>, With<Enemy>, With<Bullet>)>>()
+        .iter(world)
+        .collect();
+    let texture_atlas_handle = match world.get_resource::<TextureAtlases>() {
+        Some(texture_atlases) => texture_atlases.main_sprite_sheet.clone(),
+        None => return,
+    };
+    let mut queue = CommandQueue::default();
+    let mut commands = Commands::new(&mut queue, world);
+    for entity in outdated {
+        commands.entity(entity).despawn_recursive();
+    }
+    for (id, transform, player, motion) in snapshot.players.iter() {
+        spawn_ship(
+            &mut commands,
+            &texture_atlas_handle,
+            *id,
+            *transform,
+            player.clone(),
+            motion.clone(),
+        );
+    }
+    for (transform, enemy, animation, index) in snapshot.enemies.iter() {
+        spawn_enemy_at(
+            &mut commands,
+            &texture_atlas_handle,
+            *transform,
+            enemy.clone(),
+            animation.clone(),
+            *index,
+        );
+    }
+    for (transform, bullet) in snapshot.bullets.iter() {
+        spawn_bullet(
+            &mut commands,
+            &texture_atlas_handle,
+            *transform,
+            bullet.clone(),
+        );
+    }
+    queue.apply(world);
+
+    for (mut transform, mut interpolated) in world
+        .query_filtered::<(&mut Transform, &mut Interpolated), With<MainCamera>>()
+        .iter_mut(world)
+    {
+        transform.translation = snapshot.camera;
+        interpolated.teleport(snapshot.camera);
+    }
+    if let Some(mut rng) = world.get_resource_mut::<GameRng>() {
+        *rng = snapshot.rng.clone();
+    }
+    if let Some(mut score) = world.get_resource_mut::<Score>() {
+        score.0 = snapshot.score;
+    }
+    if let Some(mut tilemap) = world.get_resource_mut::<ActiveTileMap>() {
+        tilemap.next_split = snapshot.next_split;
+    }
+}
+
+fn send_inputs(time: Res<Time>, session: Option<ResMut<NetSession>>) {
+    let mut session = match session {
+        Some(session) if session.running => session,
+        _ => return,
+    };
+    session.send(time.seconds_since_startup());
+}
+
+fn end_run(session: Option<ResMut<NetSession>>) {
+    let mut session = match session {
+        Some(session) => session,
+        None => return,
+    };
+    session.running = false;
+    session.run += 1;
+    if session.local == PlayerId::One {
+        session.seed = rand::random();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::PlayerActions;
+
+    const TICKS: u64 = 300;
+    const LOSS: f32 = 0.3;
+    /// Seconds
+    const LATENCY: f64 = 0.05;
+    const FRAME: f64 = 1. / 60.;
+
+    /// One side of an online run. Instead of the gameplay, it simulates a hash of all inputs so far.
+    struct Peer {
+        session: NetSession<u64>,
+        state: u64,
+        next_tick: u64,
+        /// The state after each tick
+        checksums: Vec<u64>,
+        rollbacks: u32,
+    }
+
+    impl Peer {
+        fn new(local: PlayerId, socket: UdpSocket, peer: SocketAddr) -> Self {
+            // the same packets are dropped in every run of the test
+            let loss_seed = match local {
+                PlayerId::One => 1,
+                PlayerId::Two => 2,
+            };
+            let link = Link::new(socket, peer, LOSS, loss_seed, LATENCY).unwrap();
+            Peer {
+                session: NetSession::new(local, link),
+                state: 0,
+                next_tick: 0,
+                checksums: vec![],
+                rollbacks: 0,
+            }
+        }
+
+        fn frame(&mut self, now: f64) {
+            if !self.session.running {
+                self.session.connect(now);
+                return;
+            }
+            self.session.receive();
+            if let Some((tick, state)) = self.session.rollback(self.next_tick) {
+                self.state = state;
+                self.next_tick = tick;
+                self.rollbacks += 1;
+            }
+            while self.next_tick < self.session.tick_limit().min(TICKS) {
+                let tick = self.next_tick;
+                self.session.save_snapshot(tick, self.state);
+                if self.session.needs_local_input(tick as usize) {
+                    let input = sampled_input(self.session.local, tick);
+                    self.session.add_local_input(tick as usize, input);
+                }
+                let inputs = self.session.inputs(tick as usize);
+                self.state = step(self.state, &self.ordered(inputs));
+                self.checksums.truncate(tick as usize);
+                self.checksums.push(self.state);
+                self.next_tick += 1;
+            }
+            self.session.send(now);
+        }
+
+        /// Puts the input of the local and the other player in the order of the players
+        fn ordered(&self, (local, remote): (TickActions, TickActions)) -> [TickActions; 2] {
+            match self.session.local {
+                PlayerId::One => [local, remote],
+                PlayerId::Two => [remote, local],
+            }
+        }
+
+        fn confirmed_inputs(&self, tick: usize) -> [TickActions; 2] {
+            let local = self.session.local_inputs[tick].clone();
+            let remote = self.session.remote_inputs[tick].clone();
+            self.ordered((local, remote))
+        }
+
+        fn finished(&self) -> bool {
+            self.next_tick == TICKS
+                && self.session.remote_inputs.len() as u64 >= TICKS
+                && self.session.rollback_to.is_none()
+        }
+    }
+
+    /// Changes often enough that guessing the held input goes wrong
+    fn sampled_input(player: PlayerId, tick: u64) -> TickActions {
+        let phase = match player {
+            PlayerId::One => tick / 7,
+            PlayerId::Two => tick / 11 + 3,
+        };
+        let angle = phase as f32;
+        TickActions::from_actions(&PlayerActions {
+            movement: if phase % 3 == 0 {
+                None
+            } else {
+                Some(Vec2::new(angle.cos(), angle.sin()))
+            },
+            shoot: phase % 2 == 0,
+            dash: tick % 13 == 1,
+            ..Default::default()
+        })
+    }
+
+    /// FNV-1a over the previous state and the input of both players
+    fn step(state: u64, inputs: &[TickActions; 2]) -> u64 {
+        let mut bytes = state.to_le_bytes().to_vec();
+        for input in inputs.iter() {
+            input.write(&mut bytes);
+        }
+        bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    /// A world with a running session and everything [save_snapshot] and [restore] read
+    fn snapshot_world() -> World {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+        let mut session: NetSession = NetSession::new(
+            PlayerId::One,
+            Link::new(socket, address, 0., 0, 0.).unwrap(),
+        );
+        session.running = true;
+        let mut world = World::default();
+        world.insert_resource(session);
+        world.insert_resource(SimulationTime::default());
+        world.insert_resource(GameRng::from_seed(7));
+        world.insert_resource(Score(120));
+        world.insert_resource(TextureAtlases {
+            main_sprite_sheet: Handle::default(),
+        });
+
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let handle = Handle::default();
+        commands
+            .spawn()
+            .insert(Transform::from_xyz(0., 50., 999.))
+            .insert(MainCamera)
+            .insert(Interpolated::new(Vec3::new(0., 50., 999.)));
+        for (id, x) in [(PlayerId::One, -40.), (PlayerId::Two, 40.)].iter() {
+            let player = Player {
+                lives: 3,
+                ..Default::default()
+            };
+            let transform = Transform::from_xyz(*x, 0., 1.);
+            spawn_ship(
+                &mut commands,
+                &handle,
+                *id,
+                transform,
+                player,
+                Default::default(),
+            );
+        }
+        let animation = Timer::from_seconds(0.1, true);
+        let enemy = Enemy { health: 3 };
+        spawn_enemy_at(
+            &mut commands,
+            &handle,
+            Transform::default(),
+            enemy,
+            animation,
+            189,
+        );
+        let bullet = Bullet { direction: Vec3::Y };
+        spawn_bullet(
+            &mut commands,
+            &handle,
+            Transform::from_xyz(5., 5., 1.),
+            bullet,
+        );
+        queue.apply(&mut world);
+        world
+    }
+
+    /// Everything a snapshot covers, in an order that does not depend on the entities
+    fn describe(world: &mut World) -> Vec<String> {
+        let mut rng = world.get_resource::<GameRng>().unwrap().clone();
+        let mut lines = vec![
+            format!("rng {}", rng.gen::<u64>()),
+            format!("score {}", world.get_resource::<Score>().unwrap().0),
+        ];
+        let mut entities: Vec<String> = world
+            .query_filtered::<&Transform, With<MainCamera>>()
+            .iter(world)
+            .map(|camera| format!("camera {:?}", camera.translation))
+            .collect();
+        entities.extend(
+            world
+                .query::<(&PlayerId, &Transform, &Player, &PlayerMotion)>()
+                .iter(world)
+                .map(|(id, transform, player, motion)| {
+                    format!(
+                        "player {:?} {:?} {} {:?}",
+                        id, transform.translation, player.lives, motion.velocity
+                    )
+                }),
+        );
+        entities.extend(
+            world
+                .query::<(&Transform, &Enemy, &Timer, &TextureAtlasSprite)>()
+                .iter(world)
+                .map(|(transform, enemy, timer, sprite)| {
+                    format!(
+                        "enemy {:?} {} {:?} {}",
+                        transform.translation,
+                        enemy.health,
+                        timer.elapsed(),
+                        sprite.index
+                    )
+                }),
+        );
+        entities.extend(world.query::<(&Transform, &Bullet)>().iter(world).map(
+            |(transform, bullet)| {
+                format!("bullet {:?} {:?}", transform.translation, bullet.direction)
+            },
+        ));
+        entities.sort();
+        lines.extend(entities);
+        lines
+    }
+
+    #[test]
+    fn restoring_a_snapshot_puts_back_the_saved_state() {
+        let mut world = snapshot_world();
+        let mut stage = SystemStage::single(save_snapshot.system());
+        stage.run(&mut world);
+        let saved = describe(&mut world);
+
+        world.get_resource_mut::<GameRng>().unwrap().gen::<u64>();
+        world.get_resource_mut::<Score>().unwrap().0 += 50;
+        for (mut transform, mut player) in world
+            .query::<(&mut Transform, &mut Player)>()
+            .iter_mut(&mut world)
+        {
+            transform.translation.y += 10.;
+            player.lives -= 1;
+        }
+        for mut camera in world
+            .query_filtered::<&mut Transform, With<MainCamera>>()
+            .iter_mut(&mut world)
+        {
+            camera.translation.y += 10.;
+        }
+        let enemies: Vec<Entity> = world
+            .query_filtered::<Entity, With<Enemy>>()
+            .iter(&world)
+            .collect();
+        for enemy in enemies {
+            world.despawn(enemy);
+        }
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let bullet = Bullet {
+            direction: -Vec3::Y,
+        };
+        spawn_bullet(
+            &mut commands,
+            &Handle::default(),
+            Transform::default(),
+            bullet,
+        );
+        queue.apply(&mut world);
+        assert_ne!(describe(&mut world), saved);
+
+        let snapshot = world
+            .get_resource::<NetSession>()
+            .and_then(|session| session.snapshots.back())
+            .map(|(_, snapshot)| snapshot.clone())
+            .unwrap();
+        restore(&mut world, &snapshot);
+        assert_eq!(describe(&mut world), saved);
+    }
+
+    #[test]
+    fn peers_agree_on_every_tick_over_a_lossy_link() {
+        let socket_one = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let socket_two = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address_one = socket_one.local_addr().unwrap();
+        let address_two = socket_two.local_addr().unwrap();
+        let mut one = Peer::new(PlayerId::One, socket_one, address_two);
+        let mut two = Peer::new(PlayerId::Two, socket_two, address_one);
+
+        let mut now = 0.;
+        while !(one.finished() && two.finished()) {
+            assert!(now < 60., "the peers did not finish the run");
+            one.frame(now);
+            two.frame(now);
+            now += FRAME;
+            // loopback packets are there right away, this only keeps the loop from spinning
+            std::thread::sleep(std::time::Duration::from_micros(100));
+        }
+
+        assert_eq!(one.session.seed, two.session.seed);
+        assert!(one.rollbacks + two.rollbacks > 0, "no guess went wrong");
+        for tick in 0..TICKS as usize {
+            assert!(
+                one.confirmed_inputs(tick) == two.confirmed_inputs(tick),
+                "the inputs of tick {} differ",
+                tick
+            );
+            assert_eq!(
+                one.checksums[tick], two.checksums[tick],
+                "the state after tick {} differs",
+                tick
+            );
+        }
+    }
+}
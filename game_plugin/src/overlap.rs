@@ -1,17 +1,116 @@
 use crate::enemy::Enemy;
-use crate::player::Player;
-use bevy::{
-    diagnostic::{Diagnostics, FrameTimeDiagnosticsPlugin},
-    prelude::*,
-};
+use crate::juice::{HitEvent, HitKind};
+use crate::player::{Bullet, Player, PlayerMotion};
+use crate::score::Score;
+use crate::simulation::{SimulationStage, SimulationTime};
+use bevy::prelude::*;
+
+pub struct OverlapPlugin;
+
+/// Half the size of a tile in the sprite sheet, used as the hit box of everything for now
+const HALF_SIZE: f32 = 8.;
+
+/// This plugin checks for overlapping bullets and enemies, and for enemies ramming players.
+/// It reports hits as [HitEvent]s and adds them to the [Score]
+impl Plugin for OverlapPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        #[cfg(feature = "debug_overlay")]
+        app.init_resource::<CollisionStats>();
+        app.add_system_to_stage(
+            SimulationStage::Tick,
+            collision_check_system.system().after("gather_input"),
+        )
+        .add_system_to_stage(
+            SimulationStage::Tick,
+            player_collision_system
+                .system()
+                .label("player_collisions")
+                .after("move_player"),
+        );
+    }
+}
+
+/// Counts the work done by the collision checks, only kept with the `debug_overlay` feature
+#[cfg(feature = "debug_overlay")]
+#[derive(Default)]
+pub struct CollisionStats {
+    /// Pairs of hit boxes tested for overlap since the debug overlay last reset this, once a frame
+    pub pairs_tested: u32,
+}
 
 fn collision_check_system(
-    mut query_player: Query<(&Player, &Transform)>,
-    mut query_enemy: Query<(&Enemy, &Transform)>,
+    mut commands: Commands,
+    simulation: Res<SimulationTime>,
+    #[cfg(feature = "debug_overlay")] mut stats: ResMut<CollisionStats>,
+    mut score: ResMut<Score>,
+    mut hits: EventWriter<HitEvent>,
+    query_bullet: Query<(Entity, &Transform), With<Bullet>>,
+    mut query_enemy: Query<(Entity, &mut Enemy, &Transform)>,
 ) {
-    for (player, transform) in query_player.iter() {
-        for (enemy, transform_enemy) in query_enemy.iter() {
-            todo!();
+    for (bullet, transform_bullet) in query_bullet.iter() {
+        for (entity, mut enemy, transform_enemy) in query_enemy.iter_mut() {
+            #[cfg(feature = "debug_overlay")]
+            {
+                stats.pairs_tested += 1;
+            }
+            if enemy.health == 0 || !overlaps(transform_bullet, transform_enemy) {
+                continue;
+            }
+            commands.entity(bullet).despawn();
+            enemy.health -= 1;
+            let kind = if enemy.health == 0 {
+                commands.entity(entity).despawn();
+                HitKind::EnemyKilled
+            } else {
+                HitKind::EnemyHit
+            };
+            score.add_hit(kind);
+            // ticks that run again after a rollback already showed their hits
+            if !simulation.is_resimulating() {
+                hits.send(HitEvent {
+                    target: entity,
+                    kind,
+                    position: transform_bullet.translation,
+                });
+            }
+            break;
         }
     }
 }
+
+fn player_collision_system(
+    simulation: Res<SimulationTime>,
+    #[cfg(feature = "debug_overlay")] mut stats: ResMut<CollisionStats>,
+    mut hits: EventWriter<HitEvent>,
+    mut query_player: Query<(Entity, &mut Player, &mut PlayerMotion, &Transform)>,
+    query_enemy: Query<(&Enemy, &Transform)>,
+) {
+    for (entity, mut player, mut motion, transform_player) in query_player.iter_mut() {
+        if player.lives == 0 || motion.is_invulnerable() {
+            continue;
+        }
+        let rammed = query_enemy.iter().any(|(enemy, transform_enemy)| {
+            #[cfg(feature = "debug_overlay")]
+            {
+                stats.pairs_tested += 1;
+            }
+            enemy.health > 0 && overlaps(transform_player, transform_enemy)
+        });
+        if rammed {
+            player.lives -= 1;
+            motion.make_invulnerable();
+            if !simulation.is_resimulating() {
+                hits.send(HitEvent {
+                    target: entity,
+                    kind: HitKind::PlayerHit,
+                    position: transform_player.translation,
+                });
+            }
+        }
+    }
+}
+
+fn overlaps(a: &Transform, b: &Transform) -> bool {
+    let distance = (a.translation - b.translation).abs();
+    distance.x < 2. * HALF_SIZE && distance.y < 2. * HALF_SIZE
+}
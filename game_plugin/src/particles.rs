@@ -0,0 +1,299 @@
+use crate::actions::Actions;
+use crate::juice::{HitEvent, HitKind};
+use crate::loading::TextureAtlases;
+use crate::player::{Player, PlayerId};
+use crate::simulation::RunEntity;
+use crate::GameState;
+use bevy::math::Mat2;
+use bevy::prelude::*;
+use rand::Rng;
+
+pub struct ParticlesPlugin;
+
+/// This plugin spawns and animates sprite particles for explosions, sparks and the player's thruster.
+/// Particles are plain sprites from the main sprite sheet, simulated on the CPU.
+impl Plugin for ParticlesPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<ParticleSettings>().add_system_set(
+            SystemSet::on_update(GameState::Playing)
+                .with_system(spawn_thruster.system())
+                .with_system(spawn_hit_particles.system().after("react_to_hits"))
+                .with_system(control_thruster.system().after("gather_input"))
+                .with_system(emit_particles.system().label("emit_particles"))
+                .with_system(update_particles.system().after("emit_particles")),
+        );
+    }
+}
+
+pub struct ParticleSettings {
+    /// No new particles are spawned while this many are alive
+    pub max_particles: usize,
+}
+
+impl Default for ParticleSettings {
+    fn default() -> Self {
+        ParticleSettings {
+            max_particles: 1000,
+        }
+    }
+}
+
+/// The bullet sprite, scaled down it makes for a decent spark
+const PARTICLE_SPRITE: u32 = 188 - 24;
+
+#[derive(Clone, Copy, Debug)]
+pub enum EmitterMode {
+    /// Emit `count` particles at once, then despawn the emitter
+    Burst { count: u32 },
+    /// Emit particles for as long as the emitter is active
+    Continuous { per_second: f32 },
+}
+
+#[derive(Clone, Debug)]
+pub struct ParticleEmitter {
+    pub mode: EmitterMode,
+    pub active: bool,
+    pub sprite_index: u32,
+    /// Min and max lifetime in seconds
+    pub lifetime: (f32, f32),
+    /// Min and max initial speed in pixels per second
+    pub speed: (f32, f32),
+    pub direction: Vec2,
+    /// Maximum angle in radians between a particle's initial velocity and `direction`
+    pub spread: f32,
+    pub gravity: Vec2,
+    pub start_color: Color,
+    pub end_color: Color,
+    pub start_scale: f32,
+    pub end_scale: f32,
+    spawn_accumulator: f32,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        ParticleEmitter {
+            mode: EmitterMode::Burst { count: 10 },
+            active: true,
+            sprite_index: PARTICLE_SPRITE,
+            lifetime: (0.5, 1.),
+            speed: (50., 100.),
+            direction: Vec2::Y,
+            spread: std::f32::consts::PI,
+            gravity: Vec2::ZERO,
+            start_color: Color::WHITE,
+            end_color: Color::rgba(1., 1., 1., 0.),
+            start_scale: 1.,
+            end_scale: 0.,
+            spawn_accumulator: 0.,
+        }
+    }
+}
+
+impl ParticleEmitter {
+    pub fn explosion() -> Self {
+        ParticleEmitter {
+            mode: EmitterMode::Burst { count: 40 },
+            lifetime: (0.3, 0.8),
+            speed: (40., 160.),
+            start_color: Color::rgb(1., 0.8, 0.2),
+            end_color: Color::rgba(0.8, 0.1, 0., 0.),
+            start_scale: 0.8,
+            ..Default::default()
+        }
+    }
+
+    pub fn sparks(direction: Vec2) -> Self {
+        ParticleEmitter {
+            mode: EmitterMode::Burst { count: 8 },
+            lifetime: (0.1, 0.3),
+            speed: (100., 250.),
+            direction,
+            spread: 0.6,
+            gravity: Vec2::new(0., -400.),
+            start_color: Color::rgb(1., 1., 0.6),
+            end_color: Color::rgba(1., 0.6, 0.2, 0.),
+            start_scale: 0.4,
+            ..Default::default()
+        }
+    }
+
+    pub fn thruster() -> Self {
+        ParticleEmitter {
+            mode: EmitterMode::Continuous { per_second: 60. },
+            active: false,
+            lifetime: (0.2, 0.4),
+            speed: (60., 100.),
+            direction: -Vec2::Y,
+            spread: 0.3,
+            start_color: Color::rgb(0.4, 0.7, 1.),
+            end_color: Color::rgba(0.2, 0.2, 1., 0.),
+            start_scale: 0.5,
+            ..Default::default()
+        }
+    }
+}
+
+struct Particle {
+    velocity: Vec2,
+    gravity: Vec2,
+    age: f32,
+    lifetime: f32,
+    start_color: Color,
+    end_color: Color,
+    start_scale: f32,
+    end_scale: f32,
+}
+
+struct Thruster;
+
+fn spawn_thruster(mut commands: Commands, player_query: Query<Entity, Added<Player>>) {
+    for player in player_query.iter() {
+        commands.entity(player).with_children(|parent| {
+            parent
+                .spawn()
+                .insert(Transform::from_translation(Vec3::new(0., -8., 0.)))
+                .insert(GlobalTransform::default())
+                .insert(ParticleEmitter::thruster())
+                .insert(Thruster);
+        });
+    }
+}
+
+fn control_thruster(
+    actions: Res<Actions>,
+    player_query: Query<&PlayerId>,
+    mut query: Query<(&Parent, &mut ParticleEmitter), With<Thruster>>,
+) {
+    for (parent, mut emitter) in query.iter_mut() {
+        let movement = match player_query.get(parent.0) {
+            Ok(id) => actions.player(*id).movement,
+            Err(_) => None,
+        };
+        emitter.active = movement.is_some();
+        if let Some(movement) = movement {
+            emitter.direction = -movement;
+        }
+    }
+}
+
+fn spawn_hit_particles(mut commands: Commands, mut hits: EventReader<HitEvent>) {
+    for hit in hits.iter() {
+        let transform = Transform::from_translation(hit.position);
+        commands
+            .spawn()
+            .insert(transform)
+            .insert(GlobalTransform::from(transform))
+            .insert(ParticleEmitter::sparks(-Vec2::Y))
+            .insert(RunEntity);
+        if hit.kind == HitKind::EnemyKilled {
+            commands
+                .spawn()
+                .insert(transform)
+                .insert(GlobalTransform::from(transform))
+                .insert(ParticleEmitter::explosion())
+                .insert(RunEntity);
+        }
+    }
+}
+
+fn emit_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    settings: Res<ParticleSettings>,
+    texture_atlases: Res<TextureAtlases>,
+    mut emitters: Query<(Entity, &mut ParticleEmitter, &GlobalTransform)>,
+    particles: Query<(), With<Particle>>,
+) {
+    let mut rng = rand::thread_rng();
+    let mut alive = particles.iter().count();
+    for (entity, mut emitter, transform) in emitters.iter_mut() {
+        if !emitter.active {
+            continue;
+        }
+        let count = match emitter.mode {
+            EmitterMode::Burst { count } => {
+                commands.entity(entity).despawn();
+                count
+            }
+            EmitterMode::Continuous { per_second } => {
+                emitter.spawn_accumulator += per_second * time.delta_seconds();
+                let count = emitter.spawn_accumulator.floor();
+                emitter.spawn_accumulator -= count;
+                count as u32
+            }
+        };
+        for _ in 0..count {
+            if alive >= settings.max_particles {
+                break;
+            }
+            alive += 1;
+            let angle = rng.gen_range(-emitter.spread..=emitter.spread);
+            let speed = rng.gen_range(emitter.speed.0..=emitter.speed.1);
+            let velocity = Mat2::from_angle(angle) * emitter.direction.normalize_or_zero() * speed;
+            let mut particle_transform = Transform::from_translation(transform.translation);
+            particle_transform.scale = Vec3::splat(emitter.start_scale);
+            commands
+                .spawn_bundle(SpriteSheetBundle {
+                    texture_atlas: texture_atlases.main_sprite_sheet.clone(),
+                    transform: particle_transform,
+                    sprite: TextureAtlasSprite {
+                        color: emitter.start_color,
+                        index: emitter.sprite_index,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .insert(Particle {
+                    velocity,
+                    gravity: emitter.gravity,
+                    age: 0.,
+                    lifetime: rng.gen_range(emitter.lifetime.0..=emitter.lifetime.1),
+                    start_color: emitter.start_color,
+                    end_color: emitter.end_color,
+                    start_scale: emitter.start_scale,
+                    end_scale: emitter.end_scale,
+                })
+                .insert(RunEntity);
+        }
+    }
+}
+
+fn update_particles(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(
+        Entity,
+        &mut Particle,
+        &mut Transform,
+        &mut TextureAtlasSprite,
+    )>,
+) {
+    let delta = time.delta_seconds();
+    for (entity, mut particle, mut transform, mut sprite) in query.iter_mut() {
+        particle.age += delta;
+        if particle.age >= particle.lifetime {
+            commands.entity(entity).despawn();
+            continue;
+        }
+        let gravity = particle.gravity;
+        particle.velocity += gravity * delta;
+        transform.translation += particle.velocity.extend(0.) * delta;
+
+        let t = particle.age / particle.lifetime;
+        transform.scale = Vec3::splat(lerp(particle.start_scale, particle.end_scale, t));
+        sprite.color = lerp_color(particle.start_color, particle.end_color, t);
+    }
+}
+
+fn lerp(start: f32, end: f32, t: f32) -> f32 {
+    start + (end - start) * t
+}
+
+fn lerp_color(start: Color, end: Color, t: f32) -> Color {
+    Color::rgba(
+        lerp(start.r(), end.r(), t),
+        lerp(start.g(), end.g(), t),
+        lerp(start.b(), end.b(), t),
+        lerp(start.a(), end.a(), t),
+    )
+}
@@ -0,0 +1,298 @@
+use crate::actions::{ConnectedGamepads, GameControl};
+use crate::input_map::{InputMap, KeyboardLayout};
+use crate::loading::FontAssets;
+use crate::player::{Player, PlayerId};
+use crate::GameState;
+use bevy::prelude::*;
+
+pub struct PartyPlugin;
+
+/// This plugin keeps track of who is playing. A second player can join in the menu
+/// with their own half of the keyboard or a gamepad of their own.
+/// During a run it shows the lives left of every player.
+impl Plugin for PartyPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Party>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Menu).with_system(show_join_prompt.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Menu)
+                    .with_system(join_players.system().label("join_players"))
+                    .with_system(update_join_prompt.system().after("join_players")),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Menu).with_system(cleanup::<JoinPrompt>.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing).with_system(show_lives.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing).with_system(update_lives.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing).with_system(cleanup::<LivesText>.system()),
+            );
+    }
+}
+
+/// Where the input of a player comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlayerInput {
+    /// Whichever device was used last and is not taken by another player
+    LastUsed,
+    Keyboard(KeyboardLayout),
+    Gamepad(Gamepad),
+    /// Someone playing on another computer, see [NetcodePlugin](crate::netcode::NetcodePlugin)
+    Network,
+}
+
+/// The players taking part in the next or current run, sorted by [PlayerId]
+pub struct Party {
+    players: Vec<(PlayerId, PlayerInput)>,
+}
+
+impl Default for Party {
+    fn default() -> Self {
+        Party::with_players(1)
+    }
+}
+
+impl Party {
+    /// The first `count` players, all using the last used device
+    pub fn with_players(count: usize) -> Self {
+        Party {
+            players: PlayerId::ALL
+                .iter()
+                .take(count)
+                .map(|id| (*id, PlayerInput::LastUsed))
+                .collect(),
+        }
+    }
+
+    /// `local` on this computer and the other player online
+    pub fn online(local: PlayerId) -> Self {
+        Party {
+            players: PlayerId::ALL
+                .iter()
+                .map(|id| {
+                    let input = if *id == local {
+                        PlayerInput::LastUsed
+                    } else {
+                        PlayerInput::Network
+                    };
+                    (*id, input)
+                })
+                .collect(),
+        }
+    }
+
+    pub fn players(&self) -> impl Iterator<Item = (PlayerId, PlayerInput)> + '_ {
+        self.players.iter().copied()
+    }
+
+    /// The players using devices of this computer
+    pub fn local_players(&self) -> impl Iterator<Item = (PlayerId, PlayerInput)> + '_ {
+        self.players()
+            .filter(|(_, input)| *input != PlayerInput::Network)
+    }
+
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn input(&self, id: PlayerId) -> Option<PlayerInput> {
+        self.players
+            .iter()
+            .find(|(player, _)| *player == id)
+            .map(|(_, input)| *input)
+    }
+
+    /// The keys `id` may use. Whoever is not on their own half of the keyboard gets
+    /// the rest of it once another player took one half.
+    pub fn keyboard_layout(&self, id: PlayerId) -> KeyboardLayout {
+        match self.input(id) {
+            Some(PlayerInput::Keyboard(layout)) => layout,
+            _ if self.is_taken(PlayerInput::Keyboard(KeyboardLayout::PlayerTwo)) => {
+                KeyboardLayout::PlayerOne
+            }
+            _ => KeyboardLayout::Full,
+        }
+    }
+
+    /// Does some player use `input` as their own device?
+    pub fn is_taken(&self, input: PlayerInput) -> bool {
+        input != PlayerInput::LastUsed
+            && input != PlayerInput::Network
+            && self.players.iter().any(|(_, taken)| *taken == input)
+    }
+
+    fn join(&mut self, id: PlayerId, input: PlayerInput) {
+        info!("{} joined with {:?}", id.name(), input);
+        self.players.push((id, input));
+        self.players.sort_by_key(|(player, _)| player.index());
+    }
+
+    fn leave(&mut self, id: PlayerId) {
+        info!("{} left", id.name());
+        self.players.retain(|(player, _)| *player != id);
+    }
+}
+
+struct JoinPrompt;
+
+struct LivesText(PlayerId);
+
+/// The second player joins with their shoot key or the pause button of a gamepad, and leaves by pressing it again
+fn join_players(
+    mut party: ResMut<Party>,
+    input_map: Res<InputMap>,
+    gamepads: Res<ConnectedGamepads>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+) {
+    let pressed = if input_map.just_pressed(
+        GameControl::Shoot,
+        KeyboardLayout::PlayerTwo,
+        &keyboard_input,
+    ) {
+        Some(PlayerInput::Keyboard(KeyboardLayout::PlayerTwo))
+    } else {
+        gamepads
+            .0
+            .iter()
+            .find(|gamepad| {
+                input_map.gamepad_just_pressed(GameControl::Pause, **gamepad, &gamepad_buttons)
+            })
+            .map(|gamepad| PlayerInput::Gamepad(*gamepad))
+    };
+    let input = match pressed {
+        Some(input) => input,
+        None => return,
+    };
+    match party.input(PlayerId::Two) {
+        Some(joined) if joined == input => party.leave(PlayerId::Two),
+        Some(_) => (),
+        None => party.join(PlayerId::Two, input),
+    }
+}
+
+fn join_prompt(party: &Party, input_map: &InputMap) -> String {
+    let player = PlayerId::Two;
+    match party.input(player) {
+        Some(PlayerInput::Keyboard(layout)) => format!(
+            "{} joined on the keyboard - press {} to leave",
+            player.name(),
+            input_map.key_prompt(GameControl::Shoot, layout)
+        ),
+        Some(_) => format!(
+            "{} joined with a gamepad - press {} to leave",
+            player.name(),
+            input_map.gamepad_prompt(GameControl::Pause)
+        ),
+        None => format!(
+            "{}: press {} or {} on a gamepad to join",
+            player.name(),
+            input_map.key_prompt(GameControl::Shoot, KeyboardLayout::PlayerTwo),
+            input_map.gamepad_prompt(GameControl::Pause)
+        ),
+    }
+}
+
+fn show_join_prompt(
+    mut commands: Commands,
+    party: Res<Party>,
+    input_map: Res<InputMap>,
+    font_assets: Res<FontAssets>,
+) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(20.),
+                    bottom: Val::Px(20.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text::with_section(
+                join_prompt(&party, &input_map),
+                TextStyle {
+                    font: font_assets.fira_sans.clone(),
+                    font_size: 24.0,
+                    color: Color::rgb(0.9, 0.9, 0.9),
+                },
+                Default::default(),
+            ),
+            ..Default::default()
+        })
+        .insert(JoinPrompt);
+}
+
+fn update_join_prompt(
+    party: Res<Party>,
+    input_map: Res<InputMap>,
+    mut text_query: Query<&mut Text, With<JoinPrompt>>,
+) {
+    if !party.is_changed() {
+        return;
+    }
+    for mut text in text_query.iter_mut() {
+        text.sections[0].value = join_prompt(&party, &input_map);
+    }
+}
+
+fn show_lives(mut commands: Commands, party: Res<Party>, font_assets: Res<FontAssets>) {
+    for (index, (id, _)) in party.players().enumerate() {
+        commands
+            .spawn_bundle(TextBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        left: Val::Px(20.),
+                        top: Val::Px(20. + 30. * index as f32),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text::with_section(
+                    "",
+                    TextStyle {
+                        font: font_assets.fira_sans.clone(),
+                        font_size: 24.0,
+                        color: id.color(),
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            })
+            .insert(LivesText(id));
+    }
+}
+
+fn update_lives(
+    player_query: Query<(&PlayerId, &Player)>,
+    mut text_query: Query<(&LivesText, &mut Text)>,
+) {
+    for (LivesText(id), mut text) in text_query.iter_mut() {
+        let lives = player_query
+            .iter()
+            .find(|(player, _)| *player == id)
+            .map_or(0, |(_, player)| player.lives);
+        let value = match lives {
+            0 => format!("{}: out", id.name()),
+            1 => format!("{}: 1 life", id.name()),
+            lives => format!("{}: {} lives", id.name(), lives),
+        };
+        if text.sections[0].value != value {
+            text.sections[0].value = value;
+        }
+    }
+}
+
+fn cleanup<T: Send + Sync + 'static>(mut commands: Commands, query: Query<Entity, With<T>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
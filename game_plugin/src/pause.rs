@@ -0,0 +1,125 @@
+use crate::actions::{Actions, ConnectedGamepads, GameControl};
+use crate::input_map::{InputMap, KeyboardLayout};
+use crate::loading::FontAssets;
+use crate::player::PlayerId;
+use crate::rich_text::sections;
+use crate::touch_controls::TouchControls;
+use crate::GameState;
+use bevy::prelude::*;
+
+pub struct PausePlugin;
+
+/// This plugin pauses the game by pushing `GameState::Paused` on top of `GameState::Playing`.
+/// While paused, none of the gameplay systems run.
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<PauseToggledAt>()
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing).with_system(
+                    pause_game
+                        .system()
+                        .label("pause_game")
+                        .after("gather_input"),
+                ),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Paused).with_system(show_pause_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Paused)
+                    .with_system(resume_game.system().after("read_touches")),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Paused).with_system(hide_pause_text.system()),
+            );
+    }
+}
+
+struct PauseText;
+
+/// State transitions are applied within the same frame, so the press that paused the game
+/// would also resume it (and the other way around) if we did not remember when it happened
+#[derive(Default)]
+struct PauseToggledAt(f64);
+
+fn pause_game(
+    time: Res<Time>,
+    toggled_at: Res<PauseToggledAt>,
+    actions: Res<Actions>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if actions.pause && time.seconds_since_startup() != toggled_at.0 {
+        // another transition this frame, like the end of the run, goes first
+        let _ = state.push(GameState::Paused);
+    }
+}
+
+fn show_pause_text(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut toggled_at: ResMut<PauseToggledAt>,
+    font_assets: Res<FontAssets>,
+    actions: Res<Actions>,
+    input_map: Res<InputMap>,
+) {
+    toggled_at.0 = time.seconds_since_startup();
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(40.),
+                    top: Val::Percent(45.),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                sections: sections(
+                    &format!(
+                        "Paused - press [color=gold]{}[/color] to resume",
+                        input_map.prompt(GameControl::Pause, actions.player(PlayerId::One).device)
+                    ),
+                    &TextStyle {
+                        font: font_assets.fira_sans.clone(),
+                        font_size: 30.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .insert(PauseText);
+}
+
+// The gameplay input systems are not running while paused, so this checks the input map directly
+fn resume_game(
+    time: Res<Time>,
+    mut toggled_at: ResMut<PauseToggledAt>,
+    mut state: ResMut<State<GameState>>,
+    input_map: Res<InputMap>,
+    gamepads: Res<ConnectedGamepads>,
+    keyboard_input: Res<Input<KeyCode>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    touch_controls: Res<TouchControls>,
+) {
+    if time.seconds_since_startup() == toggled_at.0 {
+        return;
+    }
+    let resume = input_map.just_pressed(GameControl::Pause, KeyboardLayout::Full, &keyboard_input)
+        || touch_controls.pause
+        || gamepads.0.iter().any(|gamepad| {
+            input_map.gamepad_just_pressed(GameControl::Pause, *gamepad, &gamepad_buttons)
+        });
+    // another transition this frame goes first
+    if resume && state.pop().is_ok() {
+        toggled_at.0 = time.seconds_since_startup();
+    }
+}
+
+fn hide_pause_text(mut commands: Commands, text_query: Query<Entity, With<PauseText>>) {
+    for entity in text_query.iter() {
+        commands.entity(entity).despawn();
+    }
+}
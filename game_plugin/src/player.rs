@@ -1,17 +1,82 @@
-use crate::actions::Actions;
-use crate::loading::TextureAtlases;
+use crate::actions::{Actions, GameControl, InputBuffer, ACTION_BUFFER_SECONDS};
+use crate::juice::CameraShake;
+use crate::loading::{TextureAtlases, TuningAssets};
+use crate::party::Party;
+use crate::simulation::{Interpolated, RunEntity, SimulationStage, SimulationTime, TIMESTEP};
+use crate::tuning::PlayerTuning;
 use crate::GameState;
 use bevy::prelude::*;
 
 pub struct PlayerPlugin;
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Player {
     pub shot_timer: f32,
     pub state: PlayerState,
+    /// Hits the ship can still take, it is removed when this reaches 0
+    pub lives: u32,
+}
+
+pub const MAX_PLAYERS: usize = 2;
+
+/// Which player a ship belongs to, to look up their input with [Actions::player]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PlayerId {
+    One,
+    Two,
+}
+
+impl PlayerId {
+    pub const ALL: [PlayerId; MAX_PLAYERS] = [PlayerId::One, PlayerId::Two];
+
+    pub fn index(&self) -> usize {
+        match self {
+            PlayerId::One => 0,
+            PlayerId::Two => 1,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PlayerId::One => "Player 1",
+            PlayerId::Two => "Player 2",
+        }
+    }
+
+    /// Tint of the ship, so both players can tell which one is theirs
+    pub fn color(&self) -> Color {
+        match self {
+            PlayerId::One => Color::WHITE,
+            PlayerId::Two => Color::rgb(0.5, 1., 0.6),
+        }
+    }
+}
+
+const STARTING_LIVES: u32 = 3;
+/// Seconds the ship can not be hit after spawning or losing a life
+const HIT_INVULNERABILITY: f32 = 2.;
+
+/// Velocity and dash timers of the player, driven by [PlayerTuning]
+#[derive(Clone, Default)]
+pub struct PlayerMotion {
+    pub velocity: Vec2,
+    dash_time_left: f32,
+    dash_cooldown_left: f32,
+    invulnerable_time_left: f32,
+}
+
+impl PlayerMotion {
+    /// The player can not be hit during the start of a dash, or for a moment after being hit
+    pub fn is_invulnerable(&self) -> bool {
+        self.invulnerable_time_left > 0.
+    }
+
+    pub fn make_invulnerable(&mut self) {
+        self.invulnerable_time_left = self.invulnerable_time_left.max(HIT_INVULNERABILITY);
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum PlayerState {
     ShootingBullets,
     ShootingLaser,
@@ -23,48 +88,116 @@ impl Default for PlayerState {
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Bullet {
     pub direction: Vec3,
 }
 
 pub struct Laser;
 
+pub struct MainCamera;
+
 /// This plugin handles player related stuff like movement
 /// Player logic is only active during the State `GameState::Playing`
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Defeat>();
         app.add_system_set(
             SystemSet::on_enter(GameState::Playing)
                 .with_system(spawn_player.system())
                 .with_system(spawn_camera.system()),
         )
+        .add_system_set_to_stage(
+            SimulationStage::Tick,
+            SystemSet::new()
+                .with_system(
+                    move_player
+                        .system()
+                        .label("move_player")
+                        .after("gather_input"),
+                )
+                .with_system(shoot.system().after("gather_input"))
+                .with_system(
+                    bullet_movement
+                        .system()
+                        .label("move_bullets")
+                        .after("gather_input"),
+                )
+                .with_system(remove_defeated_players.system().after("player_collisions")),
+        )
+        .add_system_to_stage(SimulationStage::Interpolate, end_defeated_run.system())
         .add_system_set(
             SystemSet::on_update(GameState::Playing)
-                .with_system(move_player.system().after("gather_input"))
-                .with_system(shoot.system().after("gather_input"))
-                .with_system(bullet_movement.system().after("gather_input"))
+                .with_system(blink_invulnerable_player.system())
                 .with_system(laser_movement.system().after("gather_input")),
         );
     }
 }
 
 fn spawn_camera(mut commands: Commands) {
-    commands.spawn_bundle(OrthographicCameraBundle::new_2d());
+    let camera = OrthographicCameraBundle::new_2d();
+    let translation = camera.transform.translation;
+    commands
+        .spawn_bundle(camera)
+        .insert(MainCamera)
+        .insert(RunEntity)
+        .insert(CameraShake::default())
+        .insert(Interpolated::new(translation));
 }
 
-fn spawn_player(mut commands: Commands, texture_atlases: Res<TextureAtlases>) {
+fn spawn_player(mut commands: Commands, party: Res<Party>, texture_atlases: Res<TextureAtlases>) {
+    commands.insert_resource(Defeat::default());
     let texture_atlas_handle = &texture_atlases.main_sprite_sheet;
 
-    // Spawn Player
+    for (id, _) in party.players() {
+        // side by side in co-op
+        let x = match (party.len(), id) {
+            (1, _) => 0.,
+            (_, PlayerId::One) => -40.,
+            (_, PlayerId::Two) => 40.,
+        };
+        let translation = Vec3::new(x, 0., 1.);
+        // a moment to get going before enemies can hurt
+        let mut motion = PlayerMotion::default();
+        motion.make_invulnerable();
+        spawn_ship(
+            &mut commands,
+            texture_atlas_handle,
+            id,
+            Transform::from_translation(translation),
+            Player {
+                lives: STARTING_LIVES,
+                ..Default::default()
+            },
+            motion,
+        );
+    }
+}
+
+/// Spawns the ship of `id` with its weapons
+pub fn spawn_ship(
+    commands: &mut Commands,
+    texture_atlas_handle: &Handle<TextureAtlas>,
+    id: PlayerId,
+    transform: Transform,
+    player: Player,
+    motion: PlayerMotion,
+) {
     commands
         .spawn_bundle(SpriteSheetBundle {
             texture_atlas: texture_atlas_handle.clone(),
-            transform: Transform::from_translation(Vec3::new(0., 0., 1.)),
-            sprite: TextureAtlasSprite::new(188),
+            transform,
+            sprite: TextureAtlasSprite {
+                color: id.color(),
+                ..TextureAtlasSprite::new(188)
+            },
             ..Default::default()
         })
-        .insert(Player::default())
+        .insert(player)
+        .insert(id)
+        .insert(motion)
+        .insert(RunEntity)
+        .insert(Interpolated::new(transform.translation))
         .with_children(|parent| {
             parent
                 .spawn()
@@ -88,40 +221,91 @@ fn spawn_player(mut commands: Commands, texture_atlases: Res<TextureAtlases>) {
 }
 
 fn move_player(
-    time: Res<Time>,
     actions: Res<Actions>,
-    mut player_query: Query<&mut Transform, With<Player>>,
+    mut buffer: ResMut<InputBuffer>,
+    tuning_assets: Res<TuningAssets>,
+    tunings: Res<Assets<PlayerTuning>>,
+    mut player_query: Query<(&PlayerId, &mut Transform, &mut PlayerMotion), With<Player>>,
 ) {
-    if actions.player_movement.is_none() {
-        return;
+    let tuning = match tunings.get(&tuning_assets.player) {
+        Some(tuning) => tuning,
+        None => return,
+    };
+    let delta = TIMESTEP;
+    for (id, mut player_transform, mut motion) in player_query.iter_mut() {
+        let actions = actions.player(*id);
+        let input = actions.movement.unwrap_or(Vec2::ZERO);
+        motion.dash_time_left = (motion.dash_time_left - delta).max(0.);
+        motion.dash_cooldown_left = (motion.dash_cooldown_left - delta).max(0.);
+        motion.invulnerable_time_left = (motion.invulnerable_time_left - delta).max(0.);
+
+        if let Some(dash) = &tuning.dash {
+            // a dash pressed shortly before the cooldown ends still happens
+            if actions.dash && motion.dash_cooldown_left <= 0. {
+                buffer.consume(*id, GameControl::Dash, ACTION_BUFFER_SECONDS);
+                // dash in the steering direction, or forward when standing still
+                let direction = if input == Vec2::ZERO {
+                    Vec2::Y
+                } else {
+                    input.normalize()
+                };
+                motion.velocity = direction * dash.speed;
+                motion.dash_time_left = dash.duration;
+                motion.dash_cooldown_left = dash.cooldown;
+                motion.invulnerable_time_left = dash.invulnerability;
+            }
+        }
+
+        if motion.dash_time_left <= 0. {
+            let target = input * tuning.max_speed;
+            let rate = if input == Vec2::ZERO {
+                tuning.friction
+            } else {
+                tuning.acceleration
+            };
+            motion.velocity = move_towards(motion.velocity, target, rate * delta);
+        }
+        player_transform.translation += motion.velocity.extend(0.) * delta;
     }
-    let speed = 150.;
-    let movement = Vec3::new(
-        actions.player_movement.unwrap().x * speed * time.delta_seconds(),
-        actions.player_movement.unwrap().y * speed * time.delta_seconds(),
-        0.,
-    );
-    for mut player_transform in player_query.iter_mut() {
-        player_transform.translation += movement;
+}
+
+fn move_towards(current: Vec2, target: Vec2, max_step: f32) -> Vec2 {
+    let difference = target - current;
+    if difference.length() <= max_step {
+        target
+    } else {
+        current + difference.normalize() * max_step
+    }
+}
+
+/// Flicker the player sprite while it is invulnerable
+fn blink_invulnerable_player(
+    time: Res<Time>,
+    mut player_query: Query<(&PlayerMotion, &mut Visible), With<Player>>,
+) {
+    for (motion, mut visible) in player_query.iter_mut() {
+        visible.is_visible =
+            !motion.is_invulnerable() || (time.seconds_since_startup() * 20.) as u64 % 2 == 0;
     }
 }
 
 fn shoot(
     mut commands: Commands,
-    time: Res<Time>,
     actions: Res<Actions>,
+    mut buffer: ResMut<InputBuffer>,
     texture_atlases: ResMut<TextureAtlases>,
     //mut query: Query<(&Transform, &mut Player)>,
-    mut query: Query<(&Transform, &mut Player, &Children)>,
-    mut q_laser: Query<(&mut Laser, &Children)>,
+    mut query: Query<(&PlayerId, &Transform, &mut Player, &Children)>,
+    q_laser: Query<&Children, With<Laser>>,
     mut q_laser_sprite: Query<&mut Visible>,
 ) {
     let shot_delay = 0.2f32;
 
-    for (_, mut player, _) in query.iter_mut() {
-        player.shot_timer += time.delta().as_secs_f32();
+    for (id, _, mut player, _) in query.iter_mut() {
+        player.shot_timer += TIMESTEP;
 
-        if actions.player_switch_weapon {
+        if actions.player(*id).switch_weapon {
+            buffer.consume(*id, GameControl::SwitchWeapon, ACTION_BUFFER_SECONDS);
             player.state = match player.state {
                 PlayerState::ShootingBullets => PlayerState::ShootingLaser,
                 PlayerState::ShootingLaser => PlayerState::ShootingBullets,
@@ -129,25 +313,27 @@ fn shoot(
         }
     }
 
-    if actions.player_shoot {
-        let texture_atlas_handle = &texture_atlases.main_sprite_sheet;
-
-        for (transform, mut player, children) in query.iter_mut() {
+    let texture_atlas_handle = &texture_atlases.main_sprite_sheet;
+    for (id, transform, mut player, children) in query.iter_mut() {
+        let actions = actions.player(*id);
+        if actions.shoot {
             match player.state {
                 PlayerState::ShootingBullets => shoot_bullet_spray(
                     &mut player,
                     transform,
+                    aim_rotation(actions.aim),
                     &mut commands,
                     shot_delay,
                     texture_atlas_handle,
                 ), // TODO: also, delete/hide the laser ... maybe in a different system
                 PlayerState::ShootingLaser => {
-                    dbg!("lasors!");
                     for &child in children.iter() {
-                        let (mut laser, mut children) = q_laser.get_mut(child).unwrap();
-                        for &child in children.iter() {
-                            let mut visible = q_laser_sprite.get_mut(child).unwrap();
-                            visible.is_visible = false;
+                        // the player has other children too, like the thruster
+                        if let Ok(children) = q_laser.get(child) {
+                            for &child in children.iter() {
+                                let mut visible = q_laser_sprite.get_mut(child).unwrap();
+                                visible.is_visible = false;
+                            }
                         }
                     }
 
@@ -159,9 +345,18 @@ fn shoot(
     }
 }
 
+/// Rotation that turns the upwards facing ship and its weapons towards `aim`
+fn aim_rotation(aim: Option<Vec2>) -> Quat {
+    match aim {
+        Some(aim) => Quat::from_rotation_z(-aim.x.atan2(aim.y)),
+        None => Quat::IDENTITY,
+    }
+}
+
 fn shoot_bullet_spray(
     player: &mut Player,
     transform: &Transform,
+    rotation: Quat,
     commands: &mut Commands,
     shot_delay: f32,
     texture_atlas_handle: &Handle<TextureAtlas>,
@@ -180,36 +375,115 @@ fn shoot_bullet_spray(
         Vec3::new(-0.5, 0.5, 0.).normalize(),
     ];
     for dir in bullet_spread_directions {
-        commands
-            .spawn()
-            .insert_bundle(SpriteSheetBundle {
-                texture_atlas: texture_atlas_handle.clone(),
-                transform: transform.clone(),
-                sprite: TextureAtlasSprite::new(188 - 24),
-                ..Default::default()
-            })
-            .insert(Bullet { direction: dir });
+        spawn_bullet(
+            commands,
+            texture_atlas_handle,
+            Transform {
+                rotation,
+                ..transform.clone()
+            },
+            Bullet {
+                direction: rotation * dir,
+            },
+        );
     }
 }
 
-fn bullet_movement(mut commands: Commands, mut query: Query<(Entity, &mut Transform, &Bullet)>) {
+pub fn spawn_bullet(
+    commands: &mut Commands,
+    texture_atlas_handle: &Handle<TextureAtlas>,
+    transform: Transform,
+    bullet: Bullet,
+) {
+    commands
+        .spawn()
+        .insert_bundle(SpriteSheetBundle {
+            texture_atlas: texture_atlas_handle.clone(),
+            transform,
+            sprite: TextureAtlasSprite::new(188 - 24),
+            ..Default::default()
+        })
+        .insert(bullet)
+        .insert(RunEntity)
+        .insert(Interpolated::new(transform.translation));
+}
+
+fn bullet_movement(
+    mut commands: Commands,
+    camera_query: Query<&Transform, With<MainCamera>>,
+    mut query: Query<(Entity, &mut Transform, &Bullet), Without<MainCamera>>,
+) {
+    let camera = camera_query
+        .single()
+        .map(|camera| camera.translation)
+        .unwrap_or(Vec3::ZERO);
     for (entity, mut transform, bullet) in query.iter_mut() {
         transform.translation += bullet.direction * 16.;
 
-        if transform.translation.y > 280. {
+        let offset = (transform.translation - camera).abs();
+        if offset.x > 490. || offset.y > 280. {
             commands.entity(entity).despawn();
         }
     }
 }
 
 fn laser_movement(
-    mut q_player: Query<(&Player, &Children)>,
-    mut q_laser: Query<(&mut Laser, &mut Transform)>,
+    actions: Res<Actions>,
+    q_player: Query<(&PlayerId, &Children), With<Player>>,
+    mut q_laser: Query<&mut Transform, With<Laser>>,
 ) {
-    for (player, children) in q_player.iter_mut() {
+    for (id, children) in q_player.iter() {
+        let aim = actions.player(*id).aim;
         for &child in children.iter() {
-            let (mut laser, mut transform) = q_laser.get_mut(child).unwrap();
-            transform.rotate(Quat::from_rotation_z(0.01));
+            if let Ok(mut transform) = q_laser.get_mut(child) {
+                match aim {
+                    Some(_) => transform.rotation = aim_rotation(aim),
+                    None => transform.rotate(Quat::from_rotation_z(0.01)),
+                }
+            }
         }
     }
 }
+
+/// The tick in which the last ship was removed
+#[derive(Default)]
+struct Defeat(Option<u64>);
+
+/// Ships without lives are removed, and the tick in which no ship is left is remembered
+fn remove_defeated_players(
+    mut commands: Commands,
+    simulation: Res<SimulationTime>,
+    mut defeat: ResMut<Defeat>,
+    player_query: Query<(Entity, &Player)>,
+) {
+    let mut alive = 0;
+    for (entity, player) in player_query.iter() {
+        if player.lives == 0 {
+            commands.entity(entity).despawn_recursive();
+        } else {
+            alive += 1;
+        }
+    }
+    // a rollback runs the ticks again, which can bring the ships back
+    defeat.0 = if alive == 0 {
+        defeat.0.or(Some(simulation.tick()))
+    } else {
+        None
+    };
+}
+
+/// The run is over once the defeat can not be undone by a rollback any more
+fn end_defeated_run(
+    simulation: Res<SimulationTime>,
+    mut defeat: ResMut<Defeat>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let tick = match defeat.0 {
+        Some(tick) if simulation.is_confirmed(tick) => tick,
+        _ => return,
+    };
+    match state.set(GameState::Menu) {
+        Ok(()) => defeat.0 = None,
+        Err(error) => warn!("Ending the run after tick {} next frame: {:?}", tick, error),
+    }
+}
@@ -0,0 +1,538 @@
+use crate::actions::{Actions, PlayerActions};
+use crate::config;
+use crate::enemy::Enemy;
+use crate::party::Party;
+use crate::player::{Bullet, Player, PlayerId, PlayerMotion, MAX_PLAYERS};
+use crate::rng::{GameRng, NextRunSeed};
+use crate::simulation::{SimulationStage, SimulationTime};
+use crate::GameState;
+use anyhow::bail;
+use bevy::app::AppExit;
+use bevy::prelude::*;
+
+pub struct ReplayPlugin;
+
+/// This plugin records the [Actions] of every player in a run, one entry per simulation tick, to `last_run.replay`
+/// in the config directory. Starting the game with `--replay <file>` plays a recording back instead of
+/// reading the keyboard, gamepads or touch screen.
+/// Every tick also stores a checksum of the player, enemies and bullets at its start, so playback can
+/// tell when it no longer matches the recording.
+impl Plugin for ReplayPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<GameRng>()
+            .init_resource::<NextRunSeed>()
+            .insert_resource(Replay {
+                queued: replay_from_args(),
+                ..Default::default()
+            })
+            .add_system_set(
+                SystemSet::on_enter(GameState::Menu).with_system(start_queued_replay.system()),
+            )
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing)
+                    .with_system(start_run.system().label("start_run")),
+            )
+            .add_system_to_stage(
+                SimulationStage::Tick,
+                record_or_play_actions
+                    .system()
+                    .label("gather_input")
+                    .after("tick_actions")
+                    .after("net_input"),
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, save_on_exit.system())
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing)
+                    .with_system(finish_run.system().label("finish_run")),
+            );
+    }
+}
+
+const REPLAY_FILE: &str = "last_run.replay";
+const MAGIC: &[u8; 4] = b"RJRP";
+const VERSION: u16 = 2;
+
+#[derive(Default)]
+pub struct Replay {
+    mode: ReplayMode,
+    /// Loaded from the command line, played as soon as the menu shows up
+    queued: Option<ReplayFile>,
+}
+
+enum ReplayMode {
+    Off,
+    Recording(ReplayFile),
+    Playing {
+        file: ReplayFile,
+        tick: usize,
+        desynced: bool,
+    },
+}
+
+impl Default for ReplayMode {
+    fn default() -> Self {
+        ReplayMode::Off
+    }
+}
+
+impl Replay {
+    /// While playing back, input comes from the replay file instead of the [ActionsPlugin](crate::actions::ActionsPlugin)
+    pub fn is_playing(&self) -> bool {
+        matches!(self.mode, ReplayMode::Playing { .. })
+    }
+}
+
+/// The layout of a replay file, all numbers little endian:
+/// "RJRP", version: u16, seed: u64, player count: u8, tick count: u32, then the ticks, see [ReplayTick]
+struct ReplayFile {
+    seed: u64,
+    players: u8,
+    ticks: Vec<ReplayTick>,
+}
+
+/// One tick: the [TickActions] of every player and a `u32` checksum
+struct ReplayTick {
+    players: Vec<TickActions>,
+    checksum: u32,
+}
+
+/// The actions of one player in one tick, as stored in replays and sent to the other player online.
+/// A flags byte, then movement and aim as two `i8` each if their flag is set.
+#[derive(Clone, Default, PartialEq)]
+pub struct TickActions {
+    shoot: bool,
+    switch_weapon: bool,
+    dash: bool,
+    movement: Option<[i8; 2]>,
+    aim: Option<[i8; 2]>,
+}
+
+const SHOOT: u8 = 1;
+const SWITCH_WEAPON: u8 = 1 << 1;
+const DASH: u8 = 1 << 2;
+const MOVEMENT: u8 = 1 << 3;
+const AIM: u8 = 1 << 4;
+
+fn quantize(vector: Vec2) -> [i8; 2] {
+    let component = |value: f32| (value.clamp(-1., 1.) * 127.).round() as i8;
+    [component(vector.x), component(vector.y)]
+}
+
+fn dequantize(vector: [i8; 2]) -> Vec2 {
+    Vec2::new(vector[0] as f32, vector[1] as f32) / 127.
+}
+
+impl TickActions {
+    pub fn from_actions(actions: &PlayerActions) -> Self {
+        TickActions {
+            shoot: actions.shoot,
+            switch_weapon: actions.switch_weapon,
+            dash: actions.dash,
+            movement: actions.movement.map(quantize),
+            aim: actions.aim.map(quantize),
+        }
+    }
+
+    pub fn apply(&self, actions: &mut PlayerActions) {
+        actions.shoot = self.shoot;
+        actions.switch_weapon = self.switch_weapon;
+        actions.dash = self.dash;
+        actions.movement = self.movement.map(dequantize);
+        actions.aim = self.aim.map(dequantize);
+    }
+
+    /// The same input, without presses that should only trigger once
+    pub fn held(&self) -> Self {
+        TickActions {
+            switch_weapon: false,
+            dash: false,
+            ..self.clone()
+        }
+    }
+
+    pub fn write(&self, bytes: &mut Vec<u8>) {
+        let flag = |set: bool, flag: u8| if set { flag } else { 0 };
+        bytes.push(
+            flag(self.shoot, SHOOT)
+                | flag(self.switch_weapon, SWITCH_WEAPON)
+                | flag(self.dash, DASH)
+                | flag(self.movement.is_some(), MOVEMENT)
+                | flag(self.aim.is_some(), AIM),
+        );
+        for vector in self.movement.iter().chain(self.aim.iter()) {
+            bytes.extend(vector.iter().map(|component| *component as u8));
+        }
+    }
+
+    pub fn read(reader: &mut Reader) -> anyhow::Result<Self> {
+        let flags = reader.byte()?;
+        let mut vector = |flag: u8| -> anyhow::Result<Option<[i8; 2]>> {
+            if flags & flag == 0 {
+                return Ok(None);
+            }
+            Ok(Some([reader.byte()? as i8, reader.byte()? as i8]))
+        };
+        let movement = vector(MOVEMENT)?;
+        let aim = vector(AIM)?;
+        Ok(TickActions {
+            shoot: flags & SHOOT != 0,
+            switch_weapon: flags & SWITCH_WEAPON != 0,
+            dash: flags & DASH != 0,
+            movement,
+            aim,
+        })
+    }
+}
+
+impl ReplayTick {
+    fn from_actions(actions: &Actions, players: u8) -> Self {
+        ReplayTick {
+            players: PlayerId::ALL
+                .iter()
+                .take(players as usize)
+                .map(|id| TickActions::from_actions(actions.player(*id)))
+                .collect(),
+            checksum: 0,
+        }
+    }
+
+    fn apply(&self, actions: &mut Actions) {
+        for (id, player) in PlayerId::ALL.iter().zip(self.players.iter()) {
+            player.apply(actions.player_mut(*id));
+        }
+        actions.pause = false;
+    }
+}
+
+impl ReplayFile {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&self.seed.to_le_bytes());
+        bytes.push(self.players);
+        bytes.extend_from_slice(&(self.ticks.len() as u32).to_le_bytes());
+        for tick in self.ticks.iter() {
+            for player in tick.players.iter() {
+                player.write(&mut bytes);
+            }
+            bytes.extend_from_slice(&tick.checksum.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> anyhow::Result<Self> {
+        let mut reader = Reader::new(bytes);
+        if reader.take(4)? != MAGIC {
+            bail!("not a replay file");
+        }
+        let version = u16::from_le_bytes([reader.byte()?, reader.byte()?]);
+        if version != VERSION {
+            bail!("unsupported replay version {}", version);
+        }
+        let seed = u64::from_le_bytes(array(reader.take(8)?));
+        let players = reader.byte()?;
+        if players == 0 || players as usize > MAX_PLAYERS {
+            bail!("unsupported player count {}", players);
+        }
+        let tick_count = u32::from_le_bytes(array(reader.take(4)?));
+        // every tick takes at least 5 bytes, so a broken count can not allocate more than the file
+        let mut ticks = Vec::with_capacity((tick_count as usize).min(bytes.len() / 5));
+        for _ in 0..tick_count {
+            let mut tick = ReplayTick {
+                players: Vec::with_capacity(players as usize),
+                checksum: 0,
+            };
+            for _ in 0..players {
+                tick.players.push(TickActions::read(&mut reader)?);
+            }
+            tick.checksum = u32::from_le_bytes(array(reader.take(4)?));
+            ticks.push(tick);
+        }
+        Ok(ReplayFile {
+            seed,
+            players,
+            ticks,
+        })
+    }
+}
+
+/// Reads little endian data from the front of a byte slice
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes }
+    }
+
+    pub fn take(&mut self, count: usize) -> anyhow::Result<&'a [u8]> {
+        if self.bytes.len() < count {
+            bail!("data is truncated");
+        }
+        let (taken, rest) = self.bytes.split_at(count);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    pub fn byte(&mut self) -> anyhow::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+}
+
+pub fn array<const N: usize>(bytes: &[u8]) -> [u8; N] {
+    let mut array = [0; N];
+    array.copy_from_slice(bytes);
+    array
+}
+
+fn replay_from_args() -> Option<ReplayFile> {
+    let path = std::env::args()
+        .skip_while(|arg| arg != "--replay")
+        .nth(1)?;
+    let replay = std::fs::read(&path)
+        .map_err(anyhow::Error::from)
+        .and_then(|bytes| ReplayFile::from_bytes(&bytes));
+    match replay {
+        Ok(replay) => Some(replay),
+        Err(error) => {
+            warn!("Failed to load replay {}: {}", path, error);
+            None
+        }
+    }
+}
+
+fn start_queued_replay(
+    replay: Res<Replay>,
+    mut party: ResMut<Party>,
+    mut state: ResMut<State<GameState>>,
+) {
+    if let Some(file) = &replay.queued {
+        *party = Party::with_players(file.players as usize);
+        if let Err(error) = state.set(GameState::Playing) {
+            warn!("Not starting the replay: {:?}", error);
+        }
+    }
+}
+
+fn start_run(
+    mut replay: ResMut<Replay>,
+    party: Res<Party>,
+    mut rng: ResMut<GameRng>,
+    mut next_seed: ResMut<NextRunSeed>,
+) {
+    replay.mode = match replay.queued.take() {
+        Some(file) => {
+            info!("Playing replay with {} ticks", file.ticks.len());
+            *rng = GameRng::from_seed(file.seed);
+            ReplayMode::Playing {
+                file,
+                tick: 0,
+                desynced: false,
+            }
+        }
+        None => {
+            *rng = next_seed
+                .0
+                .take()
+                .map(GameRng::from_seed)
+                .unwrap_or_default();
+            ReplayMode::Recording(ReplayFile {
+                seed: rng.seed(),
+                players: party.len() as u8,
+                ticks: vec![],
+            })
+        }
+    };
+}
+
+fn record_or_play_actions(
+    simulation: Res<SimulationTime>,
+    mut replay: ResMut<Replay>,
+    mut actions: ResMut<Actions>,
+    mut state: ResMut<State<GameState>>,
+    player_query: Query<(&Transform, &PlayerMotion, &Player)>,
+    enemy_query: Query<(&Transform, &Enemy)>,
+    bullet_query: Query<&Bullet>,
+) {
+    if let ReplayMode::Off = replay.mode {
+        return;
+    }
+    let checksum = checksum(&player_query, &enemy_query, &bullet_query);
+    match &mut replay.mode {
+        ReplayMode::Off => (),
+        ReplayMode::Recording(file) => {
+            let mut tick = ReplayTick::from_actions(&actions, file.players);
+            tick.checksum = checksum;
+            // play with the same precision the file has, or a replay would drift
+            tick.apply(&mut actions);
+            // ticks that run again after a rollback replace what was recorded for them
+            file.ticks.truncate(simulation.tick() as usize);
+            file.ticks.push(tick);
+        }
+        ReplayMode::Playing {
+            file,
+            tick,
+            desynced,
+        } => match file.ticks.get(*tick) {
+            Some(recorded) => {
+                if !*desynced && recorded.checksum != checksum {
+                    warn!("Replay desynced at tick {}", tick);
+                    *desynced = true;
+                }
+                recorded.apply(&mut actions);
+                *tick += 1;
+            }
+            // another transition this frame, like the end of the run, goes first
+            None => {
+                if state.set(GameState::Menu).is_ok() {
+                    info!("Replay finished");
+                    replay.mode = ReplayMode::Off;
+                }
+            }
+        },
+    }
+}
+
+/// FNV-1a over the state that has to match between a recording and its playback
+fn checksum(
+    player_query: &Query<(&Transform, &PlayerMotion, &Player)>,
+    enemy_query: &Query<(&Transform, &Enemy)>,
+    bullet_query: &Query<&Bullet>,
+) -> u32 {
+    let mut checksum: u32 = 0x811c_9dc5;
+    let mut hash = |value: u32| {
+        for byte in value.to_le_bytes().iter() {
+            checksum = (checksum ^ *byte as u32).wrapping_mul(0x0100_0193);
+        }
+    };
+    for (transform, motion, player) in player_query.iter() {
+        hash(transform.translation.x.to_bits());
+        hash(transform.translation.y.to_bits());
+        hash(motion.velocity.x.to_bits());
+        hash(motion.velocity.y.to_bits());
+        hash(player.lives);
+    }
+    for (transform, enemy) in enemy_query.iter() {
+        hash(transform.translation.x.to_bits());
+        hash(transform.translation.y.to_bits());
+        hash(enemy.health);
+    }
+    hash(bullet_query.iter().count() as u32);
+    checksum
+}
+
+fn finish_run(mut replay: ResMut<Replay>) {
+    save_recording(&replay);
+    replay.mode = ReplayMode::Off;
+}
+
+/// Quitting in the middle of a run does not leave `GameState::Playing`
+fn save_on_exit(mut replay: ResMut<Replay>, mut exit_events: EventReader<AppExit>) {
+    if exit_events.iter().next().is_some() {
+        save_recording(&replay);
+        replay.mode = ReplayMode::Off;
+    }
+}
+
+fn save_recording(replay: &Replay) {
+    if let ReplayMode::Recording(file) = &replay.mode {
+        config::save_bytes(REPLAY_FILE, &file.to_bytes());
+        if let Some(path) = config::config_path(REPLAY_FILE) {
+            info!("Saved replay of {} ticks to {:?}", file.ticks.len(), path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_file() -> ReplayFile {
+        let idle = TickActions::default();
+        let busy = TickActions {
+            shoot: true,
+            switch_weapon: true,
+            dash: true,
+            movement: Some([127, -128]),
+            aim: Some([0, 64]),
+        };
+        let moving = TickActions {
+            movement: Some([-5, 90]),
+            ..Default::default()
+        };
+        ReplayFile {
+            seed: 0x0123_4567_89ab_cdef,
+            players: 2,
+            ticks: vec![
+                ReplayTick {
+                    players: vec![idle.clone(), busy.clone()],
+                    checksum: 7,
+                },
+                ReplayTick {
+                    players: vec![moving, idle],
+                    checksum: u32::MAX,
+                },
+                ReplayTick {
+                    players: vec![busy.held(), busy],
+                    checksum: 0,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn tick_actions_survive_a_round_trip() {
+        for actions in sample_file()
+            .ticks
+            .iter()
+            .flat_map(|tick| tick.players.iter())
+        {
+            let mut bytes = vec![];
+            actions.write(&mut bytes);
+            let mut reader = Reader::new(&bytes);
+            assert!(TickActions::read(&mut reader).unwrap() == *actions);
+            assert!(reader.byte().is_err(), "not all bytes were read");
+        }
+    }
+
+    #[test]
+    fn replay_files_survive_a_round_trip() {
+        let file = sample_file();
+        let read = ReplayFile::from_bytes(&file.to_bytes()).unwrap();
+        assert_eq!(read.seed, file.seed);
+        assert_eq!(read.players, file.players);
+        assert_eq!(read.ticks.len(), file.ticks.len());
+        for (read, tick) in read.ticks.iter().zip(file.ticks.iter()) {
+            assert!(read.players == tick.players);
+            assert_eq!(read.checksum, tick.checksum);
+        }
+    }
+
+    #[test]
+    fn replay_files_with_bad_magic_are_rejected() {
+        let mut bytes = sample_file().to_bytes();
+        bytes[0] = b'X';
+        assert!(ReplayFile::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn replay_files_with_another_version_are_rejected() {
+        let mut bytes = sample_file().to_bytes();
+        bytes[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(ReplayFile::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn truncated_replay_files_are_rejected() {
+        let bytes = sample_file().to_bytes();
+        for length in 0..bytes.len() {
+            assert!(
+                ReplayFile::from_bytes(&bytes[..length]).is_err(),
+                "{} of {} bytes were accepted",
+                length,
+                bytes.len()
+            );
+        }
+    }
+}
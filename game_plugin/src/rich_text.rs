@@ -0,0 +1,433 @@
+use bevy::prelude::*;
+use rand::Rng;
+use std::f32::consts::TAU;
+
+pub struct RichTextPlugin;
+
+/// This plugin lays out [RichText], text written in a small markup language like
+/// `Score: [color=gold]1200[/color] [wave]!![/wave]`. Colours are the names of the `Color`
+/// constants in snake case or hex codes, `[wave]`, `[shake]` and `[rainbow]` animate every letter.
+/// Tags can be nested, `[[` is a literal `[` and anything that is not a known tag stays as it is.
+impl Plugin for RichTextPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system(build_rich_text.system().label("build_rich_text"))
+            .add_system(animate_glyphs.system().after("build_rich_text"));
+    }
+}
+
+/// Height of the wave in font sizes
+const WAVE_HEIGHT: f32 = 0.12;
+/// Radians per second
+const WAVE_SPEED: f32 = 8.;
+/// Radians between neighbouring letters
+const WAVE_SPACING: f32 = 0.7;
+/// Furthest a letter shakes away from its place, in font sizes
+const SHAKE_DISTANCE: f32 = 0.05;
+const RAINBOW_SPEED: f32 = 3.;
+const RAINBOW_SPACING: f32 = 0.5;
+
+const EFFECTS: [&str; 3] = ["wave", "shake", "rainbow"];
+
+/// The animations of a [Span]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Effects {
+    pub wave: bool,
+    pub shake: bool,
+    pub rainbow: bool,
+}
+
+impl Effects {
+    fn any(&self) -> bool {
+        self.wave || self.shake || self.rainbow
+    }
+}
+
+/// A piece of markup with the same colour and effects
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub text: String,
+    /// `None` keeps the colour of the text style
+    pub color: Option<Color>,
+    pub effects: Effects,
+}
+
+enum Tag<'a> {
+    Open(&'a str, Option<Color>),
+    Close(&'a str),
+}
+
+fn parse_tag(content: &str) -> Option<Tag<'_>> {
+    if let Some(color) = content.strip_prefix("color=") {
+        return parse_color(color).map(|color| Tag::Open("color", Some(color)));
+    }
+    let (name, closing) = match content.strip_prefix('/') {
+        Some(name) => (name, true),
+        None => (content, false),
+    };
+    if closing && name == "color" {
+        Some(Tag::Close(name))
+    } else if !EFFECTS.contains(&name) {
+        None
+    } else if closing {
+        Some(Tag::Close(name))
+    } else {
+        Some(Tag::Open(name, None))
+    }
+}
+
+fn parse_color(color: &str) -> Option<Color> {
+    if let Some(hex) = color.strip_prefix('#') {
+        return Color::hex(hex).ok();
+    }
+    let color = match color {
+        "black" => Color::BLACK,
+        "blue" => Color::BLUE,
+        "crimson" => Color::CRIMSON,
+        "cyan" => Color::CYAN,
+        "gold" => Color::GOLD,
+        "gray" => Color::GRAY,
+        "green" => Color::GREEN,
+        "lime_green" => Color::LIME_GREEN,
+        "orange" => Color::ORANGE,
+        "orange_red" => Color::ORANGE_RED,
+        "pink" => Color::PINK,
+        "purple" => Color::PURPLE,
+        "red" => Color::RED,
+        "salmon" => Color::SALMON,
+        "silver" => Color::SILVER,
+        "tomato" => Color::TOMATO,
+        "turquoise" => Color::TURQUOISE,
+        "violet" => Color::VIOLET,
+        "white" => Color::WHITE,
+        "yellow" => Color::YELLOW,
+        _ => return None,
+    };
+    Some(color)
+}
+
+/// Splits markup into spans of the same colour and effects. Tags that are still open
+/// at the end apply to the rest of the text, closing tags without an open one are kept as text.
+pub fn parse(markup: &str) -> Vec<Span> {
+    let mut spans = vec![];
+    // the open tags, innermost last
+    let mut open: Vec<(&str, Option<Color>)> = vec![];
+    let mut text = String::new();
+    let mut rest = markup;
+
+    let mut flush = |text: &mut String, open: &[(&str, Option<Color>)]| {
+        if text.is_empty() {
+            return;
+        }
+        let has = |effect| open.iter().any(|(name, _)| *name == effect);
+        spans.push(Span {
+            text: std::mem::take(text),
+            color: open.iter().rev().find_map(|(_, color)| *color),
+            effects: Effects {
+                wave: has("wave"),
+                shake: has("shake"),
+                rainbow: has("rainbow"),
+            },
+        });
+    };
+    while let Some(start) = rest.find('[') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("[[") {
+            text.push('[');
+            rest = after;
+            continue;
+        }
+        let tag = rest
+            .find(']')
+            .and_then(|end| Some((parse_tag(&rest[1..end])?, end)));
+        match tag {
+            Some((Tag::Open(name, color), end)) => {
+                flush(&mut text, &open);
+                open.push((name, color));
+                rest = &rest[end + 1..];
+            }
+            Some((Tag::Close(name), end)) => {
+                match open.iter().rposition(|(open, _)| *open == name) {
+                    Some(index) => {
+                        flush(&mut text, &open);
+                        open.remove(index);
+                    }
+                    None => text.push_str(&rest[..=end]),
+                }
+                rest = &rest[end + 1..];
+            }
+            None => {
+                text.push('[');
+                rest = &rest[1..];
+            }
+        }
+    }
+    text.push_str(rest);
+    flush(&mut text, &open);
+    spans
+}
+
+/// The sections of a plain `Text` showing `markup` in `style`, without the effects
+pub fn sections(markup: &str, style: &TextStyle) -> Vec<TextSection> {
+    parse(markup)
+        .into_iter()
+        .map(|span| TextSection {
+            value: span.text,
+            style: TextStyle {
+                color: span.color.unwrap_or(style.color),
+                ..style.clone()
+            },
+        })
+        .collect()
+}
+
+/// Shows its markup as children of the node it is on. Changing the markup builds them again.
+pub struct RichText {
+    pub markup: String,
+    pub style: TextStyle,
+}
+
+#[derive(Bundle)]
+pub struct RichTextBundle {
+    #[bundle]
+    pub node: NodeBundle,
+    pub rich_text: RichText,
+}
+
+impl RichTextBundle {
+    /// The node only lays out the text, it is not drawn itself
+    pub fn new(markup: impl Into<String>, text_style: TextStyle, style: Style) -> Self {
+        RichTextBundle {
+            node: NodeBundle {
+                style: Style {
+                    align_items: AlignItems::FlexEnd,
+                    ..style
+                },
+                visible: Visible {
+                    is_visible: false,
+                    is_transparent: true,
+                },
+                ..Default::default()
+            },
+            rich_text: RichText {
+                markup: markup.into(),
+                style: text_style,
+            },
+        }
+    }
+}
+
+/// A letter with effects, it gets a node of its own to move around
+struct Glyph {
+    effects: Effects,
+    /// Position among the animated letters of the text, so neighbours are a little apart in the animation
+    index: usize,
+}
+
+/// Letters without effects are drawn together, every animated letter on its own
+fn build_rich_text(
+    mut commands: Commands,
+    text_query: Query<(Entity, &RichText, Option<&Children>), Changed<RichText>>,
+) {
+    for (entity, rich_text, children) in text_query.iter() {
+        for child in children.iter().flat_map(|children| children.iter()) {
+            commands.entity(*child).despawn_recursive();
+        }
+        let style = &rich_text.style;
+        commands.entity(entity).with_children(|parent| {
+            let mut glyphs = 0;
+            let mut still = vec![];
+            let flush = |parent: &mut ChildBuilder, still: &mut Vec<TextSection>| {
+                if still.is_empty() {
+                    return;
+                }
+                parent.spawn_bundle(TextBundle {
+                    text: Text {
+                        sections: std::mem::take(still),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                });
+            };
+            for span in parse(&rich_text.markup) {
+                let span_style = TextStyle {
+                    color: span.color.unwrap_or(style.color),
+                    ..style.clone()
+                };
+                if !span.effects.any() {
+                    still.push(TextSection {
+                        value: span.text,
+                        style: span_style,
+                    });
+                    continue;
+                }
+                flush(parent, &mut still);
+                for letter in span.text.chars() {
+                    parent
+                        .spawn_bundle(TextBundle {
+                            text: Text::with_section(
+                                letter.to_string(),
+                                span_style.clone(),
+                                Default::default(),
+                            ),
+                            ..Default::default()
+                        })
+                        .insert(Glyph {
+                            effects: span.effects,
+                            index: glyphs,
+                        });
+                    glyphs += 1;
+                }
+            }
+            flush(parent, &mut still);
+        });
+    }
+}
+
+/// Cycles through the colours, every channel is a sine a third of a cycle apart
+fn rainbow(phase: f32) -> Color {
+    let channel = |offset: f32| (phase + offset * TAU).sin() / 2. + 0.5;
+    Color::rgb(channel(0.), channel(1. / 3.), channel(2. / 3.))
+}
+
+fn animate_glyphs(time: Res<Time>, mut glyph_query: Query<(&Glyph, &mut Style, &mut Text)>) {
+    let seconds = time.seconds_since_startup() as f32;
+    let mut rng = rand::thread_rng();
+    for (glyph, mut style, mut text) in glyph_query.iter_mut() {
+        let index = glyph.index as f32;
+        let font_size = text.sections[0].style.font_size;
+        if glyph.effects.wave || glyph.effects.shake {
+            let mut offset = Vec2::ZERO;
+            if glyph.effects.wave {
+                offset.y += (seconds * WAVE_SPEED - index * WAVE_SPACING).sin() * WAVE_HEIGHT;
+            }
+            if glyph.effects.shake {
+                offset +=
+                    Vec2::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)) * SHAKE_DISTANCE;
+            }
+            style.position.left = Val::Px(offset.x * font_size);
+            style.position.bottom = Val::Px(offset.y * font_size);
+        }
+        if glyph.effects.rainbow {
+            text.sections[0].style.color =
+                rainbow(seconds * RAINBOW_SPEED - index * RAINBOW_SPACING);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(text: &str, color: Option<Color>, effects: Effects) -> Span {
+        Span {
+            text: text.to_string(),
+            color,
+            effects,
+        }
+    }
+
+    fn plain(text: &str) -> Span {
+        span(text, None, Effects::default())
+    }
+
+    const WAVE: Effects = Effects {
+        wave: true,
+        shake: false,
+        rainbow: false,
+    };
+
+    #[test]
+    fn plain_text_is_one_span() {
+        assert_eq!(parse("Score: 1200"), vec![plain("Score: 1200")]);
+        assert_eq!(parse(""), vec![]);
+    }
+
+    #[test]
+    fn nested_tags_add_up() {
+        assert_eq!(
+            parse("a[color=red]b[wave]c[color=blue]d[/color]e[/wave]f[/color]g"),
+            vec![
+                plain("a"),
+                span("b", Some(Color::RED), Effects::default()),
+                span("c", Some(Color::RED), WAVE),
+                span("d", Some(Color::BLUE), WAVE),
+                span("e", Some(Color::RED), WAVE),
+                span("f", Some(Color::RED), Effects::default()),
+                plain("g"),
+            ]
+        );
+        let all = Effects {
+            wave: true,
+            shake: true,
+            rainbow: true,
+        };
+        assert_eq!(
+            parse("[wave][shake][rainbow]!![/rainbow][/shake][/wave]"),
+            vec![span("!!", None, all)]
+        );
+    }
+
+    #[test]
+    fn double_brackets_are_a_literal_bracket() {
+        assert_eq!(parse("[[wave]"), vec![plain("[wave]")]);
+        assert_eq!(parse("[wave][[1][/wave]"), vec![span("[1]", None, WAVE)]);
+    }
+
+    #[test]
+    fn unknown_tags_stay_as_text() {
+        assert_eq!(parse("[bold]x[/bold]"), vec![plain("[bold]x[/bold]")]);
+        assert_eq!(parse("[wave"), vec![plain("[wave")]);
+    }
+
+    #[test]
+    fn unclosed_tags_apply_to_the_rest() {
+        assert_eq!(parse("a[wave]bc"), vec![plain("a"), span("bc", None, WAVE)]);
+    }
+
+    #[test]
+    fn stray_closing_tags_stay_as_text() {
+        assert_eq!(parse("a[/wave]b"), vec![plain("a[/wave]b")]);
+        assert_eq!(
+            parse("[wave]a[/color]b[/wave]"),
+            vec![span("a[/color]b", None, WAVE)]
+        );
+    }
+
+    #[test]
+    fn tags_are_recognized() {
+        assert!(matches!(parse_tag("wave"), Some(Tag::Open("wave", None))));
+        assert!(matches!(parse_tag("/shake"), Some(Tag::Close("shake"))));
+        assert!(matches!(parse_tag("/color"), Some(Tag::Close("color"))));
+        assert!(matches!(
+            parse_tag("color=gold"),
+            Some(Tag::Open("color", Some(color))) if color == Color::GOLD
+        ));
+        assert!(parse_tag("color").is_none());
+        assert!(parse_tag("color=").is_none());
+        assert!(parse_tag("/bold").is_none());
+        assert!(parse_tag("").is_none());
+    }
+
+    #[test]
+    fn colors_are_names_or_hex_codes() {
+        assert_eq!(parse_color("lime_green"), Some(Color::LIME_GREEN));
+        assert_eq!(parse_color("white"), Some(Color::WHITE));
+        assert_eq!(parse_color("#ff0000"), Some(Color::rgb(1., 0., 0.)));
+        assert_eq!(parse_color("#00ff0080"), Color::hex("00ff0080").ok());
+        assert_eq!(parse_color("#fff"), Some(Color::WHITE));
+        assert_eq!(
+            parse("[color=#0000ff]x"),
+            vec![span("x", Some(Color::rgb(0., 0., 1.)), Effects::default())]
+        );
+    }
+
+    #[test]
+    fn bad_colors_are_rejected() {
+        assert_eq!(parse_color("LimeGreen"), None);
+        assert_eq!(parse_color("#12345"), None);
+        assert_eq!(parse_color("#gg0000"), None);
+        assert_eq!(parse_color("ff0000"), None);
+        assert_eq!(parse("[color=nope]x"), vec![plain("[color=nope]x")]);
+    }
+}
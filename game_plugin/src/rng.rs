@@ -0,0 +1,53 @@
+use rand::rngs::StdRng;
+use rand::{Error, RngCore, SeedableRng};
+
+/// Random numbers for everything that changes how a run plays out.
+/// It is seeded at the start of every run, so a replay with the same seed rolls the same numbers.
+/// Purely visual randomness, like particles and camera shake, should keep using `rand::thread_rng`.
+#[derive(Clone)]
+pub struct GameRng {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl GameRng {
+    pub fn from_seed(seed: u64) -> Self {
+        GameRng {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+/// Seed for the next run, e.g. one agreed on with the other player online.
+/// Without one, every run starts with a random seed.
+#[derive(Default)]
+pub struct NextRunSeed(pub Option<u64>);
+
+impl Default for GameRng {
+    fn default() -> Self {
+        GameRng::from_seed(rand::random())
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
@@ -0,0 +1,301 @@
+use crate::config;
+use crate::level::LevelData;
+use crate::loading::{FontAssets, LevelAssets};
+use crate::replay::Replay;
+use crate::simulation::{SimulationTime, TIMESTEP};
+use crate::GameState;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+pub struct RunTimerPlugin;
+
+/// This plugin times every run and records a split whenever the camera reaches a split of the level.
+/// The time is in-game time counted in simulation ticks, so it stops while paused and
+/// a replay shows the same times as the run it recorded.
+/// Splits are compared against the personal best, which is saved to `personal_best.toml`
+/// whenever a run reaches the last split faster.
+impl Plugin for RunTimerPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_event::<SplitReached>()
+            .insert_resource(config::load::<PersonalBest>(PERSONAL_BEST_FILE).unwrap_or_default())
+            .init_resource::<RunTimer>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing).with_system(start_timer.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(record_splits.system().label("record_splits"))
+                    .with_system(update_timer_text.system().after("record_splits")),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing).with_system(hide_timer.system()),
+            );
+    }
+}
+
+const PERSONAL_BEST_FILE: &str = "personal_best.toml";
+
+const AHEAD_COLOR: Color = Color::rgb(0.3, 0.9, 0.4);
+const BEHIND_COLOR: Color = Color::rgb(0.9, 0.3, 0.3);
+const TEXT_COLOR: Color = Color::rgb(0.9, 0.9, 0.9);
+
+/// Sent when the camera reaches a split of the level
+pub struct SplitReached {
+    /// Index into the splits of the level
+    pub index: usize,
+    /// Simulation tick the split was reached in
+    pub tick: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Split {
+    name: String,
+    /// Seconds from the start of the run
+    time: f32,
+}
+
+/// The splits of the fastest finished run
+#[derive(Default, Serialize, Deserialize)]
+struct PersonalBest {
+    splits: Vec<Split>,
+}
+
+impl PersonalBest {
+    /// Time of the split with this name and position, if the personal best has it.
+    /// Splits of a level that changed since are not compared.
+    fn time(&self, index: usize, name: &str) -> Option<f32> {
+        self.splits
+            .get(index)
+            .filter(|split| split.name == name)
+            .map(|split| split.time)
+    }
+
+    fn finish(&self) -> Option<f32> {
+        self.splits.last().map(|split| split.time)
+    }
+}
+
+#[derive(Default)]
+struct RunTimer {
+    /// Names of the splits of the current level
+    names: Vec<String>,
+    /// Splits recorded so far in the current run
+    splits: Vec<Split>,
+}
+
+impl RunTimer {
+    fn finished(&self) -> bool {
+        !self.names.is_empty() && self.splits.len() == self.names.len()
+    }
+}
+
+struct RunTimerHud;
+
+struct TimerText;
+
+/// One line per split of the level
+struct SplitText(usize);
+
+pub fn format_time(seconds: f32) -> String {
+    let minutes = (seconds / 60.).floor();
+    format!("{}:{:05.2}", minutes, seconds - minutes * 60.)
+}
+
+fn format_delta(delta: f32) -> String {
+    format!("{}{:.2}", if delta < 0. { "-" } else { "+" }, delta.abs())
+}
+
+fn delta_color(delta: f32) -> Color {
+    if delta <= 0. {
+        AHEAD_COLOR
+    } else {
+        BEHIND_COLOR
+    }
+}
+
+fn start_timer(
+    mut commands: Commands,
+    mut timer: ResMut<RunTimer>,
+    font_assets: Res<FontAssets>,
+    level_assets: Res<LevelAssets>,
+    levels: Res<Assets<LevelData>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    // without a level the run is still timed, just without splits
+    let tilemap = levels
+        .get(&level_assets.level_1)
+        .and_then(|level| level.tilemap.as_ref());
+    *timer = RunTimer {
+        names: tilemap.map_or(vec![], |tilemap| {
+            tilemap
+                .splits
+                .iter()
+                .map(|split| split.name.clone())
+                .collect()
+        }),
+        splits: vec![],
+    };
+    let style = |font_size| TextStyle {
+        font: font_assets.share_tech_mono.clone(),
+        font_size,
+        color: TEXT_COLOR,
+    };
+
+    // a row across the top of the window keeps the timer centered at any window size
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(0.),
+                    right: Val::Px(0.),
+                    top: Val::Px(25.),
+                    ..Default::default()
+                },
+                justify_content: JustifyContent::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(RunTimerHud)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle {
+                    text: Text::with_section(format_time(0.), style(25.), Default::default()),
+                    ..Default::default()
+                })
+                .insert(TimerText);
+        });
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    right: Val::Px(20.),
+                    top: Val::Px(20.),
+                    ..Default::default()
+                },
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::FlexEnd,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(RunTimerHud)
+        .with_children(|parent| {
+            for index in 0..timer.names.len() {
+                parent
+                    .spawn_bundle(TextBundle {
+                        text: Text {
+                            sections: vec![
+                                TextSection {
+                                    value: String::new(),
+                                    style: style(18.),
+                                },
+                                TextSection {
+                                    value: String::new(),
+                                    style: style(18.),
+                                },
+                            ],
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    })
+                    .insert(SplitText(index));
+            }
+        });
+}
+
+fn record_splits(
+    mut events: EventReader<SplitReached>,
+    mut timer: ResMut<RunTimer>,
+    mut personal_best: ResMut<PersonalBest>,
+    replay: Res<Replay>,
+) {
+    for event in events.iter() {
+        // only the next split of the level counts
+        if event.index != timer.splits.len() || event.index >= timer.names.len() {
+            continue;
+        }
+        let split = Split {
+            name: timer.names[event.index].clone(),
+            time: event.tick as f32 * TIMESTEP,
+        };
+        timer.splits.push(split);
+        if !timer.finished() || replay.is_playing() {
+            continue;
+        }
+        let time = timer.splits.last().map_or(0., |split| split.time);
+        let faster = !matches!(personal_best.finish(), Some(best) if best <= time);
+        if faster || personal_best.splits.len() != timer.splits.len() {
+            personal_best.splits = timer.splits.clone();
+            config::save(PERSONAL_BEST_FILE, &*personal_best);
+        }
+    }
+}
+
+fn update_timer_text(
+    timer: Res<RunTimer>,
+    personal_best: Res<PersonalBest>,
+    simulation_time: Res<SimulationTime>,
+    mut timer_query: Query<&mut Text, (With<TimerText>, Without<SplitText>)>,
+    mut split_query: Query<(&SplitText, &mut Text), Without<TimerText>>,
+) {
+    let now = if timer.finished() {
+        timer.splits.last().map_or(0., |split| split.time)
+    } else {
+        simulation_time.tick() as f32 * TIMESTEP
+    };
+    let deltas: Vec<Option<f32>> = timer
+        .names
+        .iter()
+        .enumerate()
+        .map(|(index, name)| {
+            let best = personal_best.time(index, name)?;
+            match timer.splits.get(index) {
+                Some(split) => Some(split.time - best),
+                // the running split only shows a delta once it falls behind
+                None if index == timer.splits.len() && now > best => Some(now - best),
+                None => None,
+            }
+        })
+        .collect();
+
+    let last_delta = deltas
+        .iter()
+        .take(timer.splits.len())
+        .rev()
+        .find_map(|delta| *delta);
+    for mut text in timer_query.iter_mut() {
+        text.sections[0].value = format_time(now);
+        text.sections[0].style.color = last_delta.map_or(TEXT_COLOR, delta_color);
+    }
+    for (SplitText(index), mut text) in split_query.iter_mut() {
+        let name = &timer.names[*index];
+        let time = timer
+            .splits
+            .get(*index)
+            .map(|split| split.time)
+            .or_else(|| personal_best.time(*index, name));
+        text.sections[0].value = format!(
+            "{} {}",
+            name,
+            time.map_or("-:--.--".to_string(), format_time)
+        );
+        match deltas[*index] {
+            Some(delta) => {
+                text.sections[1].value = format!(" {}", format_delta(delta));
+                text.sections[1].style.color = delta_color(delta);
+            }
+            None => text.sections[1].value.clear(),
+        }
+    }
+}
+
+fn hide_timer(mut commands: Commands, hud_query: Query<Entity, With<RunTimerHud>>) {
+    for entity in hud_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
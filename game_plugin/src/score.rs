@@ -0,0 +1,87 @@
+use crate::juice::HitKind;
+use crate::loading::FontAssets;
+use crate::player::MAX_PLAYERS;
+use crate::rich_text::{RichText, RichTextBundle};
+use crate::GameState;
+use bevy::prelude::*;
+
+pub struct ScorePlugin;
+
+/// This plugin shows the score of the current run below the lives of the players. The score is part of
+/// the simulation: the collision checks of every tick add points for hits on enemies and more for destroying them.
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<Score>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing).with_system(show_score.system()),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing).with_system(update_score_text.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing).with_system(hide_score.system()),
+            );
+    }
+}
+
+const ENEMY_HIT_POINTS: u32 = 10;
+const ENEMY_KILLED_POINTS: u32 = 100;
+
+/// Points scored in the current or last run
+#[derive(Default)]
+pub struct Score(pub u32);
+
+impl Score {
+    /// Counts a hit of the current tick, also when the tick runs again after a rollback
+    pub fn add_hit(&mut self, kind: HitKind) {
+        self.0 += match kind {
+            HitKind::EnemyHit => ENEMY_HIT_POINTS,
+            HitKind::EnemyKilled => ENEMY_KILLED_POINTS,
+            HitKind::PlayerHit => 0,
+        };
+    }
+}
+
+struct ScoreText;
+
+fn show_score(mut commands: Commands, mut score: ResMut<Score>, font_assets: Res<FontAssets>) {
+    score.0 = 0;
+    commands
+        .spawn_bundle(RichTextBundle::new(
+            score_markup(score.0),
+            TextStyle {
+                font: font_assets.fira_sans.clone(),
+                font_size: 24.0,
+                color: Color::rgb(0.9, 0.9, 0.9),
+            },
+            Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(20.),
+                    top: Val::Px(20. + 30. * MAX_PLAYERS as f32),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+        ))
+        .insert(ScoreText);
+}
+
+fn score_markup(score: u32) -> String {
+    format!("Score: [color=gold]{}[/color]", score)
+}
+
+fn update_score_text(score: Res<Score>, mut text_query: Query<&mut RichText, With<ScoreText>>) {
+    if !score.is_changed() {
+        return;
+    }
+    for mut text in text_query.iter_mut() {
+        text.markup = score_markup(score.0);
+    }
+}
+
+fn hide_score(mut commands: Commands, text_query: Query<Entity, With<ScoreText>>) {
+    for entity in text_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
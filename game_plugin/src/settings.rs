@@ -0,0 +1,100 @@
+use crate::config;
+use bevy::prelude::*;
+use bevy::window::WindowMode;
+use serde::{Deserialize, Serialize};
+
+const SETTINGS_FILE: &str = "settings.toml";
+
+/// Window sizes to pick from on the settings screen
+pub const WINDOW_SIZES: [(f32, f32); 4] =
+    [(960., 540.), (1280., 720.), (1600., 900.), (1920., 1080.)];
+
+pub struct SettingsPlugin;
+
+/// This plugin applies the [Settings] to the primary window whenever they change.
+/// Audio and camera shake pick them up in their own plugins.
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        // the binary loads the settings before creating the window
+        if app.world().get_resource::<Settings>().is_none() {
+            app.insert_resource(Settings::load());
+        }
+        app.add_system(apply_window_settings.system());
+    }
+}
+
+/// Audio, video and gameplay options, loaded from the config directory on startup
+/// and saved whenever the settings screen is left.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub master_volume: f32,
+    pub music_volume: f32,
+    pub sfx_volume: f32,
+    pub window_width: f32,
+    pub window_height: f32,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    /// Scales the camera shake on hits, 0 turns it off
+    pub screen_shake: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            master_volume: 0.6,
+            music_volume: 0.5,
+            sfx_volume: 0.5,
+            window_width: WINDOW_SIZES[0].0,
+            window_height: WINDOW_SIZES[0].1,
+            fullscreen: false,
+            vsync: true,
+            screen_shake: 1.,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        config::load(SETTINGS_FILE).unwrap_or_default()
+    }
+
+    pub fn save(&self) {
+        config::save(SETTINGS_FILE, self);
+    }
+
+    /// The window to open on startup
+    pub fn window_descriptor(&self) -> WindowDescriptor {
+        WindowDescriptor {
+            width: self.window_width,
+            height: self.window_height,
+            vsync: self.vsync,
+            mode: self.window_mode(),
+            title: "Bevy game".to_string(), // ToDo
+            ..Default::default()
+        }
+    }
+
+    pub fn window_mode(&self) -> WindowMode {
+        if self.fullscreen {
+            WindowMode::BorderlessFullscreen
+        } else {
+            WindowMode::Windowed
+        }
+    }
+}
+
+fn apply_window_settings(settings: Res<Settings>, mut windows: ResMut<Windows>) {
+    if !settings.is_changed() {
+        return;
+    }
+    if let Some(window) = windows.get_primary_mut() {
+        window.set_resolution(settings.window_width, settings.window_height);
+        if window.mode() != settings.window_mode() {
+            window.set_mode(settings.window_mode());
+        }
+        if window.vsync() != settings.vsync {
+            window.set_vsync(settings.vsync);
+        }
+    }
+}
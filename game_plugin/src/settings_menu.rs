@@ -0,0 +1,231 @@
+use crate::loading::FontAssets;
+use crate::settings::{Settings, WINDOW_SIZES};
+use crate::widgets::{
+    spawn_back_button, spawn_button, spawn_selector, spawn_slider, spawn_toggle, Selector, Slider,
+    Toggle, UiTheme, WidgetChange, WidgetEvent,
+};
+use crate::GameState;
+use bevy::prelude::*;
+
+pub struct SettingsMenuPlugin;
+
+/// This plugin draws the settings screen. Every change is applied right away,
+/// the settings are saved when leaving the screen.
+impl Plugin for SettingsMenuPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_enter(GameState::Settings).with_system(setup_settings_menu.system()),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Settings)
+                .with_system(
+                    change_settings
+                        .system()
+                        .label("change_settings")
+                        .after("widgets"),
+                )
+                .with_system(update_widgets.system().after("change_settings")),
+        )
+        .add_system_set(
+            SystemSet::on_exit(GameState::Settings).with_system(cleanup_settings_menu.system()),
+        );
+    }
+}
+
+struct SettingsMenu;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Setting {
+    MasterVolume,
+    MusicVolume,
+    SfxVolume,
+    WindowSize,
+    Fullscreen,
+    Vsync,
+    ScreenShake,
+}
+
+fn window_size_index(settings: &Settings) -> usize {
+    WINDOW_SIZES
+        .iter()
+        .position(|size| *size == (settings.window_width, settings.window_height))
+        .unwrap_or(0)
+}
+
+enum SettingsButton {
+    Change(Setting),
+    Reset,
+    Back,
+}
+
+fn setup_settings_menu(
+    mut commands: Commands,
+    font_assets: Res<FontAssets>,
+    theme: Res<UiTheme>,
+    settings: Res<Settings>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::NONE.into()),
+            ..Default::default()
+        })
+        .insert(SettingsMenu)
+        .with_children(|parent| {
+            let volumes = [
+                ("Volume", Setting::MasterVolume, settings.master_volume),
+                ("Music", Setting::MusicVolume, settings.music_volume),
+                ("Sound effects", Setting::SfxVolume, settings.sfx_volume),
+            ];
+            for (label, setting, value) in volumes.iter() {
+                spawn_slider(
+                    parent,
+                    label,
+                    Slider::percent(*value, 0.05),
+                    &font_assets,
+                    &theme,
+                    SettingsButton::Change(*setting),
+                );
+            }
+            spawn_selector(
+                parent,
+                "Window size",
+                Selector {
+                    options: WINDOW_SIZES
+                        .iter()
+                        .map(|(width, height)| format!("{}x{}", width, height))
+                        .collect(),
+                    selected: window_size_index(&settings),
+                },
+                &font_assets,
+                &theme,
+                SettingsButton::Change(Setting::WindowSize),
+            );
+            spawn_toggle(
+                parent,
+                "Fullscreen",
+                settings.fullscreen,
+                &font_assets,
+                &theme,
+                SettingsButton::Change(Setting::Fullscreen),
+            );
+            spawn_toggle(
+                parent,
+                "Vsync",
+                settings.vsync,
+                &font_assets,
+                &theme,
+                SettingsButton::Change(Setting::Vsync),
+            );
+            spawn_slider(
+                parent,
+                "Screen shake",
+                Slider::percent(settings.screen_shake, 0.25),
+                &font_assets,
+                &theme,
+                SettingsButton::Change(Setting::ScreenShake),
+            );
+            spawn_button(parent, "Reset", &font_assets, &theme, SettingsButton::Reset);
+            spawn_back_button(parent, "Back", &font_assets, &theme, SettingsButton::Back);
+        });
+}
+
+fn change_settings(
+    mut events: EventReader<WidgetEvent>,
+    mut state: ResMut<State<GameState>>,
+    mut settings: ResMut<Settings>,
+    button_query: Query<&SettingsButton>,
+) {
+    for event in events.iter() {
+        let button = match button_query.get(event.entity) {
+            Ok(button) => button,
+            Err(_) => continue,
+        };
+        match (button, &event.change) {
+            (SettingsButton::Change(setting), change) => match (setting, change) {
+                (Setting::MasterVolume, WidgetChange::Value(value)) => {
+                    settings.master_volume = *value
+                }
+                (Setting::MusicVolume, WidgetChange::Value(value)) => {
+                    settings.music_volume = *value
+                }
+                (Setting::SfxVolume, WidgetChange::Value(value)) => settings.sfx_volume = *value,
+                (Setting::ScreenShake, WidgetChange::Value(value)) => {
+                    settings.screen_shake = *value
+                }
+                (Setting::WindowSize, WidgetChange::Selected(index)) => {
+                    let (width, height) = WINDOW_SIZES[*index];
+                    settings.window_width = width;
+                    settings.window_height = height;
+                }
+                (Setting::Fullscreen, WidgetChange::Toggled(on)) => settings.fullscreen = *on,
+                (Setting::Vsync, WidgetChange::Toggled(on)) => settings.vsync = *on,
+                _ => (),
+            },
+            (SettingsButton::Reset, _) => *settings = Settings::default(),
+            (SettingsButton::Back, _) => {
+                if let Err(error) = state.set(GameState::Menu) {
+                    warn!("Ignoring back button: {:?}", error);
+                }
+            }
+        }
+    }
+}
+
+/// Shows the settings after a reset
+fn update_widgets(
+    settings: Res<Settings>,
+    mut slider_query: Query<(&SettingsButton, &mut Slider)>,
+    mut toggle_query: Query<(&SettingsButton, &mut Toggle)>,
+    mut selector_query: Query<(&SettingsButton, &mut Selector)>,
+) {
+    if !settings.is_changed() {
+        return;
+    }
+    for (button, mut slider) in slider_query.iter_mut() {
+        let value = match button {
+            SettingsButton::Change(Setting::MasterVolume) => settings.master_volume,
+            SettingsButton::Change(Setting::MusicVolume) => settings.music_volume,
+            SettingsButton::Change(Setting::SfxVolume) => settings.sfx_volume,
+            SettingsButton::Change(Setting::ScreenShake) => settings.screen_shake,
+            _ => continue,
+        };
+        if slider.value != value {
+            slider.value = value;
+        }
+    }
+    for (button, mut toggle) in toggle_query.iter_mut() {
+        let on = match button {
+            SettingsButton::Change(Setting::Fullscreen) => settings.fullscreen,
+            SettingsButton::Change(Setting::Vsync) => settings.vsync,
+            _ => continue,
+        };
+        if toggle.on != on {
+            toggle.on = on;
+        }
+    }
+    for (_, mut selector) in selector_query.iter_mut() {
+        let selected = window_size_index(&settings);
+        if selector.selected != selected {
+            selector.selected = selected;
+        }
+    }
+}
+
+fn cleanup_settings_menu(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    menu: Query<Entity, With<SettingsMenu>>,
+) {
+    settings.save();
+    for entity in menu.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
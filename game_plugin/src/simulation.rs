@@ -0,0 +1,225 @@
+use crate::juice::HitStop;
+use crate::GameState;
+use bevy::ecs::schedule::{ShouldRun, StageLabel};
+use bevy::prelude::*;
+
+pub struct SimulationPlugin;
+
+/// This plugin runs gameplay in fixed ticks of [TIMESTEP] seconds, so it plays the same at every frame rate.
+/// Gameplay systems go into [SimulationStage::Tick], which runs as many times per frame as there are ticks due.
+/// Ticks only happen in `GameState::Playing` and not during hit stop.
+/// Entities with an [Interpolated] component are drawn in between their last two ticks in [SimulationStage::Interpolate].
+/// Ticks are numbered from the start of the run, and [SimulationTime::rollback] runs ticks again after their state was restored.
+/// Entities marked with [RunEntity] are removed when the run ends.
+impl Plugin for SimulationPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<SimulationTime>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::Playing).with_system(reset_ticks.system()),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing).with_system(despawn_run_entities.system()),
+            )
+            .add_stage_after(
+                CoreStage::Update,
+                SimulationStage::Tick,
+                SystemStage::parallel().with_run_criteria(next_tick.system()),
+            )
+            .add_stage_after(
+                SimulationStage::Tick,
+                SimulationStage::Interpolate,
+                SystemStage::parallel(),
+            )
+            .add_system_to_stage(
+                SimulationStage::Tick,
+                begin_tick.system().label("begin_tick"),
+            )
+            .add_system_to_stage(
+                SimulationStage::Interpolate,
+                interpolate.system().label("interpolate"),
+            );
+    }
+}
+
+/// Seconds of game time in one tick
+pub const TIMESTEP: f32 = 1. / 60.;
+/// After a long hitch the simulation skips ahead instead of trying to catch up
+const MAX_TICKS_PER_FRAME: u32 = 5;
+
+#[derive(Debug, Hash, PartialEq, Eq, Clone, StageLabel)]
+pub enum SimulationStage {
+    /// Runs once per tick, after `CoreStage::Update`
+    Tick,
+    /// Runs once per frame after all ticks
+    Interpolate,
+}
+
+#[derive(Default)]
+pub struct SimulationTime {
+    accumulator: f32,
+    /// Ticks that ran so far in the current frame
+    ticks: u32,
+    /// `Time::seconds_since_startup` of the current frame
+    frame: f64,
+    /// How far the current frame is between the last tick and the next one, from 0 to 1
+    alpha: f32,
+    /// Number of the tick that is running, or ran last
+    tick: u64,
+    /// Number of the tick to run next
+    next: u64,
+    /// Ticks to run again after a rollback, before any new ones
+    resimulate: u64,
+    /// Ticks that ran again so far in the current frame
+    resimulated: u32,
+    resimulating: bool,
+    /// Ticks from this number on wait, e.g. for input from the network
+    pub tick_limit: Option<u64>,
+    /// Ticks before this number will not be rolled back any more, e.g. because the input of every
+    /// player arrived for them. Without it, every tick that ran is final.
+    pub confirmed: Option<u64>,
+}
+
+impl SimulationTime {
+    /// Number of the tick that is running, counted from the start of the run
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn next_tick(&self) -> u64 {
+        self.next
+    }
+
+    /// Are the results of `tick` final?
+    pub fn is_confirmed(&self, tick: u64) -> bool {
+        self.confirmed.map_or(true, |confirmed| tick < confirmed)
+    }
+
+    /// Is the running tick a repeat after a [SimulationTime::rollback]?
+    pub fn is_resimulating(&self) -> bool {
+        self.resimulating
+    }
+
+    /// Goes back to `tick`, so it and all ticks after it run again before any new tick.
+    /// The caller has to restore the state from the start of `tick`.
+    pub fn rollback(&mut self, tick: u64) {
+        if tick < self.next {
+            self.resimulate += self.next - tick;
+            self.next = tick;
+        }
+    }
+
+    fn start_tick(&mut self) {
+        self.tick = self.next;
+        self.next += 1;
+        self.ticks += 1;
+    }
+}
+
+/// Drawn at a blend of where the entity was in the last two ticks.
+/// Gameplay systems see and change the real position in `Transform`.
+pub struct Interpolated {
+    previous: Vec3,
+    current: Vec3,
+}
+
+impl Interpolated {
+    pub fn new(translation: Vec3) -> Self {
+        Interpolated {
+            previous: translation,
+            current: translation,
+        }
+    }
+
+    /// Moves without blending, the `Transform` has to be set to `translation` as well
+    pub fn teleport(&mut self, translation: Vec3) {
+        *self = Interpolated::new(translation);
+    }
+}
+
+fn next_tick(
+    time: Res<Time>,
+    state: Res<State<GameState>>,
+    hit_stop: Res<HitStop>,
+    mut simulation: ResMut<SimulationTime>,
+) -> ShouldRun {
+    let playing = *state.current() == GameState::Playing && !hit_stop.is_active();
+    if simulation.frame != time.seconds_since_startup() {
+        simulation.frame = time.seconds_since_startup();
+        simulation.ticks = 0;
+        simulation.resimulated = 0;
+        if playing {
+            simulation.accumulator += time.delta_seconds();
+        }
+    }
+    if !playing {
+        return ShouldRun::No;
+    }
+    if simulation.resimulate > 0 {
+        simulation.resimulate -= 1;
+        simulation.resimulated += 1;
+        simulation.resimulating = true;
+        simulation.start_tick();
+        return ShouldRun::YesAndCheckAgain;
+    }
+    simulation.resimulating = false;
+    let waiting = simulation
+        .tick_limit
+        .map_or(false, |limit| simulation.next >= limit);
+    if simulation.accumulator >= TIMESTEP
+        && simulation.ticks - simulation.resimulated < MAX_TICKS_PER_FRAME
+        && !waiting
+    {
+        simulation.accumulator -= TIMESTEP;
+        simulation.start_tick();
+        ShouldRun::YesAndCheckAgain
+    } else {
+        simulation.accumulator = simulation.accumulator.min(TIMESTEP);
+        simulation.alpha = simulation.accumulator / TIMESTEP;
+        ShouldRun::No
+    }
+}
+
+/// Marks the gameplay entities of a run, like the camera, ships and enemies.
+/// Only put it on entities without a parent, their children are removed with them.
+pub struct RunEntity;
+
+fn despawn_run_entities(mut commands: Commands, query: Query<Entity, With<RunEntity>>) {
+    for entity in query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn reset_ticks(mut simulation: ResMut<SimulationTime>) {
+    simulation.tick = 0;
+    simulation.next = 0;
+    simulation.resimulate = 0;
+    simulation.tick_limit = None;
+    simulation.confirmed = None;
+}
+
+fn begin_tick(
+    simulation: Res<SimulationTime>,
+    mut query: Query<(&mut Interpolated, &mut Transform)>,
+) {
+    for (mut interpolated, mut transform) in query.iter_mut() {
+        if simulation.ticks == 1 {
+            // take back the blending of the last frame
+            transform.translation = interpolated.current;
+        }
+        interpolated.previous = transform.translation;
+    }
+}
+
+fn interpolate(
+    simulation: Res<SimulationTime>,
+    mut query: Query<(&mut Interpolated, &mut Transform)>,
+) {
+    for (mut interpolated, mut transform) in query.iter_mut() {
+        if simulation.ticks > 0 {
+            interpolated.current = transform.translation;
+        }
+        transform.translation = interpolated
+            .previous
+            .lerp(interpolated.current, simulation.alpha);
+    }
+}
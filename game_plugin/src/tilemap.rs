@@ -0,0 +1,456 @@
+use crate::level::LevelData;
+use crate::loading::{LevelAssets, TextureAtlases};
+use crate::particles::ParticleEmitter;
+use crate::player::{Bullet, MainCamera, Player};
+use crate::run_timer::SplitReached;
+use crate::simulation::{RunEntity, SimulationStage, SimulationTime, TIMESTEP};
+use crate::virtual_resolution::VIRTUAL_HEIGHT;
+use crate::GameState;
+use anyhow::anyhow;
+use bevy::prelude::*;
+use bevy::utils::HashMap;
+
+pub struct TileMapPlugin;
+
+/// This plugin builds the level's tilemap out of the main sprite sheet.
+/// The camera scrolls up the map, and only chunks close to the camera are spawned.
+/// Solid tiles block the player and bullets.
+impl Plugin for TileMapPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_system_set(
+            SystemSet::on_enter(GameState::Playing).with_system(setup_tilemap.system()),
+        )
+        .add_system_set(
+            SystemSet::on_update(GameState::Playing).with_system(update_chunks.system()),
+        )
+        .add_system_set(SystemSet::on_exit(GameState::Playing).with_system(remove_tilemap.system()))
+        .add_system_set_to_stage(
+            SimulationStage::Tick,
+            SystemSet::new()
+                .with_system(
+                    scroll_camera
+                        .system()
+                        .label("scroll_camera")
+                        .after("gather_input"),
+                )
+                .with_system(
+                    collide_player_with_tiles
+                        .system()
+                        .after("scroll_camera")
+                        .after("move_player"),
+                )
+                .with_system(collide_bullets_with_tiles.system().after("move_bullets")),
+        );
+    }
+}
+
+const TILE_SIZE: f32 = 16.;
+/// Number of tile rows spawned and despawned together
+const CHUNK_ROWS: usize = 8;
+/// Half the size of the player's hit box
+const PLAYER_HALF_SIZE: f32 = 8.;
+
+/// A grid of sprite sheet indices, loaded from a CSV file.
+/// Each line is a row of comma separated indices, top to bottom. Empty cells or `-1` mean no tile.
+#[derive(Debug)]
+pub struct TileMap {
+    pub width: usize,
+    pub height: usize,
+    tiles: Vec<Option<u32>>,
+}
+
+impl TileMap {
+    pub fn from_csv(source: &str) -> anyhow::Result<TileMap> {
+        let mut rows = vec![];
+        for (line_number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut row = vec![];
+            for (column, cell) in line.split(',').enumerate() {
+                let cell = cell.trim();
+                row.push(match cell {
+                    "" | "-1" => None,
+                    _ => Some(cell.parse::<u32>().map_err(|_| {
+                        anyhow!(
+                            "invalid tile '{}' in line {}, column {}",
+                            cell,
+                            line_number + 1,
+                            column + 1
+                        )
+                    })?),
+                });
+            }
+            rows.push(row);
+        }
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        let height = rows.len();
+        let mut tiles = Vec::with_capacity(width * height);
+        for mut row in rows {
+            row.resize(width, None);
+            tiles.extend(row);
+        }
+        Ok(TileMap {
+            width,
+            height,
+            tiles,
+        })
+    }
+}
+
+/// The tilemap of the level being played, laid out in world space
+pub struct ActiveTileMap {
+    /// World position of the bottom left corner of the map
+    origin: Vec2,
+    tile_size: f32,
+    width: usize,
+    height: usize,
+    tiles: Vec<Option<u32>>,
+    solid: Vec<bool>,
+    scroll_speed: f32,
+    /// Spawned chunks by index, they follow the camera and are not part of the simulation
+    chunks: HashMap<usize, Entity>,
+    /// World heights of the split rows, see [SplitSettings](crate::level::SplitSettings)
+    splits: Vec<f32>,
+    /// Index of the split the camera reaches next
+    pub next_split: usize,
+}
+
+impl ActiveTileMap {
+    /// Grid cell at a world position, with rows counted from the bottom of the map
+    fn cell(&self, position: Vec2) -> Option<(usize, usize)> {
+        let local = (position - self.origin) / self.tile_size;
+        if local.x < 0. || local.y < 0. {
+            return None;
+        }
+        let (column, row) = (local.x as usize, local.y as usize);
+        if column >= self.width || row >= self.height {
+            return None;
+        }
+        Some((column, row))
+    }
+
+    fn index(&self, column: usize, row_from_bottom: usize) -> usize {
+        (self.height - 1 - row_from_bottom) * self.width + column
+    }
+
+    fn is_solid(&self, position: Vec2) -> bool {
+        self.cell(position)
+            .map(|(column, row)| self.solid[self.index(column, row)])
+            .unwrap_or(false)
+    }
+
+    fn tile_center(&self, column: usize, row_from_bottom: usize) -> Vec2 {
+        self.origin
+            + (Vec2::new(column as f32, row_from_bottom as f32) + Vec2::splat(0.5)) * self.tile_size
+    }
+
+    fn top(&self) -> f32 {
+        self.origin.y + self.height as f32 * self.tile_size
+    }
+}
+
+struct Chunk;
+
+/// The chunks are despawned as [RunEntity]s, the map of them goes with the run
+fn remove_tilemap(mut commands: Commands) {
+    commands.remove_resource::<ActiveTileMap>();
+}
+
+fn setup_tilemap(
+    mut commands: Commands,
+    level_assets: Res<LevelAssets>,
+    levels: Res<Assets<LevelData>>,
+) {
+    let level = match levels.get(&level_assets.level_1) {
+        Some(level) => level,
+        None => return,
+    };
+    let (settings, map) = match (&level.tilemap, &level.tiles) {
+        (Some(settings), Some(map)) => (settings, map),
+        _ => return,
+    };
+    let tile_size = TILE_SIZE * settings.scale;
+    // the bottom row of the map starts at the bottom of the screen
+    let origin = Vec2::new(-(map.width as f32) * tile_size / 2., -VIRTUAL_HEIGHT / 2.);
+    commands.insert_resource(ActiveTileMap {
+        origin,
+        tile_size,
+        width: map.width,
+        height: map.height,
+        solid: map
+            .tiles
+            .iter()
+            .map(|tile| tile.map_or(false, |index| settings.solid.contains(&index)))
+            .collect(),
+        tiles: map.tiles.clone(),
+        scroll_speed: settings.scroll_speed,
+        chunks: HashMap::default(),
+        splits: settings
+            .splits
+            .iter()
+            .map(|split| origin.y + split.row.min(map.height) as f32 * tile_size)
+            .collect(),
+        next_split: 0,
+    });
+}
+
+fn scroll_camera(
+    simulation_time: Res<SimulationTime>,
+    mut splits: EventWriter<SplitReached>,
+    tilemap: Option<ResMut<ActiveTileMap>>,
+    mut camera_query: Query<&mut Transform, With<MainCamera>>,
+    mut player_query: Query<&mut Transform, (With<Player>, Without<MainCamera>)>,
+) {
+    let mut tilemap = match tilemap {
+        Some(tilemap) => tilemap,
+        None => return,
+    };
+    for mut camera in camera_query.iter_mut() {
+        // stop once the top of the map reaches the top of the screen
+        let max_y = tilemap.top() - VIRTUAL_HEIGHT / 2.;
+        let scroll = (tilemap.scroll_speed * TIMESTEP)
+            .min(max_y - camera.translation.y)
+            .max(0.);
+        camera.translation.y += scroll;
+        for mut player in player_query.iter_mut() {
+            player.translation.y += scroll;
+        }
+
+        // the camera stops right at the top of the map, so the last split gets a little leeway
+        let view_top = camera.translation.y + VIRTUAL_HEIGHT / 2.;
+        while let Some(split) = tilemap.splits.get(tilemap.next_split) {
+            if view_top < split - 0.5 {
+                break;
+            }
+            // the camera does not depend on input, the first run of the tick already sent it
+            if !simulation_time.is_resimulating() {
+                splits.send(SplitReached {
+                    index: tilemap.next_split,
+                    tick: simulation_time.tick(),
+                });
+            }
+            tilemap.next_split += 1;
+        }
+    }
+}
+
+fn update_chunks(
+    mut commands: Commands,
+    texture_atlases: Res<TextureAtlases>,
+    tilemap: Option<ResMut<ActiveTileMap>>,
+    camera_query: Query<&Transform, With<MainCamera>>,
+) {
+    let mut tilemap = match tilemap {
+        Some(tilemap) => tilemap,
+        None => return,
+    };
+    let camera = match camera_query.single() {
+        Ok(camera) => camera,
+        Err(_) => return,
+    };
+    let chunk_height = CHUNK_ROWS as f32 * tilemap.tile_size;
+    let chunk_count = (tilemap.height + CHUNK_ROWS - 1) / CHUNK_ROWS;
+    // keep one chunk of margin above and below the screen
+    let bottom = camera.translation.y - VIRTUAL_HEIGHT / 2. - chunk_height - tilemap.origin.y;
+    let top = camera.translation.y + VIRTUAL_HEIGHT / 2. + chunk_height - tilemap.origin.y;
+    let first = (bottom / chunk_height).floor().max(0.) as usize;
+    let last = ((top / chunk_height).ceil().max(0.) as usize).min(chunk_count);
+
+    let outdated: Vec<usize> = tilemap
+        .chunks
+        .keys()
+        .copied()
+        .filter(|chunk| *chunk < first || *chunk >= last)
+        .collect();
+    for chunk in outdated {
+        if let Some(entity) = tilemap.chunks.remove(&chunk) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    for chunk in first..last {
+        if tilemap.chunks.contains_key(&chunk) {
+            continue;
+        }
+        let entity = commands
+            .spawn()
+            .insert(Transform::default())
+            .insert(GlobalTransform::default())
+            .insert(Chunk)
+            .insert(RunEntity)
+            .with_children(|parent| {
+                let rows = chunk * CHUNK_ROWS..((chunk + 1) * CHUNK_ROWS).min(tilemap.height);
+                for row in rows {
+                    for column in 0..tilemap.width {
+                        let index = match tilemap.tiles[tilemap.index(column, row)] {
+                            Some(index) => index,
+                            None => continue,
+                        };
+                        parent.spawn_bundle(SpriteSheetBundle {
+                            texture_atlas: texture_atlases.main_sprite_sheet.clone(),
+                            transform: Transform {
+                                // in front of the background, behind the player
+                                translation: tilemap.tile_center(column, row).extend(0.9),
+                                scale: Vec3::splat(tilemap.tile_size / TILE_SIZE),
+                                ..Default::default()
+                            },
+                            sprite: TextureAtlasSprite::new(index),
+                            ..Default::default()
+                        });
+                    }
+                }
+            })
+            .id();
+        tilemap.chunks.insert(chunk, entity);
+    }
+}
+
+fn collide_player_with_tiles(
+    tilemap: Option<Res<ActiveTileMap>>,
+    mut player_query: Query<&mut Transform, With<Player>>,
+) {
+    let tilemap = match tilemap {
+        Some(tilemap) => tilemap,
+        None => return,
+    };
+    for mut transform in player_query.iter_mut() {
+        let mut position = transform.translation.truncate();
+        let min = (position - Vec2::splat(PLAYER_HALF_SIZE) - tilemap.origin) / tilemap.tile_size;
+        let max = (position + Vec2::splat(PLAYER_HALF_SIZE) - tilemap.origin) / tilemap.tile_size;
+        let columns =
+            min.x.floor().max(0.) as usize..(max.x.ceil().max(0.) as usize).min(tilemap.width);
+        let rows =
+            min.y.floor().max(0.) as usize..(max.y.ceil().max(0.) as usize).min(tilemap.height);
+        for row in rows {
+            for column in columns.clone() {
+                if tilemap.solid[tilemap.index(column, row)] {
+                    position += penetration(
+                        position,
+                        tilemap.tile_center(column, row),
+                        PLAYER_HALF_SIZE,
+                        tilemap.tile_size / 2.,
+                    );
+                }
+            }
+        }
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+    }
+}
+
+/// Smallest offset that moves a box at `position` out of a tile at `tile`
+fn penetration(position: Vec2, tile: Vec2, half_size: f32, half_tile: f32) -> Vec2 {
+    let delta = position - tile;
+    let overlap = Vec2::splat(half_size + half_tile) - delta.abs();
+    if overlap.x <= 0. || overlap.y <= 0. {
+        return Vec2::ZERO;
+    }
+    if overlap.x < overlap.y {
+        Vec2::new(overlap.x * delta.x.signum(), 0.)
+    } else {
+        Vec2::new(0., overlap.y * delta.y.signum())
+    }
+}
+
+fn collide_bullets_with_tiles(
+    mut commands: Commands,
+    tilemap: Option<Res<ActiveTileMap>>,
+    bullet_query: Query<(Entity, &Transform, &Bullet)>,
+) {
+    let tilemap = match tilemap {
+        Some(tilemap) => tilemap,
+        None => return,
+    };
+    for (entity, transform, bullet) in bullet_query.iter() {
+        if tilemap.is_solid(transform.translation.truncate()) {
+            commands.entity(entity).despawn();
+            let impact = Transform::from_translation(transform.translation);
+            commands
+                .spawn()
+                .insert(impact)
+                .insert(GlobalTransform::from(impact))
+                .insert(ParticleEmitter::sparks(-bullet.direction.truncate()))
+                .insert(RunEntity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The map laid out with one unit per tile and its bottom left corner at the origin
+    fn active(map: &TileMap) -> ActiveTileMap {
+        ActiveTileMap {
+            origin: Vec2::ZERO,
+            tile_size: 1.,
+            width: map.width,
+            height: map.height,
+            tiles: map.tiles.clone(),
+            solid: vec![false; map.tiles.len()],
+            scroll_speed: 0.,
+            chunks: HashMap::default(),
+            splits: vec![],
+            next_split: 0,
+        }
+    }
+
+    #[test]
+    fn ragged_rows_are_filled_with_empty_cells() {
+        let map = TileMap::from_csv("1,2,3\n4\n5,,-1,6\n").unwrap();
+        assert_eq!((map.width, map.height), (4, 3));
+        assert_eq!(
+            map.tiles,
+            vec![
+                Some(1),
+                Some(2),
+                Some(3),
+                None,
+                Some(4),
+                None,
+                None,
+                None,
+                Some(5),
+                None,
+                None,
+                Some(6),
+            ]
+        );
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let map = TileMap::from_csv("# top\n1, 2\n\n  # middle\n 3 ,4\n").unwrap();
+        assert_eq!((map.width, map.height), (2, 2));
+        assert_eq!(map.tiles, vec![Some(1), Some(2), Some(3), Some(4)]);
+    }
+
+    #[test]
+    fn unknown_tile_ids_are_rejected() {
+        for source in ["1,2\n3,x", "1,-2", "1,2.5", "# fine\n1,1\n1,99999999999"].iter() {
+            assert!(
+                TileMap::from_csv(source).is_err(),
+                "{:?} was accepted",
+                source
+            );
+        }
+        let error = TileMap::from_csv("# comment\n1,2\n3,x").unwrap_err();
+        assert_eq!(error.to_string(), "invalid tile 'x' in line 3, column 2");
+    }
+
+    #[test]
+    fn rows_are_counted_from_the_bottom() {
+        let map = active(&TileMap::from_csv("1,2\n3,4\n5,6").unwrap());
+        let tile = |column, row| map.tiles[map.index(column, row)];
+        assert_eq!(tile(0, 0), Some(5));
+        assert_eq!(tile(1, 0), Some(6));
+        assert_eq!(tile(0, 2), Some(1));
+        assert_eq!(tile(1, 2), Some(2));
+        assert_eq!(map.cell(Vec2::new(1.5, 0.5)), Some((1, 0)));
+        assert_eq!(map.cell(Vec2::new(0.5, 3.5)), None);
+        assert_eq!(map.tile_center(1, 2), Vec2::new(1.5, 2.5));
+        assert_eq!(map.top(), 3.);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::actions::GameControl;
+use crate::loading::FontAssets;
+use crate::GameState;
+use bevy::prelude::*;
+
+pub struct TouchControlsPlugin;
+
+/// This plugin draws a virtual joystick and buttons for touch screens.
+/// They show up once the first touch is seen and are read into [TouchControls],
+/// which the [ActionsPlugin](crate::actions::ActionsPlugin) uses like any other input device.
+impl Plugin for TouchControlsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<TouchControls>()
+            .init_resource::<TouchMaterials>()
+            .add_system(read_touches.system().label("read_touches"))
+            .add_system_set(
+                SystemSet::on_update(GameState::Playing)
+                    .with_system(spawn_touch_controls.system())
+                    .with_system(update_touch_controls.system().after("read_touches")),
+            )
+            .add_system_set(
+                SystemSet::on_exit(GameState::Playing).with_system(cleanup_touch_controls.system()),
+            );
+    }
+}
+
+/// State of the on-screen controls
+#[derive(Default)]
+pub struct TouchControls {
+    /// Set by the first touch, the controls are only drawn after that
+    pub detected: bool,
+    /// Joystick direction, with a length between 0 and 1
+    pub movement: Option<Vec2>,
+    pub shoot: bool,
+    pub switch_weapon: bool,
+    pub dash: bool,
+    pub pause: bool,
+    /// Id and start position of the touch that is moving the joystick
+    stick: Option<(u64, Vec2)>,
+    /// Buttons under a finger right now, to highlight them
+    held: Vec<TouchButton>,
+}
+
+impl TouchControls {
+    pub fn pressed(&self, control: GameControl) -> bool {
+        touch_button(control).map_or(false, |button| self.held.contains(&button))
+    }
+
+    pub fn just_pressed(&self, control: GameControl) -> bool {
+        match control {
+            GameControl::SwitchWeapon => self.switch_weapon,
+            GameControl::Dash => self.dash,
+            GameControl::Pause => self.pause,
+            _ => false,
+        }
+    }
+}
+
+fn touch_button(control: GameControl) -> Option<TouchButton> {
+    match control {
+        GameControl::Shoot => Some(TouchButton::Shoot),
+        GameControl::SwitchWeapon => Some(TouchButton::SwitchWeapon),
+        GameControl::Dash => Some(TouchButton::Dash),
+        GameControl::Pause => Some(TouchButton::Pause),
+        _ => None,
+    }
+}
+
+/// Distance in pixels between the joystick center and full tilt
+const STICK_RADIUS: f32 = 60.;
+const STICK_DEAD_ZONE: f32 = 0.15;
+const KNOB_SIZE: f32 = 50.;
+/// Where the joystick rests while it is not touched, from the bottom left corner
+const STICK_HOME: f32 = 110.;
+
+struct TouchMaterials {
+    stick: Handle<ColorMaterial>,
+    knob: Handle<ColorMaterial>,
+    button: Handle<ColorMaterial>,
+    pressed: Handle<ColorMaterial>,
+}
+
+impl FromWorld for TouchMaterials {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+        TouchMaterials {
+            stick: materials.add(Color::rgba(1., 1., 1., 0.1).into()),
+            knob: materials.add(Color::rgba(1., 1., 1., 0.35).into()),
+            button: materials.add(Color::rgba(1., 1., 1., 0.15).into()),
+            pressed: materials.add(Color::rgba(1., 1., 1., 0.4).into()),
+        }
+    }
+}
+
+struct TouchUi;
+
+struct StickBase;
+
+struct StickKnob;
+
+#[derive(Clone, Copy, PartialEq)]
+enum TouchButton {
+    Shoot,
+    SwitchWeapon,
+    Dash,
+    Pause,
+}
+
+/// Touch position with the origin at the bottom left, like the UI.
+/// bevy_winit only flips touch positions on mobile targets, in the browser they start at the top.
+fn ui_position(touch: &bevy::input::touch::Touch, window_height: f32) -> Vec2 {
+    let position = touch.position();
+    if cfg!(target_os = "android") || cfg!(target_os = "ios") {
+        position
+    } else {
+        Vec2::new(position.x, window_height - position.y)
+    }
+}
+
+fn read_touches(
+    windows: Res<Windows>,
+    touches: Res<Touches>,
+    mut controls: ResMut<TouchControls>,
+    button_query: Query<(&TouchButton, &Node, &GlobalTransform)>,
+) {
+    if touches.iter_just_pressed().next().is_some() {
+        controls.detected = true;
+    }
+    if !controls.detected {
+        return;
+    }
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let (width, height) = (window.width(), window.height());
+    let button_at = |position: Vec2| {
+        button_query
+            .iter()
+            .find(|(_, node, transform)| {
+                let offset = (position - transform.translation.truncate()).abs();
+                offset.x <= node.size.x / 2. && offset.y <= node.size.y / 2.
+            })
+            .map(|(button, _, _)| *button)
+    };
+
+    let held = |button| {
+        touches
+            .iter()
+            .any(|touch| button_at(ui_position(touch, height)) == Some(button))
+    };
+    let tapped = |button| {
+        touches
+            .iter_just_pressed()
+            .any(|touch| button_at(ui_position(touch, height)) == Some(button))
+    };
+    controls.held = [
+        TouchButton::Shoot,
+        TouchButton::SwitchWeapon,
+        TouchButton::Dash,
+        TouchButton::Pause,
+    ]
+    .iter()
+    .copied()
+    .filter(|button| held(*button))
+    .collect();
+    controls.shoot = held(TouchButton::Shoot);
+    controls.switch_weapon = tapped(TouchButton::SwitchWeapon);
+    controls.dash = tapped(TouchButton::Dash);
+    controls.pause = tapped(TouchButton::Pause);
+
+    // the first new touch on the left half of the screen grabs the joystick
+    if let Some((id, _)) = controls.stick {
+        if touches.get_pressed(id).is_none() {
+            controls.stick = None;
+        }
+    }
+    if controls.stick.is_none() {
+        controls.stick = touches
+            .iter_just_pressed()
+            .map(|touch| (touch.id(), ui_position(touch, height)))
+            .find(|(_, position)| position.x < width / 2. && button_at(*position).is_none());
+    }
+    controls.movement = controls.stick.and_then(|(id, start)| {
+        let touch = touches.get_pressed(id)?;
+        let tilt = ((ui_position(touch, height) - start) / STICK_RADIUS).clamp_length_max(1.);
+        if tilt.length() < STICK_DEAD_ZONE {
+            None
+        } else {
+            Some(tilt)
+        }
+    });
+}
+
+fn spawn_touch_controls(
+    mut commands: Commands,
+    controls: Res<TouchControls>,
+    font_assets: Res<FontAssets>,
+    touch_materials: Res<TouchMaterials>,
+    ui_query: Query<Entity, With<TouchUi>>,
+) {
+    if !controls.detected || ui_query.iter().next().is_some() {
+        return;
+    }
+    commands
+        .spawn_bundle(NodeBundle {
+            style: touch_style(
+                Rect {
+                    left: Val::Px(STICK_HOME - STICK_RADIUS),
+                    bottom: Val::Px(STICK_HOME - STICK_RADIUS),
+                    ..Default::default()
+                },
+                STICK_RADIUS * 2.,
+            ),
+            material: touch_materials.stick.clone(),
+            ..Default::default()
+        })
+        .insert(TouchUi)
+        .insert(StickBase);
+    commands
+        .spawn_bundle(NodeBundle {
+            style: touch_style(
+                Rect {
+                    left: Val::Px(STICK_HOME - KNOB_SIZE / 2.),
+                    bottom: Val::Px(STICK_HOME - KNOB_SIZE / 2.),
+                    ..Default::default()
+                },
+                KNOB_SIZE,
+            ),
+            material: touch_materials.knob.clone(),
+            ..Default::default()
+        })
+        .insert(TouchUi)
+        .insert(StickKnob);
+
+    let bottom_right = |right: f32, bottom: f32| Rect {
+        right: Val::Px(right),
+        bottom: Val::Px(bottom),
+        ..Default::default()
+    };
+    let buttons = [
+        (TouchButton::Shoot, "Shoot", bottom_right(30., 30.), 110.),
+        (TouchButton::Dash, "Dash", bottom_right(160., 30.), 70.),
+        (
+            TouchButton::SwitchWeapon,
+            "Weapon",
+            bottom_right(50., 160.),
+            70.,
+        ),
+        (
+            TouchButton::Pause,
+            "Pause",
+            Rect {
+                right: Val::Px(20.),
+                top: Val::Px(20.),
+                ..Default::default()
+            },
+            60.,
+        ),
+    ];
+    for (button, label, position, size) in buttons {
+        commands
+            .spawn_bundle(NodeBundle {
+                style: touch_style(position, size),
+                material: touch_materials.button.clone(),
+                ..Default::default()
+            })
+            .insert(TouchUi)
+            .insert(button)
+            .with_children(|parent| {
+                parent.spawn_bundle(TextBundle {
+                    text: Text::with_section(
+                        label,
+                        TextStyle {
+                            font: font_assets.fira_sans.clone(),
+                            font_size: 18.0,
+                            color: Color::rgba(1., 1., 1., 0.8),
+                        },
+                        Default::default(),
+                    ),
+                    ..Default::default()
+                });
+            });
+    }
+}
+
+fn touch_style(position: Rect<Val>, size: f32) -> Style {
+    Style {
+        position_type: PositionType::Absolute,
+        position,
+        size: Size::new(Val::Px(size), Val::Px(size)),
+        justify_content: JustifyContent::Center,
+        align_items: AlignItems::Center,
+        ..Default::default()
+    }
+}
+
+fn update_touch_controls(
+    controls: Res<TouchControls>,
+    touch_materials: Res<TouchMaterials>,
+    mut stick_query: Query<&mut Style, (With<StickBase>, Without<StickKnob>)>,
+    mut knob_query: Query<&mut Style, With<StickKnob>>,
+    mut button_query: Query<(&TouchButton, &mut Handle<ColorMaterial>)>,
+) {
+    if !controls.is_changed() {
+        return;
+    }
+    let center = controls
+        .stick
+        .map(|(_, start)| start)
+        .unwrap_or(Vec2::splat(STICK_HOME));
+    let knob = center + controls.movement.unwrap_or(Vec2::ZERO) * STICK_RADIUS;
+    for mut style in stick_query.iter_mut() {
+        style.position.left = Val::Px(center.x - STICK_RADIUS);
+        style.position.bottom = Val::Px(center.y - STICK_RADIUS);
+    }
+    for mut style in knob_query.iter_mut() {
+        style.position.left = Val::Px(knob.x - KNOB_SIZE / 2.);
+        style.position.bottom = Val::Px(knob.y - KNOB_SIZE / 2.);
+    }
+    for (button, mut material) in button_query.iter_mut() {
+        *material = if controls.held.contains(button) {
+            touch_materials.pressed.clone()
+        } else {
+            touch_materials.button.clone()
+        };
+    }
+}
+
+fn cleanup_touch_controls(mut commands: Commands, ui_query: Query<Entity, With<TouchUi>>) {
+    for entity in ui_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
@@ -0,0 +1,62 @@
+use bevy::asset::{AssetLoader, LoadContext, LoadedAsset};
+use bevy::prelude::*;
+use bevy::reflect::TypeUuid;
+use bevy::utils::BoxedFuture;
+use serde::Deserialize;
+
+pub struct TuningPlugin;
+
+/// This plugin registers the [PlayerTuning] asset, so gameplay values can be tweaked in `.tuning.ron` files
+impl Plugin for TuningPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_asset::<PlayerTuning>()
+            .init_asset_loader::<PlayerTuningLoader>();
+    }
+}
+
+/// How the player ship moves, see `assets/data/player.tuning.ron`
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "fe8c3b60-9420-410a-9527-adb997719b34"]
+pub struct PlayerTuning {
+    /// Top speed in pixels per second at full stick tilt
+    pub max_speed: f32,
+    /// Speed gained per second while steering
+    pub acceleration: f32,
+    /// Speed lost per second without input
+    pub friction: f32,
+    #[serde(default)]
+    pub dash: Option<DashTuning>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DashTuning {
+    /// Speed in pixels per second during the dash
+    pub speed: f32,
+    /// Length of the dash in seconds
+    pub duration: f32,
+    /// Seconds from the start of a dash until the next one is possible
+    pub cooldown: f32,
+    /// Seconds from the start of a dash in which the player can not be hit
+    pub invulnerability: f32,
+}
+
+#[derive(Default)]
+pub struct PlayerTuningLoader;
+
+impl AssetLoader for PlayerTuningLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let tuning: PlayerTuning = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(tuning));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["tuning.ron"]
+    }
+}
@@ -0,0 +1,137 @@
+use crate::player::MainCamera;
+use bevy::prelude::*;
+use bevy::render::camera::OrthographicProjection;
+use bevy::sprite::SpriteResizeMode;
+use bevy::window::WindowResized;
+
+/// The part of the world that is always visible, in world units
+pub const VIRTUAL_WIDTH: f32 = 960.;
+pub const VIRTUAL_HEIGHT: f32 = 540.;
+
+pub struct VirtualResolutionPlugin;
+
+/// This plugin scales the game world by the largest whole number that fits the virtual resolution
+/// into the window, so pixel art stays crisp. The rest of the window is covered by black bars.
+/// The UI is laid out in window pixels and anchored to the window edges instead.
+impl Plugin for VirtualResolutionPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<VirtualScreen>()
+            .add_system(add_letterbox.system().label("add_letterbox"))
+            .add_system(fit_to_window.system().after("add_letterbox"));
+    }
+}
+
+/// How the virtual screen fits into the window
+pub struct VirtualScreen {
+    /// Window pixels per world unit
+    pub scale: f32,
+}
+
+impl Default for VirtualScreen {
+    fn default() -> Self {
+        VirtualScreen { scale: 1. }
+    }
+}
+
+impl VirtualScreen {
+    /// A window position relative to the middle of the window, in world units
+    pub fn world_offset(&self, window: &Window, position: Vec2) -> Vec2 {
+        (position - Vec2::new(window.width(), window.height()) / 2.) / self.scale
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Letterbox {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+fn add_letterbox(
+    mut commands: Commands,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    camera_query: Query<Entity, Added<MainCamera>>,
+) {
+    for camera in camera_query.iter() {
+        let material = materials.add(Color::BLACK.into());
+        commands.entity(camera).with_children(|parent| {
+            for bar in [
+                Letterbox::Left,
+                Letterbox::Right,
+                Letterbox::Top,
+                Letterbox::Bottom,
+            ]
+            .iter()
+            {
+                parent
+                    .spawn_bundle(SpriteBundle {
+                        sprite: Sprite {
+                            size: Vec2::ZERO,
+                            resize_mode: SpriteResizeMode::Manual,
+                            ..Default::default()
+                        },
+                        material: material.clone(),
+                        ..Default::default()
+                    })
+                    .insert(*bar);
+            }
+        });
+    }
+}
+
+fn fit_to_window(
+    windows: Res<Windows>,
+    mut resized_events: EventReader<WindowResized>,
+    mut screen: ResMut<VirtualScreen>,
+    new_bars: Query<(), Added<Letterbox>>,
+    mut camera_query: Query<&mut OrthographicProjection, With<MainCamera>>,
+    mut bar_query: Query<(&Letterbox, &mut Sprite, &mut Transform)>,
+) {
+    let resized = resized_events.iter().last().is_some();
+    if !resized && new_bars.iter().next().is_none() {
+        return;
+    }
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let (width, height) = (window.width(), window.height());
+    screen.scale = (width / VIRTUAL_WIDTH)
+        .min(height / VIRTUAL_HEIGHT)
+        .floor()
+        .max(1.);
+    for mut projection in camera_query.iter_mut() {
+        projection.scale = 1. / screen.scale;
+    }
+
+    // the bars are children of the camera and cover what is visible beyond the virtual screen
+    let margin = Vec2::new(
+        (width / screen.scale - VIRTUAL_WIDTH).max(0.) / 2.,
+        (height / screen.scale - VIRTUAL_HEIGHT).max(0.) / 2.,
+    );
+    let view_height = VIRTUAL_HEIGHT + 2. * margin.y;
+    for (bar, mut sprite, mut transform) in bar_query.iter_mut() {
+        let (size, position) = match bar {
+            Letterbox::Left => (
+                Vec2::new(margin.x, view_height),
+                Vec2::new(-(VIRTUAL_WIDTH + margin.x) / 2., 0.),
+            ),
+            Letterbox::Right => (
+                Vec2::new(margin.x, view_height),
+                Vec2::new((VIRTUAL_WIDTH + margin.x) / 2., 0.),
+            ),
+            Letterbox::Top => (
+                Vec2::new(VIRTUAL_WIDTH, margin.y),
+                Vec2::new(0., (VIRTUAL_HEIGHT + margin.y) / 2.),
+            ),
+            Letterbox::Bottom => (
+                Vec2::new(VIRTUAL_WIDTH, margin.y),
+                Vec2::new(0., -(VIRTUAL_HEIGHT + margin.y) / 2.),
+            ),
+        };
+        sprite.size = size;
+        // just in front of the camera, so above everything else in the world
+        transform.translation = position.extend(-0.1);
+    }
+}
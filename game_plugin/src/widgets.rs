@@ -0,0 +1,724 @@
+use crate::focus::move_focus;
+use crate::loading::FontAssets;
+use bevy::ecs::system::Command;
+use bevy::input::mouse::MouseWheel;
+use bevy::prelude::*;
+use bevy::ui::FocusPolicy;
+use bevy::window::ReceivedCharacter;
+
+pub struct WidgetsPlugin;
+
+/// This plugin runs the widgets menus are built from: buttons, sliders, toggles, selectors,
+/// scrollable lists and letter pickers. Every widget changed by the player sends a [WidgetEvent].
+/// Buttons can also run an [OnClick] callback. All widgets are drawn with the materials of the [UiTheme].
+impl Plugin for WidgetsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.init_resource::<UiTheme>()
+            .add_event::<WidgetEvent>()
+            .add_system(click_widgets.system().label("widgets"))
+            .add_system(drag_sliders.system().label("widgets"))
+            .add_system(type_letters.system().label("widgets"))
+            .add_system(scroll_lists.system().label("widgets"))
+            .add_system(update_widget_text.system().after("widgets"))
+            .add_system(update_slider_fill.system().after("widgets"))
+            .add_system(update_visuals.system().after("widgets"));
+    }
+}
+
+pub struct UiTheme {
+    pub normal: Handle<ColorMaterial>,
+    pub hovered: Handle<ColorMaterial>,
+    pub pressed: Handle<ColorMaterial>,
+    pub focused: Handle<ColorMaterial>,
+    /// The filled part of a slider
+    pub fill: Handle<ColorMaterial>,
+    pub text_color: Color,
+    pub button_size: Vec2,
+    pub button_font_size: f32,
+    /// Size of all other widgets, which show a label next to their value
+    pub row_size: Vec2,
+    pub row_font_size: f32,
+}
+
+impl FromWorld for UiTheme {
+    fn from_world(world: &mut World) -> Self {
+        let mut materials = world.get_resource_mut::<Assets<ColorMaterial>>().unwrap();
+        UiTheme {
+            normal: materials.add(Color::rgb(0.15, 0.15, 0.15).into()),
+            hovered: materials.add(Color::rgb(0.25, 0.25, 0.25).into()),
+            pressed: materials.add(Color::rgb(0.35, 0.35, 0.35).into()),
+            focused: materials.add(Color::rgb(0.2, 0.2, 0.3).into()),
+            fill: materials.add(Color::rgba(0.4, 0.4, 0.7, 0.6).into()),
+            text_color: Color::rgb(0.9, 0.9, 0.9),
+            button_size: Vec2::new(200., 50.),
+            button_font_size: 40.,
+            row_size: Vec2::new(400., 32.),
+            row_font_size: 25.,
+        }
+    }
+}
+
+/// Every widget has this, its label is shown in front of the value
+pub struct Widget {
+    pub label: String,
+}
+
+/// The widget keyboard and gamepad input goes to. Clicking a widget focuses it,
+/// menu controls move the focus, see [FocusPlugin](crate::focus::FocusPlugin).
+pub struct Focused;
+
+/// Runs when the button is clicked
+pub struct OnClick(pub fn(&mut World));
+
+pub struct Slider {
+    pub value: f32,
+    pub min: f32,
+    pub max: f32,
+    pub step: f32,
+    pub display: fn(f32) -> String,
+}
+
+impl Slider {
+    /// A slider from 0 to 1 shown in percent
+    pub fn percent(value: f32, step: f32) -> Self {
+        Slider {
+            value,
+            min: 0.,
+            max: 1.,
+            step,
+            display: |value| format!("{}%", (value * 100.).round()),
+        }
+    }
+
+    /// Sets the value snapped to a step, returns whether it changed
+    pub fn set(&mut self, value: f32) -> bool {
+        let steps = ((value - self.min) / self.step).round();
+        let value = (self.min + steps * self.step).clamp(self.min, self.max);
+        let changed = value != self.value;
+        self.value = value;
+        changed
+    }
+
+    fn fraction(&self) -> f32 {
+        ((self.value - self.min) / (self.max - self.min)).clamp(0., 1.)
+    }
+}
+
+struct SliderFill;
+
+pub struct Toggle {
+    pub on: bool,
+}
+
+/// Cycles through its options when clicked
+pub struct Selector {
+    pub options: Vec<String>,
+    pub selected: usize,
+}
+
+impl Selector {
+    pub fn cycle(&mut self, forward: bool) {
+        let count = self.options.len().max(1);
+        self.selected = if forward {
+            (self.selected + 1) % count
+        } else {
+            (self.selected + count - 1) % count
+        };
+    }
+}
+
+/// One letter of an arcade style name entry. Up and down cycle through the [LETTERS](LetterPicker::LETTERS),
+/// typing one of them picks it and moves the focus on to the next letter picker.
+pub struct LetterPicker {
+    pub letter: char,
+}
+
+impl LetterPicker {
+    pub const LETTERS: &'static str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 ";
+
+    pub fn cycle(&mut self, forward: bool) {
+        let count = Self::LETTERS.chars().count();
+        let index = Self::LETTERS
+            .chars()
+            .position(|letter| letter == self.letter)
+            .unwrap_or(0);
+        let index = if forward {
+            (index + 1) % count
+        } else {
+            (index + count - 1) % count
+        };
+        self.letter = Self::LETTERS.chars().nth(index).unwrap_or(' ');
+    }
+}
+
+/// A column of widgets that only shows `visible_rows` of them and scrolls with the mouse wheel
+pub struct ScrollList {
+    pub offset: usize,
+    pub visible_rows: usize,
+}
+
+pub struct WidgetEvent {
+    pub entity: Entity,
+    pub change: WidgetChange,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum WidgetChange {
+    Clicked,
+    Value(f32),
+    Toggled(bool),
+    Selected(usize),
+    Letter(char),
+}
+
+/// Pressing back in a menu presses this button
+pub struct BackButton;
+
+struct RunCallback(fn(&mut World));
+
+impl Command for RunCallback {
+    fn write(self: Box<Self>, world: &mut World) {
+        (self.0)(world)
+    }
+}
+
+/// A big button, `marker` is inserted on it to tell buttons apart
+pub fn spawn_button<T: Send + Sync + 'static>(
+    parent: &mut ChildBuilder,
+    label: &str,
+    font_assets: &FontAssets,
+    theme: &UiTheme,
+    marker: T,
+) {
+    let font_size = theme.button_font_size;
+    spawn_widget(
+        parent,
+        font_assets,
+        theme,
+        theme.button_size,
+        font_size,
+        label.to_string(),
+        (Widget::new(label), marker),
+        None,
+    );
+}
+
+/// A big button that is also pressed by going back in the menu
+pub fn spawn_back_button<T: Send + Sync + 'static>(
+    parent: &mut ChildBuilder,
+    label: &str,
+    font_assets: &FontAssets,
+    theme: &UiTheme,
+    marker: T,
+) {
+    let font_size = theme.button_font_size;
+    spawn_widget(
+        parent,
+        font_assets,
+        theme,
+        theme.button_size,
+        font_size,
+        label.to_string(),
+        (Widget::new(label), marker, BackButton),
+        None,
+    );
+}
+
+/// A button as wide as the other widgets
+pub fn spawn_row_button<T: Send + Sync + 'static>(
+    parent: &mut ChildBuilder,
+    label: &str,
+    font_assets: &FontAssets,
+    theme: &UiTheme,
+    marker: T,
+) {
+    spawn_row(
+        parent,
+        font_assets,
+        theme,
+        label.to_string(),
+        (Widget::new(label), marker),
+        None,
+    );
+}
+
+pub fn spawn_slider<T: Send + Sync + 'static>(
+    parent: &mut ChildBuilder,
+    label: &str,
+    slider: Slider,
+    font_assets: &FontAssets,
+    theme: &UiTheme,
+    marker: T,
+) {
+    let widget = Widget::new(label);
+    let text = widget.slider_text(&slider);
+    let fill = slider.fraction();
+    spawn_row(
+        parent,
+        font_assets,
+        theme,
+        text,
+        (widget, slider, marker),
+        Some(fill),
+    );
+}
+
+pub fn spawn_toggle<T: Send + Sync + 'static>(
+    parent: &mut ChildBuilder,
+    label: &str,
+    on: bool,
+    font_assets: &FontAssets,
+    theme: &UiTheme,
+    marker: T,
+) {
+    let widget = Widget::new(label);
+    let toggle = Toggle { on };
+    let text = widget.toggle_text(&toggle);
+    spawn_row(
+        parent,
+        font_assets,
+        theme,
+        text,
+        (widget, toggle, marker),
+        None,
+    );
+}
+
+pub fn spawn_selector<T: Send + Sync + 'static>(
+    parent: &mut ChildBuilder,
+    label: &str,
+    selector: Selector,
+    font_assets: &FontAssets,
+    theme: &UiTheme,
+    marker: T,
+) {
+    let widget = Widget::new(label);
+    let text = widget.selector_text(&selector);
+    spawn_row(
+        parent,
+        font_assets,
+        theme,
+        text,
+        (widget, selector, marker),
+        None,
+    );
+}
+
+/// A square button showing a single letter
+pub fn spawn_letter_picker<T: Send + Sync + 'static>(
+    parent: &mut ChildBuilder,
+    letter: char,
+    font_assets: &FontAssets,
+    theme: &UiTheme,
+    marker: T,
+) {
+    let font_size = theme.button_font_size;
+    spawn_widget(
+        parent,
+        font_assets,
+        theme,
+        Vec2::splat(theme.button_size.y),
+        font_size,
+        letter.to_string(),
+        (Widget::new(""), LetterPicker { letter }, marker),
+        None,
+    );
+}
+
+/// A list of widgets spawned by `spawn_rows`, one per row
+pub fn spawn_scroll_list(
+    parent: &mut ChildBuilder,
+    visible_rows: usize,
+    spawn_rows: impl FnOnce(&mut ChildBuilder),
+) {
+    parent
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            visible: Visible {
+                is_visible: false,
+                is_transparent: true,
+            },
+            ..Default::default()
+        })
+        .insert(ScrollList {
+            offset: 0,
+            visible_rows,
+        })
+        .with_children(spawn_rows);
+}
+
+fn spawn_row(
+    parent: &mut ChildBuilder,
+    font_assets: &FontAssets,
+    theme: &UiTheme,
+    text: String,
+    components: impl Bundle,
+    fill: Option<f32>,
+) {
+    let font_size = theme.row_font_size;
+    spawn_widget(
+        parent,
+        font_assets,
+        theme,
+        theme.row_size,
+        font_size,
+        text,
+        components,
+        fill,
+    );
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_widget(
+    parent: &mut ChildBuilder,
+    font_assets: &FontAssets,
+    theme: &UiTheme,
+    size: Vec2,
+    font_size: f32,
+    text: String,
+    components: impl Bundle,
+    fill: Option<f32>,
+) {
+    parent
+        .spawn_bundle(ButtonBundle {
+            style: Style {
+                size: Size::new(Val::Px(size.x), Val::Px(size.y)),
+                margin: Rect::all(Val::Px(if size.y < 40. { 3. } else { 10. })),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: theme.normal.clone(),
+            ..Default::default()
+        })
+        .insert_bundle(components)
+        .with_children(|parent| {
+            if let Some(fill) = fill {
+                parent
+                    .spawn_bundle(NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            position: Rect {
+                                left: Val::Px(0.),
+                                top: Val::Px(0.),
+                                ..Default::default()
+                            },
+                            size: Size::new(Val::Percent(fill * 100.), Val::Percent(100.)),
+                            ..Default::default()
+                        },
+                        material: theme.fill.clone(),
+                        ..Default::default()
+                    })
+                    // clicks go through to the slider
+                    .insert(FocusPolicy::Pass)
+                    .insert(SliderFill);
+            }
+            parent.spawn_bundle(TextBundle {
+                text: Text::with_section(
+                    text,
+                    TextStyle {
+                        font: font_assets.fira_sans.clone(),
+                        font_size,
+                        color: theme.text_color,
+                    },
+                    Default::default(),
+                ),
+                ..Default::default()
+            });
+        });
+}
+
+impl Widget {
+    fn new(label: &str) -> Self {
+        Widget {
+            label: label.to_string(),
+        }
+    }
+
+    fn slider_text(&self, slider: &Slider) -> String {
+        format!("{}: {}", self.label, (slider.display)(slider.value))
+    }
+
+    fn toggle_text(&self, toggle: &Toggle) -> String {
+        format!("{}: {}", self.label, if toggle.on { "on" } else { "off" })
+    }
+
+    fn selector_text(&self, selector: &Selector) -> String {
+        let option = selector
+            .options
+            .get(selector.selected)
+            .map_or("-", String::as_str);
+        format!("{}: < {} >", self.label, option)
+    }
+}
+
+type ClickedWidget<'a> = (
+    Entity,
+    &'a Interaction,
+    Option<&'a mut Toggle>,
+    Option<&'a mut Selector>,
+    Option<&'a OnClick>,
+    Option<&'a Slider>,
+);
+
+fn click_widgets(
+    mut commands: Commands,
+    mut events: EventWriter<WidgetEvent>,
+    mut widget_query: Query<ClickedWidget, (Changed<Interaction>, With<Widget>)>,
+    focused_query: Query<Entity, With<Focused>>,
+) {
+    for (entity, interaction, mut toggle, mut selector, on_click, slider) in widget_query.iter_mut()
+    {
+        if *interaction != Interaction::Clicked {
+            continue;
+        }
+        move_focus(&mut commands, focused_query.iter(), entity);
+        // sliders send their value while dragged
+        if slider.is_some() {
+            continue;
+        }
+        let change = activate(
+            &mut commands,
+            toggle.as_deref_mut(),
+            selector.as_deref_mut(),
+            on_click,
+        );
+        events.send(WidgetEvent { entity, change });
+    }
+}
+
+/// Clicks or confirms a widget: flips toggles, cycles selectors and runs [OnClick] callbacks
+pub fn activate(
+    commands: &mut Commands,
+    toggle: Option<&mut Toggle>,
+    selector: Option<&mut Selector>,
+    on_click: Option<&OnClick>,
+) -> WidgetChange {
+    let change = if let Some(toggle) = toggle {
+        toggle.on = !toggle.on;
+        WidgetChange::Toggled(toggle.on)
+    } else if let Some(selector) = selector {
+        selector.cycle(true);
+        WidgetChange::Selected(selector.selected)
+    } else {
+        WidgetChange::Clicked
+    };
+    if let Some(on_click) = on_click {
+        commands.add(RunCallback(on_click.0));
+    }
+    change
+}
+
+fn drag_sliders(
+    windows: Res<Windows>,
+    mut events: EventWriter<WidgetEvent>,
+    mut slider_query: Query<(Entity, &Interaction, &Node, &GlobalTransform, &mut Slider)>,
+) {
+    let cursor = match windows
+        .get_primary()
+        .and_then(|window| window.cursor_position())
+    {
+        Some(cursor) => cursor,
+        None => return,
+    };
+    for (entity, interaction, node, transform, mut slider) in slider_query.iter_mut() {
+        if *interaction != Interaction::Clicked || node.size.x <= 0. {
+            continue;
+        }
+        let left = transform.translation.x - node.size.x / 2.;
+        let fraction = ((cursor.x - left) / node.size.x).clamp(0., 1.);
+        let value = slider.min + fraction * (slider.max - slider.min);
+        if slider.set(value) {
+            events.send(WidgetEvent {
+                entity,
+                change: WidgetChange::Value(slider.value),
+            });
+        }
+    }
+}
+
+/// Typing a letter into the focused letter picker focuses the next one, backspace the one before
+fn type_letters(
+    mut commands: Commands,
+    mut characters: EventReader<ReceivedCharacter>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut events: EventWriter<WidgetEvent>,
+    mut picker_query: Query<(Entity, &Parent, &mut LetterPicker), With<Focused>>,
+    children_query: Query<&Children>,
+    siblings_query: Query<(), With<LetterPicker>>,
+) {
+    let typed: Option<char> = characters
+        .iter()
+        .flat_map(|character| character.char.to_uppercase())
+        .rfind(|character| LetterPicker::LETTERS.contains(*character));
+    let (entity, parent, mut picker) = match picker_query.iter_mut().next() {
+        Some(picker) => picker,
+        None => return,
+    };
+    let step: isize = if let Some(letter) = typed {
+        picker.letter = letter;
+        events.send(WidgetEvent {
+            entity,
+            change: WidgetChange::Letter(letter),
+        });
+        1
+    } else if keyboard_input.just_pressed(KeyCode::Back) {
+        -1
+    } else {
+        return;
+    };
+    let pickers: Vec<Entity> = children_query
+        .get(parent.0)
+        .map(|children| {
+            children
+                .iter()
+                .copied()
+                .filter(|child| siblings_query.get(*child).is_ok())
+                .collect()
+        })
+        .unwrap_or_default();
+    let next = pickers
+        .iter()
+        .position(|picker| *picker == entity)
+        .and_then(|index| pickers.get((index as isize + step).max(0) as usize));
+    if let Some(next) = next {
+        move_focus(&mut commands, std::iter::once(entity), *next);
+    }
+}
+
+/// Rows outside of the visible part are taken out of the layout and hidden
+fn scroll_lists(
+    windows: Res<Windows>,
+    mut wheel_events: EventReader<MouseWheel>,
+    mut list_query: Query<(&mut ScrollList, &Node, &GlobalTransform, &Children)>,
+    children_query: Query<&Children>,
+    mut style_query: Query<&mut Style>,
+    mut visible_query: Query<&mut Visible>,
+) {
+    let scrolled: f32 = wheel_events.iter().map(|event| event.y).sum();
+    let cursor = windows
+        .get_primary()
+        .and_then(|window| window.cursor_position());
+    for (mut list, node, transform, rows) in list_query.iter_mut() {
+        let hovered = cursor.map_or(false, |cursor| {
+            let offset = (cursor - transform.translation.truncate()).abs();
+            offset.x <= node.size.x / 2. && offset.y <= node.size.y / 2.
+        });
+        let max_offset = rows.len().saturating_sub(list.visible_rows);
+        if hovered && scrolled != 0. {
+            // the first row is at the top, so scrolling down moves further into the list
+            list.offset = if scrolled < 0. {
+                (list.offset + 1).min(max_offset)
+            } else {
+                list.offset.saturating_sub(1)
+            };
+        }
+        if !list.is_changed() {
+            continue;
+        }
+        for (index, row) in rows.iter().enumerate() {
+            let shown = index >= list.offset && index < list.offset + list.visible_rows;
+            if let Ok(mut style) = style_query.get_mut(*row) {
+                style.display = if shown { Display::Flex } else { Display::None };
+            }
+            set_visible(*row, shown, &children_query, &mut visible_query);
+        }
+    }
+}
+
+/// Scrolls `list` so that `row` is visible
+pub fn scroll_to(list: &mut ScrollList, row: usize) {
+    let offset = if row < list.offset {
+        row
+    } else if row >= list.offset + list.visible_rows {
+        row + 1 - list.visible_rows
+    } else {
+        return;
+    };
+    list.offset = offset;
+}
+
+fn set_visible(
+    entity: Entity,
+    shown: bool,
+    children_query: &Query<&Children>,
+    visible_query: &mut Query<&mut Visible>,
+) {
+    if let Ok(mut visible) = visible_query.get_mut(entity) {
+        visible.is_visible = shown;
+    }
+    if let Ok(children) = children_query.get(entity) {
+        for child in children.iter() {
+            set_visible(*child, shown, children_query, visible_query);
+        }
+    }
+}
+
+type WidgetText<'a> = (
+    &'a Widget,
+    &'a Children,
+    Option<&'a Slider>,
+    Option<&'a Toggle>,
+    Option<&'a Selector>,
+    Option<&'a LetterPicker>,
+);
+
+fn update_widget_text(widget_query: Query<WidgetText>, mut text_query: Query<&mut Text>) {
+    for (widget, children, slider, toggle, selector, picker) in widget_query.iter() {
+        let value = if let Some(slider) = slider {
+            widget.slider_text(slider)
+        } else if let Some(toggle) = toggle {
+            widget.toggle_text(toggle)
+        } else if let Some(selector) = selector {
+            widget.selector_text(selector)
+        } else if let Some(picker) = picker {
+            picker.letter.to_string()
+        } else {
+            widget.label.clone()
+        };
+        for child in children.iter() {
+            if let Ok(mut text) = text_query.get_mut(*child) {
+                if text.sections[0].value != value {
+                    text.sections[0].value = value.clone();
+                }
+            }
+        }
+    }
+}
+
+fn update_slider_fill(
+    slider_query: Query<(&Slider, &Children), Changed<Slider>>,
+    mut fill_query: Query<&mut Style, With<SliderFill>>,
+) {
+    for (slider, children) in slider_query.iter() {
+        for child in children.iter() {
+            if let Ok(mut style) = fill_query.get_mut(*child) {
+                style.size.width = Val::Percent(slider.fraction() * 100.);
+            }
+        }
+    }
+}
+
+fn update_visuals(
+    theme: Res<UiTheme>,
+    mut widget_query: Query<
+        (&Interaction, Option<&Focused>, &mut Handle<ColorMaterial>),
+        With<Widget>,
+    >,
+) {
+    for (interaction, focused, mut material) in widget_query.iter_mut() {
+        let wanted = match interaction {
+            Interaction::Clicked => &theme.pressed,
+            Interaction::Hovered => &theme.hovered,
+            Interaction::None if focused.is_some() => &theme.focused,
+            Interaction::None => &theme.normal,
+        };
+        if *material != *wanted {
+            *material = wanted.clone();
+        }
+    }
+}
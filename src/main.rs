@@ -4,21 +4,18 @@
 #[cfg(target_arch = "wasm32")]
 use bevy_webgl2;
 
-use bevy::prelude::{App, ClearColor, Color, WindowDescriptor};
+use bevy::prelude::{App, ClearColor, Color};
 use bevy::DefaultPlugins;
-use game_plugin::GamePlugin;
+use game_plugin::{GamePlugin, Settings};
 
 fn main() {
+    let settings = Settings::load();
     let mut app = App::build();
     app
         // .insert_resource(Msaa { samples: 4 })
-        .insert_resource(ClearColor(Color::rgb(0.4, 0.4, 0.4)))
-        .insert_resource(WindowDescriptor {
-            width: 960.,
-            height: 540.,
-            title: "Bevy game".to_string(), // ToDo
-            ..Default::default()
-        })
+        .insert_resource(ClearColor(Color::rgb(0.02, 0.02, 0.05)))
+        .insert_resource(settings.window_descriptor())
+        .insert_resource(settings)
         .add_plugins(DefaultPlugins)
         .add_plugin(GamePlugin);
 